@@ -0,0 +1,300 @@
+#![allow(dead_code)]
+//! Loading of Apache Arrow IPC and Apache Parquet files into `PointData`.
+//! Numeric, boolean and date/time columns are mapped into `PointData::data` (nulls become NaN),
+//! strings and dictionaries into `PointData::categorical`
+//! and all the other columns (lists, structs...) are formatted into `PointData::aux`.
+
+use crate::pointdata::{Categorical, ColumnType, PointData, MAX_CATEGORIES};
+use anyhow::*;
+use arrow::array::{Array, Float64Array};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Schema, SchemaRef, TimeUnit};
+use arrow::ipc::reader::FileReader;
+use arrow::record_batch::RecordBatch;
+use arrow::util::display::{ArrayFormatter, FormatOptions};
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::Arc;
+
+enum ColumnBuffer {
+    Numeric(ColumnType, Vec<f64>),
+    Categorical(Categorical),
+    Text(Vec<String>),
+}
+
+impl ColumnBuffer {
+    fn new(data_type: &DataType) -> ColumnBuffer {
+        match data_type {
+            DataType::Boolean => ColumnBuffer::Numeric(ColumnType::Boolean, Vec::new()),
+            DataType::Timestamp(_, _) | DataType::Date32 | DataType::Date64 => {
+                ColumnBuffer::Numeric(ColumnType::DateTime, Vec::new())
+            }
+            t if t.is_integer() => ColumnBuffer::Numeric(ColumnType::Integer, Vec::new()),
+            t if t.is_numeric() => ColumnBuffer::Numeric(ColumnType::Float, Vec::new()),
+            DataType::Utf8
+            | DataType::LargeUtf8
+            | DataType::Utf8View
+            | DataType::Dictionary(_, _) => ColumnBuffer::Categorical(Categorical::new()),
+            _ => ColumnBuffer::Text(Vec::new()),
+        }
+    }
+}
+
+/// Values of a numeric, boolean or date/time array as f64 (date/time as seconds since the epoch).
+fn to_f64(array: &dyn Array, column_type: ColumnType) -> Result<Vec<f64>> {
+    let (values, factor) = if column_type == ColumnType::DateTime {
+        let microseconds = cast(array, &DataType::Timestamp(TimeUnit::Microsecond, None))?;
+        (cast(&microseconds, &DataType::Int64)?, 1e-6)
+    } else {
+        (cast(array, &DataType::Float64)?, 1.0)
+    };
+    let values = cast(&values, &DataType::Float64)?;
+    let values = values
+        .as_any()
+        .downcast_ref::<Float64Array>()
+        .ok_or_else(|| anyhow!("Failed to convert a numeric column"))?;
+    Ok(values
+        .iter()
+        .map(|x| x.map(|x| x * factor).unwrap_or(f64::NAN))
+        .collect())
+}
+
+/// Indices of the (top-level) columns in the schema.
+fn column_indices(schema: &Schema, columns: &[&str]) -> Result<Vec<usize>> {
+    columns
+        .iter()
+        .map(|&column| {
+            schema
+                .index_of(column)
+                .map_err(|_| anyhow!("Column '{}' not found", column))
+        })
+        .collect()
+}
+
+impl PointData {
+    /// Create point data from a sequence of record batches sharing the same schema.
+    pub fn from_record_batches<I>(schema: SchemaRef, batches: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = Result<RecordBatch>>,
+    {
+        let mut buffers = schema
+            .fields()
+            .iter()
+            .map(|field| ColumnBuffer::new(field.data_type()))
+            .collect::<Vec<_>>();
+        let mut length = 0;
+
+        for batch in batches {
+            let batch = batch?;
+            for (buffer, array) in buffers.iter_mut().zip(batch.columns().iter()) {
+                match buffer {
+                    ColumnBuffer::Numeric(column_type, v) => {
+                        v.extend(to_f64(array.as_ref(), *column_type)?);
+                    }
+                    ColumnBuffer::Categorical(categorical) => {
+                        let formatter =
+                            ArrayFormatter::try_new(array.as_ref(), &FormatOptions::default())?;
+                        for i in 0..array.len() {
+                            categorical.push(&formatter.value(i).to_string());
+                        }
+                        if categorical.categories.len() > MAX_CATEGORIES {
+                            *buffer = ColumnBuffer::Text(
+                                categorical.iter().map(|x| x.to_owned()).collect(),
+                            );
+                        }
+                    }
+                    ColumnBuffer::Text(v) => {
+                        let formatter =
+                            ArrayFormatter::try_new(array.as_ref(), &FormatOptions::default())?;
+                        v.extend((0..array.len()).map(|i| formatter.value(i).to_string()));
+                    }
+                }
+            }
+            length += batch.num_rows();
+        }
+
+        let mut point_data = Self::new();
+        for (field, buffer) in schema.fields().iter().zip(buffers) {
+            let column = field.name();
+            point_data.headers.push(column.to_owned());
+            point_data.all_headers.push(column.to_owned());
+            match buffer {
+                ColumnBuffer::Numeric(column_type, v) => {
+                    point_data
+                        .column_types
+                        .insert(column.to_owned(), column_type);
+                    point_data.data.insert(column.to_owned(), v);
+                }
+                ColumnBuffer::Categorical(v) => {
+                    point_data.categorical.insert(column.to_owned(), v);
+                }
+                ColumnBuffer::Text(v) => {
+                    point_data.aux.insert(column.to_owned(), v);
+                }
+            }
+        }
+        point_data.length = length;
+        Ok(point_data)
+    }
+
+    /// Read an Arrow IPC file (a.k.a. Feather v2).
+    /// If `columns` are specified, only these columns are loaded (in the given order).
+    pub fn from_arrow_ipc<R: Read + Seek>(mut reader: R, columns: Option<&[&str]>) -> Result<Self> {
+        let schema = FileReader::try_new(&mut reader, None)?.schema();
+        reader.seek(SeekFrom::Start(0))?;
+        let (schema, projection) = if let Some(columns) = columns {
+            let indices = column_indices(&schema, columns)?;
+            (Arc::new(schema.project(&indices)?), Some(indices))
+        } else {
+            (schema, None)
+        };
+        let ipc_reader = FileReader::try_new(reader, projection)?;
+        Self::from_record_batches(schema, ipc_reader.map(|batch| batch.map_err(Error::from)))
+    }
+
+    /// Read an Apache Parquet file, e.g. from `std::fs::File` or `bytes::Bytes`.
+    /// If `columns` are specified, only these columns are loaded (in the given order).
+    #[cfg(feature = "parquet")]
+    pub fn from_parquet<R: parquet::file::reader::ChunkReader + 'static>(
+        reader: R,
+        columns: Option<&[&str]>,
+    ) -> Result<Self> {
+        use arrow::record_batch::RecordBatchReader;
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+        use parquet::arrow::ProjectionMask;
+
+        let mut builder = ParquetRecordBatchReaderBuilder::try_new(reader)?;
+        if let Some(columns) = columns {
+            let indices = column_indices(builder.schema(), columns)?;
+            let mask = ProjectionMask::roots(builder.parquet_schema(), indices);
+            builder = builder.with_projection(mask);
+        }
+        let parquet_reader = builder.build()?;
+        let schema = parquet_reader.schema();
+        let mut point_data = Self::from_record_batches(
+            schema,
+            parquet_reader.map(|batch| batch.map_err(Error::from)),
+        )?;
+        if let Some(columns) = columns {
+            // projection keeps the order of the file
+            point_data.select_columns(columns)?;
+        }
+        Ok(point_data)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::csv_loader::CsvOptions;
+    use arrow::array::{ArrayRef, BooleanArray, Date32Array, Int32Array, StringArray};
+    use arrow::ipc::writer::FileWriter;
+    use std::io::Cursor;
+
+    fn test_batch() -> RecordBatch {
+        let x: ArrayRef = Arc::new(Int32Array::from(vec![1, 2, 3]));
+        let y: ArrayRef = Arc::new(Float64Array::from(vec![0.5, 1.5, 2.5]));
+        let missing: ArrayRef = Arc::new(Float64Array::from(vec![Some(1.0), None, Some(3.0)]));
+        let label: ArrayRef = Arc::new(StringArray::from(vec!["a", "b", "a"]));
+        let flag: ArrayRef = Arc::new(BooleanArray::from(vec![true, false, true]));
+        let date: ArrayRef = Arc::new(Date32Array::from(vec![0, 1, 2]));
+        RecordBatch::try_from_iter(vec![
+            ("x", x),
+            ("y", y),
+            ("missing", missing),
+            ("label", label),
+            ("flag", flag),
+            ("date", date),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn test_arrow_ipc() {
+        let batch = test_batch();
+        let mut buffer = Vec::new();
+        {
+            let mut writer = FileWriter::try_new(&mut buffer, &batch.schema()).unwrap();
+            writer.write(&batch).unwrap();
+            writer.write(&batch).unwrap();
+            writer.finish().unwrap();
+        }
+        let point_data = PointData::from_arrow_ipc(Cursor::new(&buffer), None).unwrap();
+        assert_eq!(point_data.len(), 6);
+        assert_eq!(
+            point_data.all_headers,
+            vec!["x", "y", "missing", "label", "flag", "date"]
+        );
+        assert_eq!(point_data.data["x"], vec![1.0, 2.0, 3.0, 1.0, 2.0, 3.0]);
+        assert_eq!(point_data.column_type("x"), Some(ColumnType::Integer));
+        assert_eq!(point_data.data["y"][1], 1.5);
+        assert!(point_data.data["missing"][1].is_nan());
+        assert_eq!(point_data.missing_count("missing"), 2);
+        assert_eq!(point_data.categorical["label"].categories, vec!["a", "b"]);
+        assert_eq!(point_data.get("label", 3), "a");
+        assert_eq!(point_data.data["flag"][..3], [1.0, 0.0, 1.0]);
+        assert_eq!(point_data.get("flag", 1), "false");
+        assert_eq!(point_data.column_type("date"), Some(ColumnType::DateTime));
+        assert_eq!(point_data.data["date"][1], 86400.0);
+        assert_eq!(point_data.get("date", 2), "1970-01-03");
+
+        let point_data =
+            PointData::from_arrow_ipc(Cursor::new(&buffer), Some(&["label", "y"])).unwrap();
+        assert_eq!(point_data.all_headers, vec!["label", "y"]);
+        assert!(PointData::from_arrow_ipc(Cursor::new(&buffer), Some(&["z"])).is_err());
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_parquet() {
+        let batch = test_batch();
+        let mut buffer = Vec::new();
+        {
+            let mut writer =
+                parquet::arrow::ArrowWriter::try_new(&mut buffer, batch.schema(), None).unwrap();
+            writer.write(&batch).unwrap();
+            writer.close().unwrap();
+        }
+        let point_data =
+            PointData::from_parquet(bytes::Bytes::from(buffer), Some(&["label", "x"])).unwrap();
+        assert_eq!(point_data.len(), 3);
+        assert_eq!(point_data.all_headers, vec!["label", "x"]);
+        assert_eq!(point_data.data["x"], vec![1.0, 2.0, 3.0]);
+        assert_eq!(point_data.get("label", 2), "a");
+    }
+
+    #[test]
+    fn test_from_file() {
+        let batch = test_batch();
+        let path = std::env::temp_dir().join("pointcloud-viewer-test.arrow");
+        let path = path.to_str().unwrap();
+        {
+            let file = std::fs::File::create(path).unwrap();
+            let mut writer = FileWriter::try_new(file, &batch.schema()).unwrap();
+            writer.write(&batch).unwrap();
+            writer.finish().unwrap();
+        }
+        let point_data =
+            PointData::from_file(path, &CsvOptions::new(), Some(&["label", "y"])).unwrap();
+        assert_eq!(point_data.len(), 3);
+        assert_eq!(point_data.all_headers, vec!["label", "y"]);
+        std::fs::remove_file(path).unwrap();
+
+        #[cfg(feature = "parquet")]
+        {
+            let path = std::env::temp_dir().join("pointcloud-viewer-test.parquet");
+            let path = path.to_str().unwrap();
+            {
+                let file = std::fs::File::create(path).unwrap();
+                let mut writer =
+                    parquet::arrow::ArrowWriter::try_new(file, batch.schema(), None).unwrap();
+                writer.write(&batch).unwrap();
+                writer.close().unwrap();
+            }
+            let point_data =
+                PointData::from_file(path, &CsvOptions::new(), Some(&["x"])).unwrap();
+            assert_eq!(point_data.data["x"], vec![1.0, 2.0, 3.0]);
+            assert_eq!(point_data.all_headers, vec!["x"]);
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
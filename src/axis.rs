@@ -0,0 +1,373 @@
+//! Axis ticks of the density plot. The ticks are placed at round data values,
+//! the visible range of the values is found by running the view box through `Transform::inverse`,
+//! so that logarithmic and quantile axes are labeled by the actual data values.
+
+use crate::transform::Transform;
+use chrono::{DateTime, Datelike, NaiveDate};
+
+/// Time steps (in seconds) used for datetime axes shorter than a few weeks.
+const TIME_STEPS: [f64; 23] = [
+    1.0, 2.0, 5.0, 10.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0, 900.0, 1800.0, 3600.0, 7200.0,
+    10800.0, 21600.0, 43200.0, 86400.0, 172800.0, 345600.0, 604800.0, 1209600.0, 2419200.0,
+];
+/// Month steps used for longer datetime axes, then multiples of years.
+const MONTH_STEPS: [i32; 4] = [1, 2, 3, 6];
+const SECONDS_PER_MONTH: f64 = 30.44 * 86400.0;
+
+/// How the values along an axis are distributed.
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub enum Scale {
+    Linear,
+    Logarithmic,
+    /// Any other monotonic transformation, e.g. quantile.
+    Nonlinear,
+}
+
+/// Tick of an axis.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tick {
+    /// Relative position on the axis, 0 is the start and 1 the end of the axis.
+    pub position: f64,
+    pub value: f64,
+    pub label: String,
+}
+
+/// Round step for about `count` intervals in a range: 1, 2 or 5 times a power of 10.
+pub fn nice_step(range: f64, count: usize) -> f64 {
+    let raw = range.abs() / (count.max(1) as f64);
+    if !raw.is_finite() || raw <= 0.0 {
+        return 1.0;
+    }
+    let magnitude = 10.0f64.powf(raw.log10().floor());
+    let fraction = raw / magnitude;
+    let nice = if fraction <= 1.0 {
+        1.0
+    } else if fraction <= 2.0 {
+        2.0
+    } else if fraction <= 5.0 {
+        5.0
+    } else {
+        10.0
+    };
+    nice * magnitude
+}
+
+/// Relative tolerance for rounding errors of the inverse transformations.
+const EPSILON: f64 = 1e-9;
+
+/// Multiples of `step` between `min` and `max`.
+fn multiples(min: f64, max: f64, step: f64) -> Vec<f64> {
+    let first = (min / step - EPSILON).ceil() as i64;
+    let last = (max / step + EPSILON).floor() as i64;
+    (first..=last).map(|i| i as f64 * step).collect()
+}
+
+/// Round values with a step of 1, 2 or 5 times a power of 10 between `min` and `max`.
+pub fn linear_ticks(min: f64, max: f64, count: usize) -> Vec<f64> {
+    multiples(min, max, nice_step(max - min, count))
+}
+
+/// Format a number with as many decimals as the step between the ticks needs.
+pub fn format_number(value: f64, step: f64) -> String {
+    let decimals = (-step.abs().log10().floor()).max(0.0) as usize;
+    if value != 0.0 && (value.abs() >= 1e7 || decimals > 6) {
+        return format!("{:.2e}", value);
+    }
+    let text = format!("{:.*}", decimals.min(6), value);
+    if text
+        .trim_start_matches('-')
+        .chars()
+        .all(|c| c == '0' || c == '.')
+    {
+        text.trim_start_matches('-').to_owned()
+    } else {
+        text
+    }
+}
+
+/// The largest power of 10 (at most 1) that the value is a multiple of.
+fn resolution(value: f64) -> f64 {
+    let mut step = 1.0;
+    for _ in 0..6 {
+        if ((value / step).round() * step - value).abs() <= EPSILON * value.abs().max(1.0) {
+            break;
+        }
+        step /= 10.0;
+    }
+    step
+}
+
+/// Format a timestamp with the precision needed by the step between the ticks.
+pub fn format_time(value: f64, step: f64) -> String {
+    let format = if step >= 365.0 * 86400.0 {
+        "%Y"
+    } else if step >= 28.0 * 86400.0 {
+        "%Y-%m"
+    } else if step >= 86400.0 {
+        "%Y-%m-%d"
+    } else if step >= 60.0 {
+        "%Y-%m-%d %H:%M"
+    } else {
+        "%Y-%m-%d %H:%M:%S"
+    };
+    match DateTime::from_timestamp(value.round() as i64, 0) {
+        Some(datetime) => datetime.format(format).to_string(),
+        None => format_number(value, step),
+    }
+}
+
+/// Start of a month counted from the year 0.
+fn month_start(months: i32) -> Option<f64> {
+    let year = months.div_euclid(12);
+    let month = months.rem_euclid(12) as u32 + 1;
+    NaiveDate::from_ymd_opt(year, month, 1)?
+        .and_hms_opt(0, 0, 0)
+        .map(|datetime| datetime.and_utc().timestamp() as f64)
+}
+
+/// Round times between `min` and `max` (seconds since the epoch) and the approximate step.
+/// Steps up to four weeks are multiples of seconds, longer steps start at a month or a year.
+pub fn time_ticks(min: f64, max: f64, count: usize) -> (Vec<f64>, f64) {
+    let target = (max - min) / (count.max(1) as f64);
+    if target <= 1.0 {
+        let step = nice_step(max - min, count);
+        return (multiples(min, max, step), step);
+    }
+    if let Some(&step) = TIME_STEPS.iter().find(|&&step| step >= target) {
+        return (multiples(min, max, step), step);
+    }
+    let months = match MONTH_STEPS
+        .iter()
+        .find(|&&months| months as f64 * SECONDS_PER_MONTH >= target)
+    {
+        Some(&months) => months,
+        None => 12 * nice_step(target / (12.0 * SECONDS_PER_MONTH), 1).max(1.0) as i32,
+    };
+    let start = match DateTime::from_timestamp(min.floor() as i64, 0) {
+        Some(datetime) => datetime,
+        None => return (Vec::new(), target),
+    };
+    let mut month = (start.year() * 12 + start.month0() as i32).div_euclid(months) * months;
+    let mut ticks = Vec::new();
+    while let Some(value) = month_start(month) {
+        if value > max {
+            break;
+        }
+        if value >= min {
+            ticks.push(value);
+        }
+        month += months;
+    }
+    (ticks, months as f64 * SECONDS_PER_MONTH)
+}
+
+/// Powers of 10 (and 2 and 5 times the powers if there are only few decades) between `min` and `max`.
+fn logarithmic_ticks(min: f64, max: f64, count: usize) -> Option<Vec<f64>> {
+    if min <= 0.0 || max / min < 10.0 {
+        return None;
+    }
+    let first = min.log10().floor() as i32;
+    let last = max.log10().ceil() as i32;
+    let decades = (last - first) as usize;
+    let every = 1.max((decades + count - 1) / count.max(1)) as i32;
+    let mut ticks = Vec::new();
+    for exponent in first..=last {
+        let power = 10.0f64.powi(exponent);
+        let mantissas: &[f64] = if decades * 3 <= count {
+            &[1.0, 2.0, 5.0]
+        } else if exponent % every == 0 {
+            &[1.0]
+        } else {
+            &[]
+        };
+        ticks.extend(
+            mantissas
+                .iter()
+                .map(|m| m * power)
+                .filter(|&x| x >= min * (1.0 - EPSILON) && x <= max * (1.0 + EPSILON)),
+        );
+    }
+    Some(ticks)
+}
+
+/// Ticks of an axis showing the range `start`..`end` of transformed values.
+/// The values are found with `transform.inverse`, the tick positions with `transform.transform`.
+/// With `datetime` the values are timestamps and the labels are dates.
+pub fn ticks(
+    transform: &dyn Transform,
+    scale: Scale,
+    datetime: bool,
+    start: f64,
+    end: f64,
+    count: usize,
+) -> Vec<Tick> {
+    let (first, last) = match (transform.inverse(start), transform.inverse(end)) {
+        (Some(first), Some(last)) if first.is_finite() && last.is_finite() => {
+            (first.min(last), first.max(last))
+        }
+        _ => return Vec::new(),
+    };
+    if last <= first || count == 0 {
+        return Vec::new();
+    }
+    let mut values = Vec::new();
+    match (scale, datetime) {
+        (Scale::Linear, false) => {
+            let step = nice_step(last - first, count);
+            for value in multiples(first, last, step) {
+                values.push((value, format_number(value, step)));
+            }
+        }
+        (Scale::Linear, true) => {
+            let (times, step) = time_ticks(first, last, count);
+            for value in times {
+                values.push((value, format_time(value, step)));
+            }
+        }
+        _ => {
+            let logarithmic = if scale == Scale::Logarithmic && !datetime {
+                logarithmic_ticks(first, last, count)
+            } else {
+                None
+            };
+            if let Some(powers) = logarithmic {
+                for value in powers {
+                    values.push((value, format_number(value, value)));
+                }
+            } else {
+                // sample the axis uniformly and round the values according to the local spacing
+                let samples = (0..=count)
+                    .filter_map(|i| {
+                        transform.inverse(start + (end - start) * i as f64 / count as f64)
+                    })
+                    .collect::<Vec<_>>();
+                let mut rounded = Vec::new();
+                for (i, &value) in samples.iter().enumerate() {
+                    let neighbour = if i + 1 < samples.len() {
+                        samples[i + 1]
+                    } else if i > 0 {
+                        samples[i - 1]
+                    } else {
+                        continue;
+                    };
+                    let spacing = (neighbour - value).abs();
+                    if datetime {
+                        let step = TIME_STEPS
+                            .iter()
+                            .rev()
+                            .find(|&&step| step <= spacing)
+                            .copied()
+                            .unwrap_or(1.0);
+                        let value = (value / step).round() * step;
+                        values.push((value, format_time(value, step)));
+                    } else if spacing > 0.0 {
+                        // not coarser than the value itself, e.g. 1 should not be rounded to 0
+                        let precision = if value != 0.0 {
+                            spacing.min(value.abs())
+                        } else {
+                            spacing
+                        };
+                        let step = 10.0f64.powf(precision.log10().floor());
+                        rounded.push((value / step).round() * step);
+                    }
+                }
+                // the same number of decimals for all the labels
+                let step = rounded
+                    .iter()
+                    .map(|&value| resolution(value))
+                    .fold(1.0, f64::min);
+                for value in rounded {
+                    values.push((value, format_number(value, step)));
+                }
+            }
+        }
+    }
+
+    let mut result: Vec<Tick> = Vec::new();
+    for (value, label) in values {
+        if let Some(transformed) = transform.transform(value) {
+            let position = (transformed - start) / (end - start);
+            if (-EPSILON..=1.0 + EPSILON).contains(&position)
+                && !matches!(result.last(), Some(tick) if tick.label == label)
+            {
+                result.push(Tick {
+                    position: position.clamp(0.0, 1.0),
+                    value,
+                    label,
+                });
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transform::{NewTransform, Normalize, NormalizedLogarithmic, Quantile};
+
+    fn labels(ticks: &[Tick]) -> Vec<&str> {
+        ticks.iter().map(|tick| tick.label.as_str()).collect()
+    }
+
+    #[test]
+    fn test_nice_numbers() {
+        assert_eq!(nice_step(10.0, 5), 2.0);
+        assert_eq!(nice_step(0.7, 5), 0.2);
+        assert_eq!(linear_ticks(-0.3, 1.1, 5).len(), 3);
+        assert_eq!(linear_ticks(-0.3, 1.1, 10).len(), 7);
+        assert_eq!(
+            linear_ticks(0.0, 100.0, 5),
+            vec![0.0, 20.0, 40.0, 60.0, 80.0, 100.0]
+        );
+        assert_eq!(format_number(3.0 * 0.2, 0.2), "0.6");
+        assert_eq!(format_number(-0.0001, 0.1), "0.0");
+        assert_eq!(format_number(1500.0, 500.0), "1500");
+        assert_eq!(format_number(2.5e9, 1e9), "2.50e9");
+    }
+
+    #[test]
+    fn test_ticks() {
+        let mut transform = Normalize::new();
+        transform.calibrate(&[10.0, 20.0]);
+        let ticks = ticks(&transform, Scale::Linear, false, 0.0, 1.0, 5);
+        assert_eq!(labels(&ticks), vec!["10", "12", "14", "16", "18", "20"]);
+        assert_eq!(ticks[1].position, 0.2);
+
+        let mut transform = NormalizedLogarithmic::new();
+        transform.calibrate(&[1.0, 1000.0]);
+        let ticks = super::ticks(&transform, Scale::Logarithmic, false, 0.0, 1.0, 5);
+        assert_eq!(labels(&ticks), vec!["1", "10", "100", "1000"]);
+        assert!((ticks[1].position - 1.0 / 3.0).abs() < 1e-9);
+
+        let mut transform = Quantile::new();
+        transform.calibrate(&(1..=100).map(|x| (x * x) as f64).collect::<Vec<_>>());
+        let ticks = super::ticks(&transform, Scale::Nonlinear, false, 0.0, 1.0, 4);
+        assert_eq!(ticks.first().unwrap().label, "1");
+        assert!(ticks
+            .iter()
+            .all(|tick| (0.0..=1.0).contains(&tick.position)));
+        assert!(ticks.windows(2).all(|x| x[0].value < x[1].value));
+    }
+
+    #[test]
+    fn test_time_ticks() {
+        let day = 86400.0;
+        let start = 1682899200.0; // 2023-05-01
+        let mut transform = Normalize::new();
+        transform.calibrate(&[start, start + 3.0 * day]);
+        let ticks = ticks(&transform, Scale::Linear, true, 0.0, 1.0, 3);
+        assert_eq!(
+            labels(&ticks),
+            vec!["2023-05-01", "2023-05-02", "2023-05-03", "2023-05-04"]
+        );
+
+        let (ticks, _) = time_ticks(start, start + 400.0 * day, 5);
+        let labels = ticks
+            .iter()
+            .map(|&x| format_time(x, 3.0 * SECONDS_PER_MONTH))
+            .collect::<Vec<_>>();
+        assert_eq!(labels, vec!["2023-07", "2023-10", "2024-01", "2024-04"]);
+        assert_eq!(format_time(start + 90.0, 60.0), "2023-05-01 00:01");
+    }
+}
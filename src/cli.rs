@@ -0,0 +1,590 @@
+#![allow(dead_code)]
+use crate::colormap::{Colormap, Rgb};
+use crate::csv_loader::{CsvOptions, Encoding};
+use crate::evaluate::Expression;
+use crate::highlight::{query_filter, CombinedHighlightFilter};
+use crate::mesh::ValueStatistic;
+use crate::pipeline::{Pipeline, TransformationType};
+use crate::session::Session;
+use anyhow::*;
+use std::str::FromStr;
+
+pub const DEFAULT_INPUT: &str = "data.csv";
+pub const DEFAULT_WINDOW_WIDTH: i32 = 1200;
+pub const DEFAULT_WINDOW_HEIGHT: i32 = 820;
+
+pub const USAGE: &str = "Usage: pointcloud-viewer [OPTIONS] [FILE]
+
+Arguments:
+  [FILE]                 Input CSV, Arrow IPC (.arrow) or Parquet (.parquet) file
+                         (default: data.csv, or the file of the session)
+
+Options:
+  -c, --columns <LIST>   Comma-separated list of columns to load (default: all)
+  -x, --x-column <NAME>  Column shown on the X axis
+  -y, --y-column <NAME>  Column shown on the Y axis
+      --x-transform <T>  Transformation of the X axis
+      --y-transform <T>  Transformation of the Y axis
+  -w, --weight <NAME>    Weight column
+      --color-by <NAME>  Color the points by the categories of a column
+      --value-column <NAME>
+                         Color the cells by a statistic of a numeric column
+      --value-statistic <S>
+                         mean (default), median or max of the value column
+  -f, --filter <QUERY>   Show only the rows matching a query, e.g. \"price > 0 and price < 1000\"
+      --width <PIXELS>   Window width (default: 1200)
+      --height <PIXELS>  Window height (default: 820)
+  -o, --output <FILE>    Render the plot into a PNG file without opening a window
+  -s, --session <FILE>   Restore a session saved as YAML or JSON (.json); the other
+                         options override the session
+      --colormap <NAME>  Colormap of the density, e.g. viridis; add _r for the reversed map
+                         (default: blue-cyan)
+      --colormap-file <FILE>
+                         Load additional colormaps from a YAML or JSON (.json) file
+      --highlight-color <#RRGGBB>
+                         Color of the first highlight group (default: #ff0000)
+  -h, --help             Print this help
+
+CSV options:
+  -d, --delimiter <CHAR> Field delimiter (default: ',', tab for .tsv files)
+      --quote <CHAR>     Quote character (default: '\"')
+      --escape <CHAR>    Escape character inside quoted values
+      --comment <CHAR>   Ignore the lines starting with this character
+      --no-header        The file has no header, columns are named column1, column2...
+      --skip-rows <N>    Number of rows to skip before the header
+      --decimal-comma    Numbers use a decimal comma (e.g. 3,14); the default delimiter is ';'
+      --no-datetime      Do not recognize dates, keep them as text
+      --encoding <ENC>   utf-8 (default) or latin-1
+      --null-values <LIST>
+                         Comma-separated values treated as missing
+                         (default: empty, NA, N/A, NaN, null, NULL)
+
+Transformations: linear, logarithmic, quantile, quantile-normal";
+
+/// Command line arguments of the viewer and of the headless `pointcloud-render`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Arguments {
+    pub input: String,
+    pub columns: Option<Vec<String>>,
+    pub xcolumn: Option<String>,
+    pub ycolumn: Option<String>,
+    pub txtype: Option<TransformationType>,
+    pub tytype: Option<TransformationType>,
+    pub weight_column: Option<String>,
+    pub color_column: Option<String>,
+    pub value_column: Option<String>,
+    pub value_statistic: Option<ValueStatistic>,
+    /// Query of the shown rows.
+    pub row_filter: Option<String>,
+    pub window_width: i32,
+    pub window_height: i32,
+    pub output: Option<String>,
+    pub session: Option<String>,
+    pub colormap: Option<String>,
+    pub colormap_file: Option<String>,
+    pub highlight_color: Option<Rgb>,
+    pub csv_options: CsvOptions,
+    pub help: bool,
+}
+
+impl Default for Arguments {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Arguments {
+    pub fn new() -> Arguments {
+        Arguments {
+            input: DEFAULT_INPUT.to_owned(),
+            columns: None,
+            xcolumn: None,
+            ycolumn: None,
+            txtype: None,
+            tytype: None,
+            weight_column: None,
+            color_column: None,
+            value_column: None,
+            value_statistic: None,
+            row_filter: None,
+            window_width: DEFAULT_WINDOW_WIDTH,
+            window_height: DEFAULT_WINDOW_HEIGHT,
+            output: None,
+            session: None,
+            colormap: None,
+            colormap_file: None,
+            highlight_color: None,
+            csv_options: CsvOptions::for_path(DEFAULT_INPUT),
+            help: false,
+        }
+    }
+
+    /// Parse the arguments of the current process (without the program name).
+    pub fn from_env() -> Result<Arguments> {
+        Self::parse(std::env::args().skip(1))
+    }
+
+    pub fn parse<I, S>(args: I) -> Result<Arguments>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut arguments = Arguments::new();
+        let mut input = None;
+        let mut delimiter = None;
+        let mut args = args.into_iter().map(|x| x.into());
+        while let Some(arg) = args.next() {
+            let (option, inline_value) = match arg.split_once('=') {
+                Some((option, value)) if arg.starts_with("--") => {
+                    (option.to_owned(), Some(value.to_owned()))
+                }
+                _ => (arg.clone(), None),
+            };
+            let mut value = |name: &str| -> Result<String> {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| anyhow!("Missing value for option '{}'", name))
+            };
+            match option.as_str() {
+                "-h" | "--help" => arguments.help = true,
+                "-c" | "--columns" => {
+                    arguments.columns = Some(
+                        value(&option)?
+                            .split(',')
+                            .map(|x| x.trim().to_owned())
+                            .filter(|x| !x.is_empty())
+                            .collect(),
+                    )
+                }
+                "-x" | "--x-column" => arguments.xcolumn = Some(value(&option)?),
+                "-y" | "--y-column" => arguments.ycolumn = Some(value(&option)?),
+                "--x-transform" => {
+                    arguments.txtype = Some(TransformationType::from_str(&value(&option)?)?)
+                }
+                "--y-transform" => {
+                    arguments.tytype = Some(TransformationType::from_str(&value(&option)?)?)
+                }
+                "-w" | "--weight" => arguments.weight_column = Some(value(&option)?),
+                "--color-by" => arguments.color_column = Some(value(&option)?),
+                "--value-column" => arguments.value_column = Some(value(&option)?),
+                "--value-statistic" => {
+                    arguments.value_statistic = Some(ValueStatistic::from_str(&value(&option)?)?)
+                }
+                "-f" | "--filter" => {
+                    let query = value(&option)?;
+                    Expression::parse(&query)
+                        .with_context(|| format!("Invalid filter '{}'", query))?;
+                    arguments.row_filter = Some(query)
+                }
+                "--width" => arguments.window_width = Self::parse_size(&option, &value(&option)?)?,
+                "--height" => {
+                    arguments.window_height = Self::parse_size(&option, &value(&option)?)?
+                }
+                "-o" | "--output" => arguments.output = Some(value(&option)?),
+                "-s" | "--session" => arguments.session = Some(value(&option)?),
+                "--colormap" => arguments.colormap = Some(value(&option)?),
+                "--colormap-file" => arguments.colormap_file = Some(value(&option)?),
+                "--highlight-color" => {
+                    arguments.highlight_color = Some(Rgb::from_str(&value(&option)?)?)
+                }
+                "-d" | "--delimiter" => {
+                    delimiter = Some(Self::parse_char(&option, &value(&option)?)?)
+                }
+                "--quote" => {
+                    arguments.csv_options.quote = Self::parse_char(&option, &value(&option)?)?
+                }
+                "--escape" => {
+                    arguments.csv_options.escape =
+                        Some(Self::parse_char(&option, &value(&option)?)?)
+                }
+                "--comment" => {
+                    arguments.csv_options.comment =
+                        Some(Self::parse_char(&option, &value(&option)?)?)
+                }
+                "--no-header" => arguments.csv_options.has_headers = false,
+                "--skip-rows" => {
+                    let rows = value(&option)?;
+                    arguments.csv_options.skip_rows = rows.parse().map_err(|_| {
+                        anyhow!(
+                            "Invalid value '{}' for option '{}', expected a number of rows",
+                            rows,
+                            option
+                        )
+                    })?
+                }
+                "--decimal-comma" => arguments.csv_options.decimal_comma = true,
+                "--no-datetime" => arguments.csv_options.parse_datetimes = false,
+                "--null-values" => {
+                    arguments.csv_options.null_values =
+                        value(&option)?.split(',').map(|x| x.to_owned()).collect()
+                }
+                "--encoding" => {
+                    arguments.csv_options.encoding = Encoding::from_str(&value(&option)?)?
+                }
+                _ if option.starts_with('-') && option.len() > 1 => {
+                    bail!("Unknown option '{}'", option)
+                }
+                _ => {
+                    if input.is_some() {
+                        bail!(
+                            "Unexpected argument '{}', only one input file is supported",
+                            arg
+                        );
+                    }
+                    input = Some(arg);
+                }
+            }
+        }
+        if let Some(input) = input {
+            arguments.input = input;
+        } else if arguments.session.is_some() {
+            // taken from the session
+            arguments.input = String::new();
+        }
+        arguments.csv_options.delimiter =
+            delimiter.unwrap_or_else(|| match CsvOptions::for_path(&arguments.input).delimiter {
+                ',' if arguments.csv_options.decimal_comma => ';',
+                delimiter => delimiter,
+            });
+        arguments.csv_options.validate()?;
+        Ok(arguments)
+    }
+
+    /// Check that the input file exists, to report it before opening a window.
+    pub fn check_input(&self) -> Result<()> {
+        if !std::path::Path::new(&self.input).is_file() {
+            bail!("Input file '{}' does not exist", self.input);
+        }
+        Ok(())
+    }
+
+    /// Render the density plot into a PNG file without opening a window.
+    pub fn render(&self, session: Option<&Session>, output: &str) -> Result<()> {
+        let mut pipeline = Pipeline::new();
+        pipeline
+            .load(&self.input, &self.csv_options, self.columns().as_deref())
+            .with_context(|| format!("Failed to load '{}'", self.input))?;
+        if let Some(session) = session {
+            session.apply(&mut pipeline)?;
+        }
+        self.apply(&mut pipeline)?;
+        pipeline.run();
+        pipeline
+            .mesh
+            .save_png(output)
+            .with_context(|| format!("Failed to write '{}'", output))
+    }
+
+    /// Columns to load, if restricted by the `--columns` option.
+    pub fn columns(&self) -> Option<Vec<&str>> {
+        self.columns
+            .as_ref()
+            .map(|columns| columns.iter().map(|x| x.as_str()).collect())
+    }
+
+    /// Load the session given by `--session`. The input file and CSV options of the session
+    /// are used when no input file is given, the columns to load when `--columns` is not given.
+    pub fn load_session(&mut self) -> Result<Option<Session>> {
+        let session = match &self.session {
+            Some(path) => Session::load(path)?,
+            None => return Ok(None),
+        };
+        if self.input.is_empty() {
+            self.input = session.input.clone();
+            self.csv_options = session.csv_options.clone();
+        }
+        if self.columns.is_none() {
+            self.columns = session.columns.clone();
+        }
+        Ok(Some(session))
+    }
+
+    /// The built-in colormaps and the colormaps loaded from `--colormap-file`.
+    pub fn colormaps(&self) -> Result<Vec<Colormap>> {
+        let mut colormaps = Colormap::builtin();
+        if let Some(path) = &self.colormap_file {
+            colormaps.extend(Colormap::load(path)?);
+        }
+        Ok(colormaps)
+    }
+
+    /// Apply the column, transformation and color choices to a pipeline with loaded data.
+    pub fn apply(&self, pipeline: &mut Pipeline) -> Result<()> {
+        let check_column = |column: &str| -> Result<String> {
+            if pipeline.point_data.all_headers.iter().any(|x| x == column) {
+                Ok(column.to_owned())
+            } else {
+                Err(anyhow!(
+                    "Column '{}' not found in '{}'; available columns: {}",
+                    column,
+                    self.input,
+                    pipeline.point_data.all_headers.join(", ")
+                ))
+            }
+        };
+        let xcolumn = self.xcolumn.as_deref().map(check_column).transpose()?;
+        let ycolumn = self.ycolumn.as_deref().map(check_column).transpose()?;
+        let weight_column = self
+            .weight_column
+            .as_deref()
+            .map(check_column)
+            .transpose()?;
+        let color_column = self.color_column.as_deref().map(check_column).transpose()?;
+        let value_column = self.value_column.as_deref().map(check_column).transpose()?;
+
+        if let Some(column) = xcolumn {
+            pipeline.set_xcolumn(column);
+        }
+        if let Some(column) = ycolumn {
+            pipeline.set_ycolumn(column);
+        }
+        if let Some(column) = weight_column {
+            pipeline.set_weight_column(column);
+        }
+        if let Some(column) = color_column {
+            pipeline.set_color_column(column);
+        }
+        if let Some(column) = value_column {
+            pipeline.set_value_column(column);
+        }
+        if let Some(statistic) = self.value_statistic {
+            pipeline.set_value_statistic(statistic);
+        }
+        if let Some(query) = &self.row_filter {
+            // unknown columns are reported here, the filter itself ignores invalid queries
+            query_filter(query, &pipeline.point_data)
+                .with_context(|| format!("Invalid filter '{}'", query))?;
+            pipeline.set_row_filter(CombinedHighlightFilter::from_query(query)?);
+        }
+        if let Some(txtype) = self.txtype {
+            pipeline.set_txtype(txtype);
+        }
+        if let Some(tytype) = self.tytype {
+            pipeline.set_tytype(tytype);
+        }
+        if let Some(name) = &self.colormap {
+            let colormaps = self.colormaps()?;
+            let colormap = Colormap::find(&colormaps, name).ok_or_else(|| {
+                anyhow!(
+                    "Unknown colormap '{}'; available colormaps: {}",
+                    name,
+                    colormaps
+                        .iter()
+                        .map(|x| x.name.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })?;
+            pipeline.set_colormap(colormap);
+        }
+        if let Some(color) = self.highlight_color {
+            pipeline.set_highlight_color(color);
+        }
+        Ok(())
+    }
+
+    /// Parse a single ASCII character, "tab" and "\t" stand for the tab character.
+    fn parse_char(option: &str, value: &str) -> Result<char> {
+        match value {
+            "tab" | "\\t" => Ok('\t'),
+            "space" => Ok(' '),
+            _ => {
+                let mut chars = value.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) if c.is_ascii() => Ok(c),
+                    _ => Err(anyhow!(
+                        "Invalid value '{}' for option '{}', expected a single character",
+                        value,
+                        option
+                    )),
+                }
+            }
+        }
+    }
+
+    fn parse_size(option: &str, value: &str) -> Result<i32> {
+        value
+            .parse::<i32>()
+            .ok()
+            .filter(|&size| size > 0)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Invalid value '{}' for option '{}', expected a positive number of pixels",
+                    value,
+                    option
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_defaults() {
+        let args = Arguments::parse(Vec::<String>::new()).unwrap();
+        assert_eq!(args, Arguments::new());
+        assert_eq!(args.input, "data.csv");
+    }
+
+    #[test]
+    fn test_options() {
+        let args = Arguments::parse(vec![
+            "-c",
+            "a, b,weight",
+            "-x",
+            "a",
+            "--y-column=b",
+            "--x-transform",
+            "log",
+            "--y-transform",
+            "Quantile Normal",
+            "-w",
+            "weight",
+            "--color-by",
+            "b",
+            "--value-column",
+            "weight",
+            "--value-statistic",
+            "median",
+            "--width",
+            "800",
+            "-o",
+            "plot.png",
+            "points.csv",
+        ])
+        .unwrap();
+        assert_eq!(args.input, "points.csv");
+        assert_eq!(args.columns(), Some(vec!["a", "b", "weight"]));
+        assert_eq!(args.xcolumn.as_deref(), Some("a"));
+        assert_eq!(args.ycolumn.as_deref(), Some("b"));
+        assert_eq!(args.txtype, Some(TransformationType::Logarithmic));
+        assert_eq!(args.tytype, Some(TransformationType::QuantileNormal));
+        assert_eq!(args.weight_column.as_deref(), Some("weight"));
+        assert_eq!(args.color_column.as_deref(), Some("b"));
+        assert_eq!(args.value_column.as_deref(), Some("weight"));
+        assert_eq!(args.value_statistic, Some(ValueStatistic::Median));
+        assert_eq!(args.window_width, 800);
+        assert_eq!(args.window_height, DEFAULT_WINDOW_HEIGHT);
+        assert_eq!(args.output.as_deref(), Some("plot.png"));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(Arguments::parse(vec!["--unknown"]).is_err());
+        assert!(Arguments::parse(vec!["-x"]).is_err());
+        assert!(Arguments::parse(vec!["--width", "-5"]).is_err());
+        assert!(Arguments::parse(vec!["--x-transform", "cubic"]).is_err());
+        assert!(Arguments::parse(vec!["--value-statistic", "mode"]).is_err());
+        assert!(Arguments::parse(vec!["a.csv", "b.csv"]).is_err());
+        assert!(Arguments::parse(vec!["--delimiter", ";;"]).is_err());
+        assert!(Arguments::parse(vec!["--encoding", "ebcdic"]).is_err());
+        assert!(Arguments::parse(vec!["--highlight-color", "red"]).is_err());
+        assert!(Arguments::parse(vec!["--filter", "x >"]).is_err());
+    }
+
+    #[test]
+    fn test_csv_options() {
+        let args = Arguments::parse(vec!["data.tsv"]).unwrap();
+        assert_eq!(args.csv_options.delimiter, '\t');
+        let args = Arguments::parse(vec![
+            "-d",
+            ";",
+            "--comment=#",
+            "--no-header",
+            "--skip-rows",
+            "2",
+            "--decimal-comma",
+            "--no-datetime",
+            "--encoding",
+            "latin-1",
+            "--null-values=NA,,-",
+            "data.tsv",
+        ])
+        .unwrap();
+        let options = CsvOptions {
+            delimiter: ';',
+            comment: Some('#'),
+            has_headers: false,
+            skip_rows: 2,
+            decimal_comma: true,
+            encoding: Encoding::Latin1,
+            parse_datetimes: false,
+            null_values: vec!["NA".to_owned(), "".to_owned(), "-".to_owned()],
+            ..CsvOptions::new()
+        };
+        assert_eq!(args.csv_options, options);
+        let args = Arguments::parse(vec!["--delimiter", "tab"]).unwrap();
+        assert_eq!(args.csv_options.delimiter, '\t');
+        let args = Arguments::parse(vec!["--decimal-comma"]).unwrap();
+        assert_eq!(args.csv_options.delimiter, ';');
+        let args = Arguments::parse(vec!["--decimal-comma", "data.tsv"]).unwrap();
+        assert_eq!(args.csv_options.delimiter, '\t');
+        assert!(Arguments::parse(vec!["--decimal-comma", "-d", ","]).is_err());
+    }
+
+    #[test]
+    fn test_filter() {
+        let args = Arguments::parse(vec!["-f", "x > 1 and y < 9"]).unwrap();
+        let mut pipeline = Pipeline::new();
+        pipeline
+            .load_csv(&mut std::io::Cursor::new("x,y\n0,0\n1,1\n2,4\n3,9\n"))
+            .unwrap();
+        args.apply(&mut pipeline).unwrap();
+        assert_eq!(pipeline.shown_count(), 1);
+        assert_eq!(pipeline.row_filter.filters.len(), 2);
+        let args = Arguments::parse(vec!["--filter=z > 1"]).unwrap();
+        assert!(args.apply(&mut pipeline).is_err());
+    }
+
+    #[test]
+    fn test_colormap() {
+        let args = Arguments::parse(vec![
+            "--colormap",
+            "magma_r",
+            "--highlight-color",
+            "#00ff00",
+        ])
+        .unwrap();
+        let mut pipeline = Pipeline::new();
+        args.apply(&mut pipeline).unwrap();
+        assert_eq!(pipeline.colormap().full_name(), "magma_r");
+        assert_eq!(pipeline.highlight_color(), Rgb([0, 255, 0]));
+
+        let args = Arguments::parse(vec!["--colormap", "rainbow"]).unwrap();
+        assert!(args.apply(&mut pipeline).is_err());
+        let args = Arguments::parse(vec!["--colormap-file", "missing.yaml"]).unwrap();
+        assert!(args.colormaps().is_err());
+    }
+
+    #[test]
+    fn test_session() {
+        let path = std::env::temp_dir().join("pointcloud-viewer-test-session.json");
+        let path = path.to_str().unwrap();
+        let session = Session {
+            input: "points.tsv".to_owned(),
+            csv_options: CsvOptions::for_path("points.tsv"),
+            columns: Some(vec!["a".to_owned(), "b".to_owned()]),
+            ..Session::new()
+        };
+        session.save(path).unwrap();
+
+        let mut args = Arguments::parse(vec!["--session", path]).unwrap();
+        assert_eq!(args.load_session().unwrap(), Some(session.clone()));
+        assert_eq!(args.input, "points.tsv");
+        assert_eq!(args.csv_options.delimiter, '\t');
+        assert_eq!(args.columns(), Some(vec!["a", "b"]));
+
+        let mut args = Arguments::parse(vec!["-s", path, "-c", "a", "data.csv"]).unwrap();
+        args.load_session().unwrap();
+        assert_eq!(args.input, "data.csv");
+        assert_eq!(args.csv_options.delimiter, ',');
+        assert_eq!(args.columns(), Some(vec!["a"]));
+        std::fs::remove_file(path).unwrap();
+
+        let mut args = Arguments::parse(vec!["-s", path]).unwrap();
+        assert!(args.load_session().is_err());
+    }
+}
@@ -0,0 +1,333 @@
+//! Colormaps of the density plot: gradients defined by color stops.
+//! Besides the built-in maps, user-defined colormaps can be loaded from a YAML or JSON file
+//! with a list of maps, e.g.
+//!
+//! ```yaml
+//! - name: fire
+//!   stops:
+//!   - [0.0, "#000000"]
+//!   - [0.6, "#ff0000"]
+//!   - [1.0, "#ffff00"]
+//! ```
+
+use anyhow::*;
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+/// Suffix of the name of a reversed colormap, e.g. "viridis_r".
+pub const REVERSED_SUFFIX: &str = "_r";
+/// Number of colors in the lookup table used to color a mesh.
+const LUT_SIZE: usize = 256;
+
+// Perceptually uniform maps sampled uniformly from the matplotlib colormaps.
+const VIRIDIS: [&str; 11] = [
+    "#440154", "#482475", "#414487", "#355f8d", "#2a788e", "#21918c", "#22a884", "#44bf70",
+    "#7ad151", "#bddf26", "#fde725",
+];
+const MAGMA: [&str; 11] = [
+    "#000004", "#140e36", "#3b0f70", "#641a80", "#8c2981", "#b73779", "#de4968", "#f7705c",
+    "#fe9f6d", "#fecf92", "#fcfdbf",
+];
+const INFERNO: [&str; 11] = [
+    "#000004", "#160b39", "#420a68", "#6a176e", "#932667", "#bc3754", "#dd513a", "#f37819",
+    "#fca50a", "#f6d746", "#fcffa4",
+];
+const PLASMA: [&str; 11] = [
+    "#0d0887", "#41049d", "#6a00a8", "#8f0da4", "#b12a90", "#cc4778", "#e16462", "#f2844b",
+    "#fca636", "#fcce25", "#f0f921",
+];
+const CIVIDIS: [&str; 10] = [
+    "#00224e", "#123570", "#3b496c", "#575d6d", "#707173", "#8a8779", "#a69d75", "#c4b56c",
+    "#e4cf5b", "#fee838",
+];
+// Diverging maps for values around a center.
+const COOLWARM: [&str; 9] = [
+    "#3b4cc0", "#6282ea", "#8db0fe", "#b8d0f9", "#dddcdc", "#f5c4ac", "#f49a7b", "#e36a53",
+    "#b40426",
+];
+const RDBU: [&str; 11] = [
+    "#67001f", "#b2182b", "#d6604d", "#f4a582", "#fddbc7", "#f7f7f7", "#d1e5f0", "#92c5de",
+    "#4393c3", "#2166ac", "#053061",
+];
+
+/// Colors of categories: the "tab10" palette followed by its lighter variants ("tab20").
+const PALETTE: [&str; 20] = [
+    "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b", "#e377c2", "#7f7f7f",
+    "#bcbd22", "#17becf", "#aec7e8", "#ffbb78", "#98df8a", "#ff9896", "#c5b0d5", "#c49c94",
+    "#f7b6d2", "#c7c7c7", "#dbdb8d", "#9edae5",
+];
+
+/// RGB color, written as "#rrggbb" in configuration and session files.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub struct Rgb(pub [u8; 3]);
+
+impl Rgb {
+    /// Color of the n-th category, the palette repeats after 20 colors.
+    pub fn palette(index: usize) -> Rgb {
+        Rgb::from_str(PALETTE[index % PALETTE.len()]).unwrap()
+    }
+}
+
+impl FromStr for Rgb {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Rgb> {
+        let hex = s.trim().trim_start_matches('#');
+        if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            bail!("Invalid color '{}', expected #rrggbb", s);
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).unwrap();
+        Ok(Rgb([channel(0), channel(1), channel(2)]))
+    }
+}
+
+impl TryFrom<String> for Rgb {
+    type Error = Error;
+    fn try_from(s: String) -> Result<Rgb> {
+        Rgb::from_str(&s)
+    }
+}
+
+impl fmt::Display for Rgb {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.0[0], self.0[1], self.0[2])
+    }
+}
+
+impl From<Rgb> for String {
+    fn from(color: Rgb) -> String {
+        color.to_string()
+    }
+}
+
+/// Gradient between color stops at positions from 0 to 1.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Colormap {
+    pub name: String,
+    pub stops: Vec<(f64, Rgb)>,
+    #[serde(default)]
+    pub reversed: bool,
+}
+
+impl Default for Colormap {
+    fn default() -> Self {
+        Self::blue_cyan()
+    }
+}
+
+impl Colormap {
+    /// Colormap from stops, the stops are sorted by their position.
+    pub fn new(name: &str, stops: Vec<(f64, Rgb)>) -> Colormap {
+        let mut stops = stops;
+        stops.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        Colormap {
+            name: name.to_owned(),
+            stops,
+            reversed: false,
+        }
+    }
+
+    /// Colormap with uniformly spaced stops given as "#rrggbb".
+    fn uniform(name: &str, colors: &[&str]) -> Colormap {
+        let n = (colors.len().max(2) - 1) as f64;
+        Colormap::new(
+            name,
+            colors
+                .iter()
+                .enumerate()
+                .map(|(i, color)| (i as f64 / n, Rgb::from_str(color).unwrap()))
+                .collect(),
+        )
+    }
+
+    /// The original colormap of the viewer: black - blue - cyan.
+    pub fn blue_cyan() -> Colormap {
+        Colormap::uniform("blue-cyan", &["#000000", "#0000ff", "#00ffff"])
+    }
+
+    pub fn gray() -> Colormap {
+        Colormap::uniform("gray", &["#000000", "#ffffff"])
+    }
+
+    /// Diverging blue - white - red colormap.
+    pub fn coolwarm() -> Colormap {
+        Colormap::uniform("coolwarm", &COOLWARM)
+    }
+
+    /// All the built-in colormaps.
+    pub fn builtin() -> Vec<Colormap> {
+        vec![
+            Colormap::blue_cyan(),
+            Colormap::gray(),
+            Colormap::uniform("viridis", &VIRIDIS),
+            Colormap::uniform("magma", &MAGMA),
+            Colormap::uniform("inferno", &INFERNO),
+            Colormap::uniform("plasma", &PLASMA),
+            Colormap::uniform("cividis", &CIVIDIS),
+            Colormap::coolwarm(),
+            Colormap::uniform("rdbu", &RDBU),
+        ]
+    }
+
+    /// Find a colormap by name; a name ending with "_r" gives the reversed colormap.
+    pub fn find(colormaps: &[Colormap], name: &str) -> Option<Colormap> {
+        if let Some(colormap) = colormaps.iter().find(|x| x.name == name) {
+            return Some(colormap.clone());
+        }
+        let name = name.strip_suffix(REVERSED_SUFFIX)?;
+        colormaps
+            .iter()
+            .find(|x| x.name == name)
+            .map(|x| x.reversed())
+    }
+
+    /// Load user-defined colormaps from a YAML file, or JSON if the name ends with `.json`.
+    pub fn load(path: &str) -> Result<Vec<Colormap>> {
+        let text =
+            std::fs::read_to_string(path).with_context(|| format!("Failed to read '{}'", path))?;
+        let colormaps: Vec<Colormap> = if path.to_lowercase().ends_with(".json") {
+            serde_json::from_str(&text)?
+        } else {
+            serde_yaml::from_str(&text)?
+        };
+        colormaps
+            .into_iter()
+            .map(|colormap| {
+                if colormap.stops.len() < 2 {
+                    bail!("Colormap '{}' needs at least two stops", colormap.name);
+                }
+                if colormap
+                    .stops
+                    .iter()
+                    .any(|(position, _)| !(0.0..=1.0).contains(position))
+                {
+                    bail!(
+                        "Stop positions of the colormap '{}' must be between 0 and 1",
+                        colormap.name
+                    );
+                }
+                Ok(Colormap {
+                    reversed: colormap.reversed,
+                    ..Colormap::new(&colormap.name, colormap.stops)
+                })
+            })
+            .collect::<Result<Vec<_>>>()
+            .with_context(|| format!("Invalid colormap file '{}'", path))
+    }
+
+    /// The same colormap in the opposite direction.
+    pub fn reversed(&self) -> Colormap {
+        Colormap {
+            reversed: !self.reversed,
+            ..self.clone()
+        }
+    }
+
+    /// Name including the "_r" suffix of reversed maps.
+    pub fn full_name(&self) -> String {
+        if self.reversed {
+            format!("{}{}", self.name, REVERSED_SUFFIX)
+        } else {
+            self.name.clone()
+        }
+    }
+
+    /// Color of a level between 0 and 1 (clamped), interpolated between the stops.
+    pub fn color(&self, level: f64) -> [u8; 3] {
+        let level = if level.is_nan() {
+            0.0
+        } else {
+            level.clamp(0.0, 1.0)
+        };
+        let level = if self.reversed { 1.0 - level } else { level };
+        let (first, last) = match (self.stops.first(), self.stops.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return [0, 0, 0],
+        };
+        if level <= first.0 {
+            return first.1 .0;
+        }
+        for pair in self.stops.windows(2) {
+            let ((x0, Rgb(c0)), (x1, Rgb(c1))) = (pair[0], pair[1]);
+            if level <= x1 {
+                let f = if x1 > x0 {
+                    (level - x0) / (x1 - x0)
+                } else {
+                    1.0
+                };
+                let mix =
+                    |i: usize| (c0[i] as f64 + f * (c1[i] as f64 - c0[i] as f64)).round() as u8;
+                return [mix(0), mix(1), mix(2)];
+            }
+        }
+        last.1 .0
+    }
+
+    /// Colors of uniformly spaced levels, used to color the meshes.
+    pub fn lut(&self) -> Vec<[u8; 3]> {
+        (0..LUT_SIZE)
+            .map(|i| self.color(i as f64 / (LUT_SIZE - 1) as f64))
+            .collect()
+    }
+
+    /// Index of a level in the lookup table.
+    pub fn lut_index(level: f64) -> usize {
+        if level >= 1.0 {
+            LUT_SIZE - 1
+        } else if level > 0.0 {
+            (level * (LUT_SIZE - 1) as f64) as usize
+        } else {
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_colormap() {
+        let colormap = Colormap::blue_cyan();
+        assert_eq!(colormap.color(0.0), [0, 0, 0]);
+        assert_eq!(colormap.color(0.25), [0, 0, 128]);
+        assert_eq!(colormap.color(1.0), [0, 255, 255]);
+        assert_eq!(colormap.color(2.0), [0, 255, 255]);
+        assert_eq!(colormap.reversed().color(1.0), [0, 0, 0]);
+
+        let colormaps = Colormap::builtin();
+        let viridis = Colormap::find(&colormaps, "viridis").unwrap();
+        assert_eq!(viridis.color(0.0), [0x44, 0x01, 0x54]);
+        let reversed = Colormap::find(&colormaps, "viridis_r").unwrap();
+        assert!(reversed.reversed);
+        assert_eq!(reversed.full_name(), "viridis_r");
+        assert_eq!(reversed.color(0.0), [0xfd, 0xe7, 0x25]);
+        assert!(Colormap::find(&colormaps, "unknown").is_none());
+        assert_eq!(viridis.lut().len(), 256);
+        assert_eq!(Colormap::lut_index(1.5), 255);
+
+        assert_eq!(Rgb::from_str("#FF8000").unwrap(), Rgb([255, 128, 0]));
+        assert_eq!(Rgb([255, 128, 0]).to_string(), "#ff8000");
+        assert!(Rgb::from_str("red").is_err());
+        assert_eq!(Rgb::palette(21), Rgb::palette(1));
+    }
+
+    #[test]
+    fn test_load() {
+        let path = std::env::temp_dir().join("pointcloud-viewer-test-colormaps.yaml");
+        let path = path.to_str().unwrap();
+        std::fs::write(
+            path,
+            "- name: fire\n  stops:\n  - [1.0, \"#ffff00\"]\n  - [0.0, \"#000000\"]\n",
+        )
+        .unwrap();
+        let colormaps = Colormap::load(path).unwrap();
+        assert_eq!(colormaps[0].name, "fire");
+        assert_eq!(colormaps[0].color(0.5), [128, 128, 0]);
+
+        std::fs::write(path, "- name: bad\n  stops:\n  - [0.0, \"#000000\"]\n").unwrap();
+        assert!(Colormap::load(path).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
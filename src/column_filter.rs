@@ -1,13 +1,50 @@
 #![allow(dead_code)]
+use anyhow::*;
+use regex::Regex;
 
-#[derive(Debug, Clone, PartialOrd, PartialEq, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialOrd, PartialEq, Copy)]
 pub enum Interpretation{
     Contains,
     Prefix,
-    Postfix
+    Postfix,
+    /// Regular expression matching a part of the text
+    Regex
 }
 
-#[derive(Debug, Clone, PartialOrd, PartialEq, Copy)]
+/// Test of a text.
+pub type Matcher = Box<dyn Fn(&str)->bool>;
+
+impl Interpretation{
+    pub const ALL:[Interpretation;4] = [
+        Interpretation::Contains,
+        Interpretation::Prefix,
+        Interpretation::Postfix,
+        Interpretation::Regex
+    ];
+    pub fn name(&self)->&str{
+        match self{
+            Interpretation::Contains => "contains",
+            Interpretation::Prefix => "starts with",
+            Interpretation::Postfix => "ends with",
+            Interpretation::Regex => "matches regex"
+        }
+    }
+    /// Test of texts matching a pattern, fails for an invalid regular expression.
+    pub fn matcher(&self, pattern:&str)->Result<Matcher>{
+        let pattern = pattern.to_owned();
+        Ok(match self{
+            Interpretation::Contains => Box::new(move |text:&str| text.contains(&pattern)),
+            Interpretation::Prefix => Box::new(move |text:&str| text.starts_with(&pattern)),
+            Interpretation::Postfix => Box::new(move |text:&str| text.ends_with(&pattern)),
+            Interpretation::Regex => {
+                let regex = Regex::new(&pattern)?;
+                Box::new(move |text:&str| regex.is_match(text))
+            }
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialOrd, PartialEq, Copy)]
 pub enum Operator{
     And, Or
 }
@@ -20,6 +57,12 @@ pub struct ColumnFilter{
     case_sensitive:bool
 }
 
+impl Default for ColumnFilter{
+    fn default()->Self{
+        Self::new()
+    }
+}
+
 impl ColumnFilter{
     pub fn new()->Self{
         ColumnFilter{
@@ -53,7 +96,10 @@ impl ColumnFilter{
         let matching:fn(&str,&str)->bool = match self.interpretation{
             Interpretation::Contains => |column, token| column.contains(token),
             Interpretation::Prefix => |column, token| column.starts_with(token),
-            Interpretation::Postfix => |column, token| column.ends_with(token)
+            Interpretation::Postfix => |column, token| column.ends_with(token),
+            Interpretation::Regex => |column, token| {
+                Regex::new(token).map(|regex| regex.is_match(column)).unwrap_or(false)
+            }
         };
 
         match self.operator{
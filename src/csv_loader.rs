@@ -0,0 +1,792 @@
+#![allow(dead_code)]
+//! Single-pass streaming CSV loader.
+//!
+//! Column types are inferred from a sample of the first rows; the remaining rows are parsed
+//! directly into the column vectors, so every value is parsed only once and no intermediate
+//! records are kept. Integer, float, boolean, datetime (ISO-8601) and text columns are recognized,
+//! datetimes are converted to seconds since the epoch and text columns
+//! are dictionary-encoded (categorical) unless they have too many distinct values.
+//! A numeric, boolean or datetime column that meets a non-matching value after the sample
+//! is converted to a text column; its values loaded so far are read again from the input,
+//! so that the text column keeps the original values (e.g. `007` or `NA`).
+//! This is why the input has to be seekable.
+//! Values matching one of the null tokens (e.g. empty cells or NA) are missing values,
+//! they are stored as NaN in numeric columns.
+//!
+//! The loading is done in chunks (see `CsvLoader::read_chunk`), so that the caller can
+//! report progress and cancel the loading between the chunks.
+//!
+//! The CSV dialect (delimiter, quoting, comments, header, decimal comma, encoding)
+//! is configured by `CsvOptions`.
+
+use crate::datetime::parse_timestamp;
+use crate::pointdata::{parse_bool, Categorical, ColumnType, PointData, MAX_CATEGORIES};
+use anyhow::*;
+use csv::ByteRecord;
+use std::io::{Read, Seek};
+use std::path::Path;
+use std::str::FromStr;
+
+pub const DEFAULT_SAMPLE_SIZE: usize = 1000;
+pub const DEFAULT_NULL_VALUES: [&str; 6] = ["", "NA", "N/A", "NaN", "null", "NULL"];
+
+/// Character encoding of a CSV file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Copy)]
+pub enum Encoding {
+    Utf8,
+    Latin1,
+}
+
+impl Encoding {
+    pub fn text(&self) -> &str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Latin1 => "Latin-1",
+        }
+    }
+
+    pub fn decode(&self, value: &[u8]) -> Result<String> {
+        match self {
+            Encoding::Utf8 => std::str::from_utf8(value)
+                .map(|x| x.to_owned())
+                .map_err(|_| anyhow!("Invalid UTF-8 text, try the Latin-1 encoding")),
+            Encoding::Latin1 => Ok(value.iter().map(|&x| x as char).collect()),
+        }
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Encoding> {
+        match s.to_lowercase().as_str() {
+            "utf-8" | "utf8" => Ok(Encoding::Utf8),
+            "latin-1" | "latin1" | "iso-8859-1" => Ok(Encoding::Latin1),
+            _ => Err(anyhow!("Unsupported encoding '{}'", s)),
+        }
+    }
+}
+
+/// CSV dialect.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct CsvOptions {
+    pub delimiter: char,
+    pub quote: char,
+    /// Escape character inside quoted values (besides the doubled quote).
+    pub escape: Option<char>,
+    /// Lines starting with this character are ignored.
+    pub comment: Option<char>,
+    /// When false, the columns are named column1, column2, ...
+    pub has_headers: bool,
+    /// Number of rows skipped before the header (or before the data, if there is no header).
+    pub skip_rows: usize,
+    /// Numbers use a comma as the decimal separator (e.g. 3,14).
+    pub decimal_comma: bool,
+    pub encoding: Encoding,
+    /// Values treated as missing.
+    pub null_values: Vec<String>,
+    /// Recognize ISO-8601 dates and datetimes.
+    pub parse_datetimes: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CsvOptions {
+    pub fn new() -> CsvOptions {
+        CsvOptions {
+            delimiter: ',',
+            quote: '"',
+            escape: None,
+            comment: None,
+            has_headers: true,
+            skip_rows: 0,
+            decimal_comma: false,
+            encoding: Encoding::Utf8,
+            null_values: DEFAULT_NULL_VALUES.iter().map(|x| x.to_string()).collect(),
+            parse_datetimes: true,
+        }
+    }
+
+    /// Default options for a file; tab is used as the delimiter for .tsv and .tab files.
+    pub fn for_path(path: &str) -> CsvOptions {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|x| x.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        CsvOptions {
+            delimiter: if extension == "tsv" || extension == "tab" {
+                '\t'
+            } else {
+                ','
+            },
+            ..CsvOptions::new()
+        }
+    }
+
+    fn ascii(c: char, name: &str) -> Result<u8> {
+        if c.is_ascii() {
+            Ok(c as u8)
+        } else {
+            Err(anyhow!(
+                "The {} must be an ASCII character, got '{}'",
+                name,
+                c
+            ))
+        }
+    }
+
+    /// Check that the options do not conflict:
+    /// numbers with a decimal comma would be split by the comma delimiter.
+    pub fn validate(&self) -> Result<()> {
+        if self.decimal_comma && self.delimiter == ',' {
+            bail!("The decimal comma cannot be used with the comma delimiter, use e.g. ';'");
+        }
+        Ok(())
+    }
+
+    /// Reader builder for the dialect. The header is not handled by the csv reader,
+    /// it is read by the `CsvLoader` after the skipped rows.
+    pub fn reader_builder(&self) -> Result<csv::ReaderBuilder> {
+        self.validate()?;
+        let mut builder = csv::ReaderBuilder::new();
+        builder
+            .delimiter(Self::ascii(self.delimiter, "delimiter")?)
+            .quote(Self::ascii(self.quote, "quote")?)
+            .escape(self.escape.map(|c| Self::ascii(c, "escape")).transpose()?)
+            .comment(
+                self.comment
+                    .map(|c| Self::ascii(c, "comment"))
+                    .transpose()?,
+            )
+            .has_headers(false)
+            .flexible(true);
+        Ok(builder)
+    }
+
+    fn is_null(&self, value: &[u8]) -> bool {
+        self.null_values.iter().any(|x| x.as_bytes() == value)
+    }
+
+    /// Parse a date or datetime as seconds since the epoch; missing values are parsed as NaN.
+    fn parse_datetime(&self, value: &[u8]) -> Option<f64> {
+        if self.is_null(value) {
+            return Some(f64::NAN);
+        }
+        parse_timestamp(std::str::from_utf8(value).ok()?)
+    }
+
+    /// Parse a boolean value as 0 or 1; missing values are parsed as NaN.
+    fn parse_bool(&self, value: &[u8]) -> Option<f64> {
+        if self.is_null(value) {
+            return Some(f64::NAN);
+        }
+        let value = parse_bool(std::str::from_utf8(value).ok()?)?;
+        Some(if value { 1.0 } else { 0.0 })
+    }
+
+    /// Parse a numeric value; missing values are parsed as NaN.
+    fn parse_number(&self, value: &[u8]) -> Option<f64> {
+        if self.is_null(value) {
+            return Some(f64::NAN);
+        }
+        let text = std::str::from_utf8(value).ok()?;
+        if self.decimal_comma && text.contains(',') {
+            text.replacen(',', ".", 1).parse::<f64>().ok()
+        } else {
+            text.parse::<f64>().ok()
+        }
+    }
+}
+
+enum Column {
+    /// Numbers; the flag is true as long as all the values are integers
+    Numeric(Vec<f64>, bool),
+    Boolean(Vec<f64>),
+    DateTime(Vec<f64>),
+    Categorical(Categorical),
+    Text(Vec<String>),
+}
+
+fn is_integer(value: &[u8]) -> bool {
+    std::str::from_utf8(value)
+        .map(|x| x.parse::<i64>().is_ok())
+        .unwrap_or(false)
+}
+
+impl Column {
+    /// Empty column of a type fitting all the sample values.
+    fn infer(values: &[&[u8]], options: &CsvOptions) -> Column {
+        if values.iter().all(|x| options.parse_number(x).is_some()) {
+            let integer = values.iter().all(|x| options.is_null(x) || is_integer(x))
+                && values.iter().any(|x| !options.is_null(x));
+            Column::Numeric(Vec::new(), integer)
+        } else if values.iter().all(|x| options.parse_bool(x).is_some()) {
+            Column::Boolean(Vec::new())
+        } else if options.parse_datetimes
+            && values.iter().all(|x| options.parse_datetime(x).is_some())
+        {
+            Column::DateTime(Vec::new())
+        } else {
+            Column::Categorical(Categorical::new())
+        }
+    }
+
+    /// Categorical or (with too many distinct values) text column of the values.
+    fn from_text(text: Vec<String>) -> Column {
+        let categorical = Categorical::from_values(text.iter().map(|x| x.as_str()));
+        if categorical.categories.len() > MAX_CATEGORIES {
+            Column::Text(text)
+        } else {
+            Column::Categorical(categorical)
+        }
+    }
+
+    /// Add a value; returns false (and does not add the value)
+    /// if it does not match the type of a numeric, boolean or datetime column.
+    fn push(&mut self, value: &[u8], options: &CsvOptions) -> Result<bool> {
+        match self {
+            Column::Numeric(v, integer) => {
+                if let Some(x) = options.parse_number(value) {
+                    if *integer && !x.is_nan() && !is_integer(value) {
+                        *integer = false;
+                    }
+                    v.push(x);
+                    return Ok(true);
+                }
+            }
+            Column::Boolean(v) => {
+                if let Some(x) = options.parse_bool(value) {
+                    v.push(x);
+                    return Ok(true);
+                }
+            }
+            Column::DateTime(v) => {
+                if let Some(x) = options.parse_datetime(value) {
+                    v.push(x);
+                    return Ok(true);
+                }
+            }
+            Column::Categorical(categorical) => {
+                categorical.push(&options.encoding.decode(value)?);
+                if categorical.categories.len() > MAX_CATEGORIES {
+                    *self = Column::Text(categorical.iter().map(|x| x.to_owned()).collect());
+                }
+                return Ok(true);
+            }
+            Column::Text(v) => {
+                v.push(options.encoding.decode(value)?);
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+pub struct CsvLoader<R: Read + Seek> {
+    reader: csv::Reader<R>,
+    options: CsvOptions,
+    /// Position of the first data row, where the columns converted to text are read again
+    data_start: csv::Position,
+    record: ByteRecord,
+    /// First data row of a file without header, already read to determine the number of columns
+    pending: Option<ByteRecord>,
+    headers: Vec<String>,
+    columns: Vec<Column>,
+    length: usize,
+    size: Option<u64>,
+    sample_size: usize,
+    finished: bool,
+}
+
+impl<R: Read + Seek> CsvLoader<R> {
+    pub fn new(reader: R) -> Result<Self> {
+        Self::with_options(reader, &CsvOptions::new())
+    }
+
+    pub fn with_options(reader: R, options: &CsvOptions) -> Result<Self> {
+        let mut reader = options.reader_builder()?.from_reader(reader);
+        let mut record = ByteRecord::new();
+        for _ in 0..options.skip_rows {
+            if !reader.read_byte_record(&mut record)? {
+                break;
+            }
+        }
+        let mut pending = None;
+        let mut data_start = reader.position().clone();
+        let headers = if !reader.read_byte_record(&mut record)? {
+            Vec::new()
+        } else if options.has_headers {
+            let mut headers = record
+                .iter()
+                .map(|x| options.encoding.decode(x))
+                .collect::<Result<Vec<_>>>()
+                .context("Failed to read the header")?;
+            if let Some(first) = headers.first_mut() {
+                *first = first.trim_start_matches('\u{feff}').to_owned();
+            }
+            data_start = reader.position().clone();
+            headers
+        } else {
+            pending = Some(record.clone());
+            (1..=record.len()).map(|i| format!("column{}", i)).collect()
+        };
+        Ok(CsvLoader {
+            reader,
+            options: options.clone(),
+            data_start,
+            record,
+            pending,
+            headers,
+            columns: Vec::new(),
+            length: 0,
+            size: None,
+            sample_size: DEFAULT_SAMPLE_SIZE,
+            finished: false,
+        })
+    }
+
+    /// Total size of the input in bytes, used to calculate the progress.
+    pub fn with_size(mut self, size: u64) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    /// Number of rows used to infer the column types.
+    pub fn with_sample_size(mut self, sample_size: usize) -> Self {
+        self.sample_size = sample_size.max(1);
+        self
+    }
+
+    pub fn headers(&self) -> &[String] {
+        &self.headers
+    }
+
+    /// Number of rows loaded so far.
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Fraction of the input processed (between 0 and 1), if the size of the input is known.
+    pub fn progress(&self) -> Option<f64> {
+        if self.finished {
+            return Some(1.0);
+        }
+        self.size.map(|size| {
+            if size == 0 {
+                1.0
+            } else {
+                (self.reader.position().byte() as f64 / size as f64).min(1.0)
+            }
+        })
+    }
+
+    fn infer_types(&mut self, sample: &[ByteRecord]) {
+        let options = &self.options;
+        self.columns = (0..self.headers.len())
+            .map(|i| {
+                let values = sample
+                    .iter()
+                    .map(|r| r.get(i).unwrap_or(b""))
+                    .collect::<Vec<_>>();
+                Column::infer(&values, options)
+            })
+            .collect();
+    }
+
+    /// Read the first `rows` values of a column again as text.
+    /// The reading continues from the current position afterwards.
+    fn reread_column(&mut self, column: usize, rows: usize) -> Result<Vec<String>> {
+        let resume = self.reader.position().clone();
+        self.reader.seek(self.data_start.clone())?;
+        let mut record = ByteRecord::new();
+        let mut values = Vec::with_capacity(rows);
+        while values.len() < rows && self.reader.read_byte_record(&mut record)? {
+            values.push(
+                self.options
+                    .encoding
+                    .decode(record.get(column).unwrap_or(b""))?,
+            );
+        }
+        self.reader.seek(resume)?;
+        Ok(values)
+    }
+
+    /// Add the values of the row with the given index; a column meeting a value
+    /// not matching its type is converted to text.
+    fn push_record(&mut self, record: &ByteRecord, row: usize) -> Result<()> {
+        let context = || match record.position() {
+            Some(position) => format!("Line {}", position.line()),
+            None => "Failed to read a row".to_owned(),
+        };
+        for i in 0..self.columns.len() {
+            let value = record.get(i).unwrap_or(b"");
+            if !self.columns[i]
+                .push(value, &self.options)
+                .with_context(context)?
+            {
+                let text = self.reread_column(i, row).with_context(context)?;
+                self.columns[i] = Column::from_text(text);
+                self.columns[i]
+                    .push(value, &self.options)
+                    .with_context(context)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read at most `max_rows` rows. Returns true when the whole input has been read.
+    pub fn read_chunk(&mut self, max_rows: usize) -> Result<bool> {
+        if self.finished {
+            return Ok(true);
+        }
+        let mut rows = 0;
+        if self.length == 0 && self.columns.is_empty() {
+            let mut sample = self.pending.take().into_iter().collect::<Vec<_>>();
+            while sample.len() < self.sample_size {
+                let mut record = ByteRecord::new();
+                if !self.reader.read_byte_record(&mut record)? {
+                    self.finished = true;
+                    break;
+                }
+                sample.push(record);
+            }
+            self.infer_types(&sample);
+            for (row, record) in sample.iter().enumerate() {
+                self.push_record(record, row)?;
+            }
+            self.length = sample.len();
+            rows = sample.len();
+        }
+        while !self.finished && rows < max_rows {
+            if self.reader.read_byte_record(&mut self.record)? {
+                let record = std::mem::take(&mut self.record);
+                self.push_record(&record, self.length)?;
+                self.record = record;
+                self.length += 1;
+                rows += 1;
+            } else {
+                self.finished = true;
+            }
+        }
+        Ok(self.finished)
+    }
+
+    /// Read all the remaining rows.
+    pub fn read_all(&mut self) -> Result<()> {
+        while !self.read_chunk(usize::MAX)? {}
+        Ok(())
+    }
+
+    /// Create the point data from the rows loaded so far.
+    pub fn finish(mut self) -> PointData {
+        if self.columns.is_empty() {
+            self.infer_types(&[]);
+        }
+        let mut point_data = PointData::new();
+        for (column, values) in self.headers.into_iter().zip(self.columns) {
+            point_data.headers.push(column.to_owned());
+            point_data.all_headers.push(column.to_owned());
+            match values {
+                Column::Numeric(v, integer) => {
+                    let column_type = if integer {
+                        ColumnType::Integer
+                    } else {
+                        ColumnType::Float
+                    };
+                    point_data
+                        .column_types
+                        .insert(column.to_owned(), column_type);
+                    point_data.data.insert(column, v);
+                }
+                Column::Boolean(v) => {
+                    point_data
+                        .column_types
+                        .insert(column.to_owned(), ColumnType::Boolean);
+                    point_data.data.insert(column, v);
+                }
+                Column::DateTime(v) => {
+                    point_data
+                        .column_types
+                        .insert(column.to_owned(), ColumnType::DateTime);
+                    point_data.data.insert(column, v);
+                }
+                Column::Categorical(v) => {
+                    point_data.categorical.insert(column, v);
+                }
+                Column::Text(v) => {
+                    point_data.aux.insert(column, v);
+                }
+            }
+        }
+        point_data.length = self.length;
+        point_data
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    fn categories<'a>(point_data: &'a PointData, column: &str) -> Vec<&'a str> {
+        point_data.categorical[column].iter().collect()
+    }
+
+    const CSV: &str = "x,y,label,late\n1,2.5,a,1\n2,3.5,b,2\n3,4.5,c,3\n4,5.5,d,x\n";
+
+    #[test]
+    fn test_read_all() {
+        let mut loader = CsvLoader::new(Cursor::new(CSV)).unwrap();
+        loader.read_all().unwrap();
+        let point_data = loader.finish();
+        assert_eq!(point_data.len(), 4);
+        assert_eq!(point_data.all_headers, vec!["x", "y", "label", "late"]);
+        assert_eq!(point_data.data["x"], vec![1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(point_data.data["y"][3], 5.5);
+        assert_eq!(categories(&point_data, "label"), vec!["a", "b", "c", "d"]);
+        assert_eq!(categories(&point_data, "late"), vec!["1", "2", "3", "x"]);
+    }
+
+    #[test]
+    fn test_chunks() {
+        let mut loader = CsvLoader::new(Cursor::new(CSV))
+            .unwrap()
+            .with_size(CSV.len() as u64)
+            .with_sample_size(2);
+        assert!(loader.progress().unwrap() < 0.5);
+        assert!(!loader.read_chunk(1).unwrap());
+        assert_eq!(loader.len(), 2);
+        let progress = loader.progress().unwrap();
+        assert!(progress > 0.0 && progress < 1.0);
+        assert!(!loader.read_chunk(1).unwrap());
+        assert!(loader.read_chunk(10).unwrap());
+        assert_eq!(loader.progress(), Some(1.0));
+        let point_data = loader.finish();
+        assert_eq!(point_data.len(), 4);
+        // late type change after the sample
+        assert_eq!(categories(&point_data, "late"), vec!["1", "2", "3", "x"]);
+    }
+
+    #[test]
+    fn test_cancelled() {
+        let mut loader = CsvLoader::new(Cursor::new(CSV))
+            .unwrap()
+            .with_sample_size(1);
+        loader.read_chunk(1).unwrap();
+        let point_data = loader.finish();
+        assert_eq!(point_data.len(), 1);
+        assert_eq!(point_data.data["late"], vec![1.0]);
+    }
+
+    #[test]
+    fn test_empty() {
+        let mut loader = CsvLoader::new(Cursor::new("x,y\n")).unwrap();
+        assert!(loader.read_chunk(10).unwrap());
+        let point_data = loader.finish();
+        assert_eq!(point_data.len(), 0);
+        assert!(point_data.data.contains_key("x"));
+    }
+
+    #[test]
+    fn test_column_types() {
+        let csv = "i,f,b,c\n1,1.5,true,a\n2,2,False,b\n,3,,a\n";
+        let mut loader = CsvLoader::new(Cursor::new(csv)).unwrap();
+        loader.read_all().unwrap();
+        let point_data = loader.finish();
+        assert_eq!(point_data.column_type("i"), Some(ColumnType::Integer));
+        assert_eq!(point_data.column_type("f"), Some(ColumnType::Float));
+        assert_eq!(point_data.column_type("b"), Some(ColumnType::Boolean));
+        assert_eq!(point_data.column_type("c"), Some(ColumnType::Categorical));
+        assert_eq!(point_data.data["b"][..2], [1.0, 0.0]);
+        assert_eq!(point_data.get("b", 1), "false");
+        assert_eq!(point_data.categorical["c"].categories, vec!["a", "b"]);
+        assert_eq!(point_data.categorical["c"].codes, vec![0, 1, 0]);
+
+        let csv = (0..=MAX_CATEGORIES)
+            .map(|i| format!("\nid{}", i))
+            .collect::<String>();
+        let csv = format!("id{}", csv);
+        let mut loader = CsvLoader::new(Cursor::new(csv)).unwrap();
+        loader.read_all().unwrap();
+        let point_data = loader.finish();
+        assert_eq!(point_data.column_type("id"), Some(ColumnType::Text));
+        assert_eq!(
+            point_data.aux["id"][MAX_CATEGORIES],
+            format!("id{}", MAX_CATEGORIES)
+        );
+    }
+
+    #[test]
+    fn test_datetimes() {
+        let csv = "time,day,late\n2023-05-17T10:00:00Z,2023-05-17,2023-05-17\n\
+                   2023-05-17 11:30:00,,2023-05-18\n2023-05-17T12:00:00+01:00,2023-05-19,soon\n";
+        let mut loader = CsvLoader::new(Cursor::new(csv))
+            .unwrap()
+            .with_sample_size(2);
+        loader.read_all().unwrap();
+        let point_data = loader.finish();
+        assert_eq!(point_data.column_type("time"), Some(ColumnType::DateTime));
+        assert_eq!(
+            point_data.data["time"][1] - point_data.data["time"][0],
+            5400.0
+        );
+        assert_eq!(
+            point_data.data["time"][2] - point_data.data["time"][0],
+            3600.0
+        );
+        assert_eq!(point_data.get("day", 0), "2023-05-17");
+        assert!(point_data.data["day"][1].is_nan());
+        assert_eq!(
+            categories(&point_data, "late"),
+            vec!["2023-05-17", "2023-05-18", "soon"]
+        );
+
+        let options = CsvOptions {
+            parse_datetimes: false,
+            ..CsvOptions::new()
+        };
+        let mut loader = CsvLoader::with_options(Cursor::new(csv), &options).unwrap();
+        loader.read_all().unwrap();
+        assert_eq!(
+            loader.finish().column_type("day"),
+            Some(ColumnType::Categorical)
+        );
+    }
+
+    #[test]
+    fn test_missing_values() {
+        let csv = "x,y,label\n1,,a\nNA,2,\n3,4,c\n5,N/A,NA\n6,7,x\n";
+        let mut loader = CsvLoader::new(Cursor::new(csv))
+            .unwrap()
+            .with_sample_size(2);
+        loader.read_all().unwrap();
+        let point_data = loader.finish();
+        assert!(point_data.data["x"][1].is_nan());
+        assert_eq!(point_data.data["x"][4], 6.0);
+        assert!(point_data.data["y"][0].is_nan());
+        assert!(point_data.data["y"][3].is_nan());
+        assert_eq!(
+            categories(&point_data, "label"),
+            vec!["a", "", "c", "NA", "x"]
+        );
+        assert_eq!(point_data.missing_count("y"), 2);
+
+        let options = CsvOptions {
+            null_values: vec!["-".to_owned()],
+            ..CsvOptions::new()
+        };
+        let mut loader = CsvLoader::with_options(Cursor::new("x,y\n1,-\n2,\n"), &options).unwrap();
+        loader.read_all().unwrap();
+        let point_data = loader.finish();
+        assert_eq!(categories(&point_data, "y"), vec!["-", ""]);
+    }
+
+    #[test]
+    fn test_late_text_conversion() {
+        let csv = "code,flag,time,x\n007,yes,2023-05-17T10:00:00.123456+02:00,1.50\n\
+                   NA,no,2023-05-18,1e3\n012,Y,NA,\n\
+                   A1,maybe,later,text\n";
+        let mut loader = CsvLoader::new(Cursor::new(csv))
+            .unwrap()
+            .with_sample_size(3);
+        loader.read_all().unwrap();
+        let point_data = loader.finish();
+        assert_eq!(
+            categories(&point_data, "code"),
+            vec!["007", "NA", "012", "A1"]
+        );
+        assert_eq!(
+            categories(&point_data, "flag"),
+            vec!["yes", "no", "Y", "maybe"]
+        );
+        assert_eq!(
+            categories(&point_data, "time"),
+            vec![
+                "2023-05-17T10:00:00.123456+02:00",
+                "2023-05-18",
+                "NA",
+                "later"
+            ]
+        );
+        assert_eq!(
+            categories(&point_data, "x"),
+            vec!["1.50", "1e3", "", "text"]
+        );
+
+        // without a header, after skipped rows and comments, with a decimal comma
+        let csv = "skipped\n# comment\n1,50;a\n# comment\n007;b\nx;c\n";
+        let options = CsvOptions {
+            delimiter: ';',
+            comment: Some('#'),
+            has_headers: false,
+            skip_rows: 1,
+            decimal_comma: true,
+            ..CsvOptions::new()
+        };
+        let mut loader = CsvLoader::with_options(Cursor::new(csv), &options)
+            .unwrap()
+            .with_sample_size(1);
+        assert!(!loader.read_chunk(1).unwrap());
+        loader.read_all().unwrap();
+        let point_data = loader.finish();
+        assert_eq!(categories(&point_data, "column1"), vec!["1,50", "007", "x"]);
+        assert_eq!(categories(&point_data, "column2"), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_options() {
+        let csv = "Exported data\n# comment\nx;y;label\n1,5;2;'a;b'\n# comment\n3;4,25;c\n";
+        let options = CsvOptions {
+            delimiter: ';',
+            quote: '\'',
+            comment: Some('#'),
+            skip_rows: 1,
+            decimal_comma: true,
+            ..CsvOptions::new()
+        };
+        let mut loader = CsvLoader::with_options(Cursor::new(csv), &options).unwrap();
+        loader.read_all().unwrap();
+        let point_data = loader.finish();
+        assert_eq!(point_data.all_headers, vec!["x", "y", "label"]);
+        assert_eq!(point_data.data["x"], vec![1.5, 3.0]);
+        assert_eq!(point_data.data["y"], vec![2.0, 4.25]);
+        assert_eq!(categories(&point_data, "label"), vec!["a;b", "c"]);
+
+        let options = CsvOptions {
+            delimiter: '\t',
+            has_headers: false,
+            encoding: Encoding::Latin1,
+            ..CsvOptions::new()
+        };
+        let mut loader =
+            CsvLoader::with_options(Cursor::new(&b"1\tZ\xfcrich\n2\tK\xf6ln\n"[..]), &options)
+                .unwrap();
+        loader.read_all().unwrap();
+        let point_data = loader.finish();
+        assert_eq!(point_data.all_headers, vec!["column1", "column2"]);
+        assert_eq!(point_data.data["column1"], vec![1.0, 2.0]);
+        assert_eq!(categories(&point_data, "column2"), vec!["Zürich", "Köln"]);
+
+        let mut loader = CsvLoader::new(Cursor::new(&b"x\n\xfc\n"[..])).unwrap();
+        assert!(loader.read_all().is_err());
+        let options = CsvOptions {
+            decimal_comma: true,
+            ..CsvOptions::new()
+        };
+        assert!(CsvLoader::with_options(Cursor::new("x\n1,5\n"), &options).is_err());
+        assert_eq!(CsvOptions::for_path("data.tsv").delimiter, '\t');
+    }
+}
@@ -0,0 +1,86 @@
+//! Timestamps are represented as seconds since the Unix epoch (UTC) stored in f64,
+//! so that datetime columns can live in `PointData::data` and be plotted like any other number.
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Timelike};
+
+const DATETIME_FORMATS: [&str; 4] = [
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%dT%H:%M",
+];
+
+fn to_seconds(seconds: i64, nanoseconds: u32) -> f64 {
+    seconds as f64 + nanoseconds as f64 * 1e-9
+}
+
+/// Parse an ISO-8601 date or datetime (with or without a timezone) into seconds since the epoch.
+/// Datetimes without a timezone are taken as UTC.
+pub fn parse_timestamp(text: &str) -> Option<f64> {
+    let text = text.trim();
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(text) {
+        return Some(to_seconds(
+            datetime.timestamp(),
+            datetime.timestamp_subsec_nanos(),
+        ));
+    }
+    for format in DATETIME_FORMATS.iter() {
+        if let Ok(datetime) = NaiveDateTime::parse_from_str(text, format) {
+            let datetime = datetime.and_utc();
+            return Some(to_seconds(
+                datetime.timestamp(),
+                datetime.timestamp_subsec_nanos(),
+            ));
+        }
+    }
+    NaiveDate::parse_from_str(text, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|datetime| datetime.and_utc().timestamp() as f64)
+}
+
+/// Format seconds since the epoch; the time is omitted at midnight.
+/// Missing values (NaN) are formatted as an empty string.
+pub fn format_timestamp(seconds: f64) -> String {
+    if !seconds.is_finite() {
+        return String::new();
+    }
+    let whole = seconds.floor();
+    let nanoseconds = (((seconds - whole) * 1e9).round() as u32).min(999_999_999);
+    match DateTime::from_timestamp(whole as i64, nanoseconds) {
+        Some(datetime) => {
+            let format = if nanoseconds >= 1_000_000 {
+                "%Y-%m-%d %H:%M:%S%.3f"
+            } else if datetime.num_seconds_from_midnight() != 0 {
+                "%Y-%m-%d %H:%M:%S"
+            } else {
+                "%Y-%m-%d"
+            };
+            datetime.format(format).to_string()
+        }
+        None => format!("{}", seconds),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_timestamps() {
+        assert_eq!(parse_timestamp("1970-01-02"), Some(86400.0));
+        assert_eq!(parse_timestamp("1970-01-01T01:00:00"), Some(3600.0));
+        assert_eq!(parse_timestamp("1970-01-01 01:00"), Some(3600.0));
+        assert_eq!(parse_timestamp("1970-01-01T02:00:00+01:00"), Some(3600.0));
+        assert_eq!(parse_timestamp("1970-01-01 00:00:01.5"), Some(1.5));
+        assert_eq!(parse_timestamp("yesterday"), None);
+        assert_eq!(parse_timestamp("12.5"), None);
+
+        assert_eq!(format_timestamp(86400.0), "1970-01-02");
+        assert_eq!(format_timestamp(3600.0), "1970-01-01 01:00:00");
+        assert_eq!(format_timestamp(1.5), "1970-01-01 00:00:01.500");
+        assert_eq!(format_timestamp(f64::NAN), "");
+        let t = parse_timestamp("2023-05-17 13:45:10").unwrap();
+        assert_eq!(format_timestamp(t), "2023-05-17 13:45:10");
+    }
+}
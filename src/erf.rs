@@ -746,6 +746,17 @@ fn erf_inv_impl(p: f64, q: f64, s: f64) -> f64 {
 mod tests {
     use std::f64;
 
+    // borrowed from statrs (prec::almost_eq)
+    macro_rules! assert_almost_eq {
+        ($a:expr, $b:expr, $prec:expr) => {
+            let (a, b): (f64, f64) = ($a, $b);
+            let equal = if a.is_infinite() && b.is_infinite() { a == b } else { (a - b).abs() < $prec };
+            if !equal {
+                panic!("assertion failed: `abs(left - right) < {:e}`, (left: `{}`, right: `{}`)", $prec, a, b);
+            }
+        };
+    }
+
     #[test]
     fn test_erf() {
         assert!(super::erf(f64::NAN).is_nan());
@@ -1,7 +1,947 @@
-//! This code is borrowed from [statrs](https://github.com/statrs-dev/statrs) in order to allow wasm building
+//! Evaluation of expressions: the expression language of derived columns
+//! (see `Expression` and `DerivedColumn`) and polynomials.
+//!
+//! The polynomial code is borrowed from [statrs](https://github.com/statrs-dev/statrs) in order to allow wasm building
 //! Provides functions that don't have a numerical solution and must
 //! be solved computationally (e.g. evaluation of a polynomial)
 
+use crate::pointdata::{ColumnType, PointData};
+use anyhow::*;
+use std::collections::HashSet;
+use std::str::FromStr;
+
+/// Names of the functions of the expression language.
+pub const FUNCTIONS: [&str; 25] = [
+    "abs", "sqrt", "exp", "ln", "log", "log10", "log2", "sin", "cos", "tan", "asin", "acos",
+    "atan", "atan2", "floor", "ceil", "round", "sign", "pow", "min", "max", "if", "missing", "len",
+    "between",
+];
+/// Words which can not be used as column names without backticks.
+const KEYWORDS: [&str; 6] = ["and", "or", "not", "in", "true", "false"];
+
+/// Binary operators ordered by the precedence, see `BinaryOperator::precedence`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOperator {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    Power,
+}
+
+impl BinaryOperator {
+    pub fn symbol(&self) -> &str {
+        match self {
+            BinaryOperator::Or => "or",
+            BinaryOperator::And => "and",
+            BinaryOperator::Equal => "==",
+            BinaryOperator::NotEqual => "!=",
+            BinaryOperator::Less => "<",
+            BinaryOperator::LessOrEqual => "<=",
+            BinaryOperator::Greater => ">",
+            BinaryOperator::GreaterOrEqual => ">=",
+            BinaryOperator::Add => "+",
+            BinaryOperator::Subtract => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/",
+            BinaryOperator::Remainder => "%",
+            BinaryOperator::Power => "^",
+        }
+    }
+    /// Operators with a higher precedence bind stronger.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            BinaryOperator::Or => 1,
+            BinaryOperator::And => 2,
+            BinaryOperator::Equal
+            | BinaryOperator::NotEqual
+            | BinaryOperator::Less
+            | BinaryOperator::LessOrEqual
+            | BinaryOperator::Greater
+            | BinaryOperator::GreaterOrEqual => 4,
+            BinaryOperator::Add | BinaryOperator::Subtract => 5,
+            BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Remainder => 6,
+            BinaryOperator::Power => 8,
+        }
+    }
+    pub fn is_comparison(&self) -> bool {
+        self.precedence() == 4
+    }
+    pub fn is_logical(&self) -> bool {
+        matches!(self, BinaryOperator::And | BinaryOperator::Or)
+    }
+    fn apply(&self, a: f64, b: f64) -> f64 {
+        let flag = |x: bool| if x { 1.0 } else { 0.0 };
+        match self {
+            BinaryOperator::Or => flag(truth(a) || truth(b)),
+            BinaryOperator::And => flag(truth(a) && truth(b)),
+            BinaryOperator::Equal => flag(a == b),
+            BinaryOperator::NotEqual => flag(!a.is_nan() && !b.is_nan() && a != b),
+            BinaryOperator::Less => flag(a < b),
+            BinaryOperator::LessOrEqual => flag(a <= b),
+            BinaryOperator::Greater => flag(a > b),
+            BinaryOperator::GreaterOrEqual => flag(a >= b),
+            BinaryOperator::Add => a + b,
+            BinaryOperator::Subtract => a - b,
+            BinaryOperator::Multiply => a * b,
+            BinaryOperator::Divide => a / b,
+            BinaryOperator::Remainder => a % b,
+            BinaryOperator::Power => a.powf(b),
+        }
+    }
+    fn compare_texts(&self, a: &str, b: &str) -> f64 {
+        let result = match self {
+            BinaryOperator::Equal => a == b,
+            BinaryOperator::NotEqual => !a.is_empty() && !b.is_empty() && a != b,
+            BinaryOperator::Less => a < b,
+            BinaryOperator::LessOrEqual => a <= b,
+            BinaryOperator::Greater => a > b,
+            BinaryOperator::GreaterOrEqual => a >= b,
+            _ => false,
+        };
+        if result {
+            1.0
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Truth value of a number, missing values (NaN) are false.
+fn truth(x: f64) -> bool {
+    x != 0.0 && !x.is_nan()
+}
+
+/// Values of an expression for all the rows.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Values {
+    Numbers(Vec<f64>),
+    Texts(Vec<String>),
+}
+
+impl Values {
+    pub fn len(&self) -> usize {
+        match self {
+            Values::Numbers(values) => values.len(),
+            Values::Texts(values) => values.len(),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    fn numbers(self, context: &str) -> Result<Vec<f64>> {
+        match self {
+            Values::Numbers(values) => Ok(values),
+            Values::Texts(_) => bail!("{} expects a number, not a text", context),
+        }
+    }
+}
+
+/// Expression of the columns of a row, e.g. `log(price / area)`
+/// or `if(country == "DE", 1, 0)`.
+///
+/// Numbers, "text" (or 'text'), column names (`quoted column` if not a plain identifier),
+/// arithmetic (`+ - * / % ^`), comparisons (`== != < <= > >=`, also of texts),
+/// `and`, `or`, `not`, `in [...]`, `not in [...]` and the `FUNCTIONS` are supported.
+/// Booleans are numbers 0 and 1, missing values are NaN (or empty texts) and count as false;
+/// `!=` is false for them.
+/// A text compared with a numeric column is parsed as a value of the column, e.g. a date.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Number(f64),
+    Text(String),
+    Column(String),
+    Negative(Box<Expression>),
+    Not(Box<Expression>),
+    Binary(BinaryOperator, Box<Expression>, Box<Expression>),
+    /// Value equal to one of the listed values.
+    In(Box<Expression>, Vec<Expression>),
+    Function(String, Vec<Expression>),
+}
+
+/// Column name usable in an expression, in backticks if it is not a plain identifier.
+pub fn quote_column(column: &str) -> String {
+    let mut chars = column.chars();
+    let identifier = matches!(chars.next(), Some(c) if c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '.')
+        && !KEYWORDS.iter().any(|x| column.eq_ignore_ascii_case(x));
+    if identifier {
+        column.to_owned()
+    } else {
+        format!("`{}`", column.replace('\\', "\\\\").replace('`', "\\`"))
+    }
+}
+
+/// Text literal of an expression.
+pub fn quote_text(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Text(String),
+    Identifier(String),
+    Column(String),
+    Symbol(&'static str),
+    End,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Number(x) => format!("number {}", x),
+            Token::Text(x) => format!("text \"{}\"", x),
+            Token::Identifier(x) => format!("'{}'", x),
+            Token::Column(x) => format!("column `{}`", x),
+            Token::Symbol(x) => format!("'{}'", x),
+            Token::End => "end of the expression".to_owned(),
+        }
+    }
+}
+
+const SYMBOLS: [&str; 21] = [
+    "==", "!=", "<>", "<=", ">=", "&&", "||", "(", ")", "[", "]", ",", "+", "-", "*", "/", "%",
+    "^", "<", ">", "=",
+];
+
+/// Split an expression into tokens with their positions (in characters).
+fn tokenize(text: &str) -> Result<Vec<(Token, usize)>> {
+    let chars = text.chars().collect::<Vec<_>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c.is_ascii_digit()
+            || (c == '.' && matches!(chars.get(i + 1), Some(x) if x.is_ascii_digit()))
+        {
+            while i < chars.len()
+                && (chars[i].is_ascii_digit()
+                    || chars[i] == '.'
+                    || ((chars[i] == 'e' || chars[i] == 'E')
+                        && matches!(chars.get(i + 1), Some(x) if x.is_ascii_digit() || *x == '-' || *x == '+')))
+            {
+                if chars[i] == 'e' || chars[i] == 'E' {
+                    i += 1;
+                }
+                i += 1;
+            }
+            let number = chars[start..i].iter().collect::<String>();
+            let value = number
+                .parse::<f64>()
+                .map_err(|_| anyhow!("Invalid number '{}' at position {}", number, start + 1))?;
+            tokens.push((Token::Number(value), start));
+        } else if c.is_alphabetic() || c == '_' {
+            while i < chars.len()
+                && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.')
+            {
+                i += 1;
+            }
+            tokens.push((Token::Identifier(chars[start..i].iter().collect()), start));
+        } else if c == '"' || c == '\'' || c == '`' {
+            let mut value = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => bail!(
+                        "Missing closing {} of the text at position {}",
+                        c,
+                        start + 1
+                    ),
+                    Some('\\') if i + 1 < chars.len() => {
+                        value.push(chars[i + 1]);
+                        i += 2;
+                    }
+                    Some(&x) if x == c => {
+                        i += 1;
+                        break;
+                    }
+                    Some(&x) => {
+                        value.push(x);
+                        i += 1;
+                    }
+                }
+            }
+            if c == '`' {
+                tokens.push((Token::Column(value), start));
+            } else {
+                tokens.push((Token::Text(value), start));
+            }
+        } else if let Some(symbol) = SYMBOLS.iter().find(|symbol| {
+            let symbol = symbol.chars().collect::<Vec<_>>();
+            chars[i..].starts_with(&symbol)
+        }) {
+            i += symbol.chars().count();
+            tokens.push((Token::Symbol(symbol), start));
+        } else if c == '!' {
+            i += 1;
+            tokens.push((Token::Identifier("not".to_owned()), start));
+        } else {
+            bail!("Unexpected character '{}' at position {}", c, start + 1);
+        }
+    }
+    tokens.push((Token::End, chars.len()));
+    Ok(tokens)
+}
+
+/// Recursive descent parser of the expressions.
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.position].0
+    }
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.position].0.clone();
+        if token != Token::End {
+            self.position += 1;
+        }
+        token
+    }
+    fn unexpected(&self) -> Error {
+        let (token, position) = &self.tokens[self.position];
+        anyhow!(
+            "Unexpected {} at position {}",
+            token.describe(),
+            position + 1
+        )
+    }
+    fn expect(&mut self, symbol: &str) -> Result<()> {
+        if *self.peek() == Token::Symbol(Self::symbol(symbol)) {
+            self.next();
+            Ok(())
+        } else {
+            let (token, position) = &self.tokens[self.position];
+            bail!(
+                "Expected '{}' at position {}, found {}",
+                symbol,
+                position + 1,
+                token.describe()
+            )
+        }
+    }
+    fn symbol(symbol: &str) -> &'static str {
+        SYMBOLS.iter().find(|x| **x == symbol).unwrap()
+    }
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Token::Identifier(x) if x.eq_ignore_ascii_case(keyword))
+    }
+    /// Binary operator of the next token.
+    fn operator(&self) -> Option<BinaryOperator> {
+        match self.peek() {
+            Token::Symbol(symbol) => match *symbol {
+                "||" => Some(BinaryOperator::Or),
+                "&&" => Some(BinaryOperator::And),
+                "==" | "=" => Some(BinaryOperator::Equal),
+                "!=" | "<>" => Some(BinaryOperator::NotEqual),
+                "<" => Some(BinaryOperator::Less),
+                "<=" => Some(BinaryOperator::LessOrEqual),
+                ">" => Some(BinaryOperator::Greater),
+                ">=" => Some(BinaryOperator::GreaterOrEqual),
+                "+" => Some(BinaryOperator::Add),
+                "-" => Some(BinaryOperator::Subtract),
+                "*" => Some(BinaryOperator::Multiply),
+                "/" => Some(BinaryOperator::Divide),
+                "%" => Some(BinaryOperator::Remainder),
+                "^" => Some(BinaryOperator::Power),
+                _ => None,
+            },
+            Token::Identifier(x) if x.eq_ignore_ascii_case("or") => Some(BinaryOperator::Or),
+            Token::Identifier(x) if x.eq_ignore_ascii_case("and") => Some(BinaryOperator::And),
+            _ => None,
+        }
+    }
+
+    /// `in` or `not in` follows.
+    fn is_membership(&self) -> bool {
+        self.is_keyword("in")
+            || (self.is_keyword("not")
+                && matches!(&self.tokens[self.position + 1].0, Token::Identifier(x) if x.eq_ignore_ascii_case("in")))
+    }
+
+    /// Membership test `x in [a, b, ...]` or `x not in [a, b, ...]`.
+    fn membership(&mut self, left: Expression) -> Result<Expression> {
+        let negated = self.is_keyword("not");
+        if negated {
+            self.next();
+        }
+        self.next();
+        self.expect("[")?;
+        let mut values = Vec::new();
+        if *self.peek() != Token::Symbol("]") {
+            values.push(self.binary(5)?);
+            while *self.peek() == Token::Symbol(",") {
+                self.next();
+                values.push(self.binary(5)?);
+            }
+        }
+        self.expect("]")?;
+        let membership = Expression::In(Box::new(left), values);
+        Ok(if negated {
+            Expression::Not(Box::new(membership))
+        } else {
+            membership
+        })
+    }
+
+    /// Expression with binary operators of at least the given precedence.
+    fn binary(&mut self, precedence: u8) -> Result<Expression> {
+        let mut left = self.unary(precedence)?;
+        loop {
+            if precedence <= 4 && self.is_membership() {
+                left = self.membership(left)?;
+                continue;
+            }
+            let operator = match self.operator() {
+                Some(operator) if operator.precedence() >= precedence => operator,
+                _ => break,
+            };
+            self.next();
+            let right = if operator == BinaryOperator::Power {
+                // right associative
+                self.binary(operator.precedence())?
+            } else {
+                self.binary(operator.precedence() + 1)?
+            };
+            left = Expression::Binary(operator, Box::new(left), Box::new(right));
+            if operator.is_comparison() && matches!(self.operator(), Some(x) if x.is_comparison()) {
+                bail!(
+                    "Comparisons can not be chained, use 'and' at position {}",
+                    self.tokens[self.position].1 + 1
+                );
+            }
+        }
+        Ok(left)
+    }
+
+    /// `not` binds weaker than comparisons, unary minus stronger than multiplication.
+    fn unary(&mut self, precedence: u8) -> Result<Expression> {
+        if self.is_keyword("not") && precedence <= 3 {
+            self.next();
+            Ok(Expression::Not(Box::new(self.binary(3)?)))
+        } else if *self.peek() == Token::Symbol("-") {
+            self.next();
+            Ok(Expression::Negative(Box::new(self.binary(7)?)))
+        } else {
+            self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expression> {
+        match self.next() {
+            Token::Number(x) => Ok(Expression::Number(x)),
+            Token::Text(x) => Ok(Expression::Text(x)),
+            Token::Column(x) => Ok(Expression::Column(x)),
+            Token::Identifier(x)
+                if ["and", "or", "not", "in"]
+                    .iter()
+                    .any(|keyword| x.eq_ignore_ascii_case(keyword)) =>
+            {
+                self.position -= 1;
+                Err(self.unexpected())
+            }
+            Token::Identifier(x) => {
+                if *self.peek() == Token::Symbol("(") {
+                    self.next();
+                    let mut arguments = Vec::new();
+                    if *self.peek() != Token::Symbol(")") {
+                        arguments.push(self.binary(0)?);
+                        while *self.peek() == Token::Symbol(",") {
+                            self.next();
+                            arguments.push(self.binary(0)?);
+                        }
+                    }
+                    self.expect(")")?;
+                    Ok(Expression::Function(x.to_lowercase(), arguments))
+                } else if x.eq_ignore_ascii_case("true") {
+                    Ok(Expression::Number(1.0))
+                } else if x.eq_ignore_ascii_case("false") {
+                    Ok(Expression::Number(0.0))
+                } else {
+                    Ok(Expression::Column(x))
+                }
+            }
+            Token::Symbol("(") => {
+                let expression = self.binary(0)?;
+                self.expect(")")?;
+                Ok(expression)
+            }
+            _ => {
+                self.position -= 1;
+                Err(self.unexpected())
+            }
+        }
+    }
+}
+
+impl FromStr for Expression {
+    type Err = anyhow::Error;
+    fn from_str(text: &str) -> Result<Expression> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            position: 0,
+        };
+        let expression = parser.binary(0)?;
+        if *parser.peek() != Token::End {
+            return Err(parser.unexpected());
+        }
+        Ok(expression)
+    }
+}
+
+impl Expression {
+    pub fn parse(text: &str) -> Result<Expression> {
+        Expression::from_str(text)
+    }
+
+    /// The values are booleans (0 or 1).
+    pub fn is_boolean(&self) -> bool {
+        match self {
+            Expression::Not(_) | Expression::In(_, _) => true,
+            Expression::Binary(operator, _, _) => operator.is_comparison() || operator.is_logical(),
+            Expression::Function(name, arguments) => {
+                name == "missing"
+                    || name == "between"
+                    || (name == "if"
+                        && arguments.len() == 3
+                        && arguments[1..].iter().all(|x| x.is_boolean()))
+            }
+            _ => false,
+        }
+    }
+
+    /// Columns used in the expression.
+    pub fn columns(&self) -> Vec<&str> {
+        let mut columns = Vec::new();
+        self.collect_columns(&mut columns);
+        columns
+    }
+    fn collect_columns<'a>(&'a self, columns: &mut Vec<&'a str>) {
+        match self {
+            Expression::Column(column) if !columns.contains(&column.as_str()) => {
+                columns.push(column);
+            }
+            Expression::Negative(x) | Expression::Not(x) => x.collect_columns(columns),
+            Expression::Binary(_, a, b) => {
+                a.collect_columns(columns);
+                b.collect_columns(columns);
+            }
+            Expression::In(x, values) => {
+                x.collect_columns(columns);
+                for value in values.iter() {
+                    value.collect_columns(columns);
+                }
+            }
+            Expression::Function(_, arguments) => {
+                for argument in arguments.iter() {
+                    argument.collect_columns(columns);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Evaluate the expression for all the rows of the data.
+    pub fn evaluate(&self, data: &PointData) -> Result<Values> {
+        let n = data.len();
+        match self {
+            Expression::Number(x) => Ok(Values::Numbers(vec![*x; n])),
+            Expression::Text(x) => Ok(Values::Texts(vec![x.clone(); n])),
+            Expression::Column(column) => {
+                if let Some(values) = data.data.get(column) {
+                    Ok(Values::Numbers(values.clone()))
+                } else if let Some(categorical) = data.categorical.get(column) {
+                    Ok(Values::Texts(
+                        categorical.iter().map(|x| x.to_owned()).collect(),
+                    ))
+                } else if let Some(values) = data.aux.get(column) {
+                    Ok(Values::Texts(values.clone()))
+                } else {
+                    bail!(
+                        "Column '{}' not found; available columns: {}",
+                        column,
+                        data.all_headers.join(", ")
+                    )
+                }
+            }
+            Expression::Negative(x) => Ok(Values::Numbers(
+                x.evaluate(data)?
+                    .numbers("Minus")?
+                    .into_iter()
+                    .map(|x| -x)
+                    .collect(),
+            )),
+            Expression::Not(x) => Ok(Values::Numbers(
+                x.evaluate(data)?
+                    .numbers("'not'")?
+                    .into_iter()
+                    .map(|x| if truth(x) { 0.0 } else { 1.0 })
+                    .collect(),
+            )),
+            Expression::Binary(operator, a, b) => Self::evaluate_binary(*operator, a, b, data),
+            Expression::In(x, values) => Self::evaluate_membership(x, values, data),
+            Expression::Function(name, arguments) => Self::evaluate_function(name, arguments, data),
+        }
+    }
+
+    /// Text compared with a numeric column parsed as a value of the column (e.g. a date).
+    fn parse_text(&self, other: &Expression, data: &PointData) -> Result<Option<Expression>> {
+        if let (Expression::Text(text), Expression::Column(column)) = (self, other) {
+            if data.data.contains_key(column) {
+                return data
+                    .parse_value(column, text)
+                    .map(|x| Some(Expression::Number(x)))
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "Can not compare '{}' with the numeric column '{}'",
+                            text,
+                            column
+                        )
+                    });
+            }
+        }
+        Ok(None)
+    }
+
+    /// Rows of a categorical column equal to one of the texts,
+    /// compared through the dictionary codes instead of the strings.
+    fn categorical_membership(&self, texts: &[&str], data: &PointData) -> Option<Vec<bool>> {
+        let categorical = match self {
+            Expression::Column(column) => data.categorical.get(column)?,
+            _ => return None,
+        };
+        let mut accepted = vec![false; categorical.categories.len()];
+        for code in texts.iter().filter_map(|x| categorical.code(x)) {
+            accepted[code as usize] = true;
+        }
+        Some(
+            categorical
+                .codes
+                .iter()
+                .map(|&code| accepted[code as usize])
+                .collect(),
+        )
+    }
+
+    /// `x in [...]`: the left side is evaluated once and looked up in the sets of the constant
+    /// values, the other values are compared row by row.
+    fn evaluate_membership(
+        x: &Expression,
+        values: &[Expression],
+        data: &PointData,
+    ) -> Result<Values> {
+        // NaN is equal to nothing, -0 equal to 0
+        let key = |x: f64| if x == 0.0 { 0 } else { x.to_bits() };
+        let mut numbers = HashSet::new();
+        let mut texts = HashSet::new();
+        let mut others = Vec::new();
+        for value in values.iter() {
+            match (value.parse_text(x, data)?, value) {
+                (Some(Expression::Number(v)), _) | (None, &Expression::Number(v)) => {
+                    if !v.is_nan() {
+                        numbers.insert(key(v));
+                    }
+                }
+                (None, Expression::Text(v)) => {
+                    texts.insert(v.as_str());
+                }
+                _ => others.push(value),
+            }
+        }
+        let to_number = |x: bool| if x { 1.0 } else { 0.0 };
+        if numbers.is_empty() && others.is_empty() {
+            let texts = texts.iter().copied().collect::<Vec<_>>();
+            if let Some(rows) = x.categorical_membership(&texts, data) {
+                return Ok(Values::Numbers(rows.into_iter().map(to_number).collect()));
+            }
+        }
+        let left = x.evaluate(data)?;
+        let mut result = match &left {
+            Values::Numbers(a) if texts.is_empty() => a
+                .iter()
+                .map(|&a| to_number(numbers.contains(&key(a))))
+                .collect::<Vec<_>>(),
+            Values::Texts(a) if numbers.is_empty() => a
+                .iter()
+                .map(|a| to_number(texts.contains(a.as_str())))
+                .collect(),
+            _ => bail!("Operator 'in' can not be applied to texts and numbers"),
+        };
+        for value in others {
+            let equal = Self::apply_binary(BinaryOperator::Equal, &left, &value.evaluate(data)?)?
+                .numbers("'in'")?;
+            for (r, e) in result.iter_mut().zip(equal) {
+                if truth(e) {
+                    *r = 1.0;
+                }
+            }
+        }
+        Ok(Values::Numbers(result))
+    }
+
+    fn evaluate_binary(
+        operator: BinaryOperator,
+        a: &Expression,
+        b: &Expression,
+        data: &PointData,
+    ) -> Result<Values> {
+        if operator.is_comparison() {
+            if let Some(a) = a.parse_text(b, data)? {
+                return Self::evaluate_binary(operator, &a, b, data);
+            }
+            if let Some(b) = b.parse_text(a, data)? {
+                return Self::evaluate_binary(operator, a, &b, data);
+            }
+        }
+        if operator == BinaryOperator::Equal || operator == BinaryOperator::NotEqual {
+            if let (Expression::Text(text), column) | (column, Expression::Text(text)) = (a, b) {
+                let equal = operator == BinaryOperator::Equal;
+                // missing (empty) values are not unequal to any value
+                let texts = if equal {
+                    vec![text.as_str()]
+                } else {
+                    vec![text, ""]
+                };
+                if let Some(rows) = column.categorical_membership(&texts, data) {
+                    let flag = |x: bool| if x { 1.0 } else { 0.0 };
+                    return Ok(Values::Numbers(
+                        rows.into_iter()
+                            .map(|x| flag(if equal { x } else { !x && !text.is_empty() }))
+                            .collect(),
+                    ));
+                }
+            }
+        }
+        Self::apply_binary(operator, &a.evaluate(data)?, &b.evaluate(data)?)
+    }
+
+    fn apply_binary(operator: BinaryOperator, a: &Values, b: &Values) -> Result<Values> {
+        match (a, b) {
+            (Values::Numbers(a), Values::Numbers(b)) => Ok(Values::Numbers(
+                a.iter()
+                    .zip(b.iter())
+                    .map(|(&a, &b)| operator.apply(a, b))
+                    .collect(),
+            )),
+            (Values::Texts(a), Values::Texts(b)) if operator.is_comparison() => {
+                Ok(Values::Numbers(
+                    a.iter()
+                        .zip(b.iter())
+                        .map(|(a, b)| operator.compare_texts(a, b))
+                        .collect(),
+                ))
+            }
+            _ => bail!(
+                "Operator '{}' can not be applied to texts{}",
+                operator.symbol(),
+                if operator.is_comparison() {
+                    " and numbers"
+                } else {
+                    ""
+                }
+            ),
+        }
+    }
+
+    fn evaluate_function(name: &str, arguments: &[Expression], data: &PointData) -> Result<Values> {
+        let check_count = |count: usize| -> Result<()> {
+            if arguments.len() != count {
+                bail!(
+                    "Function '{}' expects {} argument{}, got {}",
+                    name,
+                    count,
+                    if count == 1 { "" } else { "s" },
+                    arguments.len()
+                );
+            }
+            Ok(())
+        };
+        let numbers = |i: usize| -> Result<Vec<f64>> {
+            arguments[i]
+                .evaluate(data)?
+                .numbers(&format!("Function '{}'", name))
+        };
+        let unary = |f: fn(f64) -> f64| -> Result<Values> {
+            check_count(1)?;
+            Ok(Values::Numbers(numbers(0)?.into_iter().map(f).collect()))
+        };
+        let binary = |f: fn(f64, f64) -> f64| -> Result<Values> {
+            check_count(2)?;
+            Ok(Values::Numbers(
+                numbers(0)?
+                    .into_iter()
+                    .zip(numbers(1)?)
+                    .map(|(a, b)| f(a, b))
+                    .collect(),
+            ))
+        };
+        match name {
+            "abs" => unary(f64::abs),
+            "sqrt" => unary(f64::sqrt),
+            "exp" => unary(f64::exp),
+            "ln" => unary(f64::ln),
+            "log" if arguments.len() == 2 => binary(f64::log),
+            "log" => unary(f64::ln),
+            "log10" => unary(f64::log10),
+            "log2" => unary(f64::log2),
+            "sin" => unary(f64::sin),
+            "cos" => unary(f64::cos),
+            "tan" => unary(f64::tan),
+            "asin" => unary(f64::asin),
+            "acos" => unary(f64::acos),
+            "atan" => unary(f64::atan),
+            "atan2" => binary(f64::atan2),
+            "floor" => unary(f64::floor),
+            "ceil" => unary(f64::ceil),
+            "round" => unary(f64::round),
+            "sign" => unary(|x| if x == 0.0 { 0.0 } else { x.signum() }),
+            "pow" => binary(f64::powf),
+            "min" | "max" => {
+                if arguments.is_empty() {
+                    bail!("Function '{}' expects at least one argument", name);
+                }
+                let mut result = numbers(0)?;
+                for i in 1..arguments.len() {
+                    for (x, y) in result.iter_mut().zip(numbers(i)?) {
+                        *x = if name == "min" { x.min(y) } else { x.max(y) };
+                    }
+                }
+                Ok(Values::Numbers(result))
+            }
+            "if" => {
+                check_count(3)?;
+                let condition = numbers(0)?;
+                match (arguments[1].evaluate(data)?, arguments[2].evaluate(data)?) {
+                    (Values::Numbers(a), Values::Numbers(b)) => Ok(Values::Numbers(
+                        condition
+                            .iter()
+                            .zip(a.into_iter().zip(b))
+                            .map(|(&c, (a, b))| if truth(c) { a } else { b })
+                            .collect(),
+                    )),
+                    (Values::Texts(a), Values::Texts(b)) => Ok(Values::Texts(
+                        condition
+                            .iter()
+                            .zip(a.into_iter().zip(b))
+                            .map(|(&c, (a, b))| if truth(c) { a } else { b })
+                            .collect(),
+                    )),
+                    _ => bail!("Both values of 'if' must be numbers or both texts"),
+                }
+            }
+            "missing" => {
+                check_count(1)?;
+                let flag = |x: bool| if x { 1.0 } else { 0.0 };
+                Ok(Values::Numbers(match arguments[0].evaluate(data)? {
+                    Values::Numbers(values) => values.iter().map(|x| flag(x.is_nan())).collect(),
+                    Values::Texts(values) => values.iter().map(|x| flag(x.is_empty())).collect(),
+                }))
+            }
+            "between" => {
+                check_count(3)?;
+                let (x, low, high) = (&arguments[0], &arguments[1], &arguments[2]);
+                let above = Self::evaluate_binary(BinaryOperator::GreaterOrEqual, x, low, data)?;
+                let below = Self::evaluate_binary(BinaryOperator::LessOrEqual, x, high, data)?;
+                Ok(Values::Numbers(
+                    above
+                        .numbers("'between'")?
+                        .into_iter()
+                        .zip(below.numbers("'between'")?)
+                        .map(|(a, b)| BinaryOperator::And.apply(a, b))
+                        .collect(),
+                ))
+            }
+            "len" => {
+                check_count(1)?;
+                match arguments[0].evaluate(data)? {
+                    Values::Texts(values) => Ok(Values::Numbers(
+                        values.iter().map(|x| x.chars().count() as f64).collect(),
+                    )),
+                    Values::Numbers(_) => bail!("Function 'len' expects a text"),
+                }
+            }
+            _ => bail!(
+                "Unknown function '{}'; available functions: {}",
+                name,
+                FUNCTIONS.join(", ")
+            ),
+        }
+    }
+}
+
+/// Column computed from an expression of other columns.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DerivedColumn {
+    pub name: String,
+    pub expression: String,
+}
+
+impl DerivedColumn {
+    pub fn new(name: &str, expression: &str) -> DerivedColumn {
+        DerivedColumn {
+            name: name.to_owned(),
+            expression: expression.to_owned(),
+        }
+    }
+
+    /// Evaluate the expression and add the column to the data;
+    /// comparisons and logical expressions make boolean columns, texts make text columns.
+    pub fn add_to(&self, data: &mut PointData) -> Result<()> {
+        let name = self.name.trim();
+        if name.is_empty() {
+            bail!("The name of the new column is empty");
+        }
+        if data.contains_column(name) {
+            bail!("Column '{}' already exists", name);
+        }
+        let expression = Expression::parse(&self.expression)
+            .with_context(|| format!("Invalid expression of the column '{}'", name))?;
+        let values = expression
+            .evaluate(data)
+            .with_context(|| format!("Failed to evaluate the column '{}'", name))?;
+        match values {
+            Values::Numbers(values) => {
+                if data.is_empty() {
+                    data.with_data_column(name);
+                }
+                for (i, value) in values.into_iter().enumerate() {
+                    data.set_data(name, i, value);
+                }
+                if expression.is_boolean() {
+                    data.column_types
+                        .insert(name.to_owned(), ColumnType::Boolean);
+                }
+            }
+            Values::Texts(values) => {
+                if data.is_empty() {
+                    data.with_aux_column(name);
+                }
+                for (i, value) in values.into_iter().enumerate() {
+                    data.set_aux(name, i, value);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 /// evaluates a polynomial at `z` where `coeff` are the coeffecients
 /// to a polynomial of order `k` where `k` is the length of `coeff` and the
 /// coeffecient
@@ -29,8 +969,93 @@ pub fn polynomial(z: f64, coeff: &[f64]) -> f64 {
 #[rustfmt::skip]
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::pointdata::{test_point_data, Categorical};
     use std::f64;
 
+    fn numbers(expression: &str) -> Vec<f64> {
+        match Expression::parse(expression).unwrap().evaluate(&test_point_data().unwrap()).unwrap() {
+            Values::Numbers(values) => values,
+            Values::Texts(values) => panic!("Numbers expected, got {:?}", values),
+        }
+    }
+
+    #[test]
+    fn test_expression() {
+        assert_eq!(numbers("1 + 2 * 3 - 4 / 2")[0], 5.0);
+        assert_eq!(numbers("2 ^ 3 ^ 2")[0], 512.0);
+        assert_eq!(numbers("-2 ^ 2")[0], -4.0);
+        assert_eq!(numbers("(1 + 2) * 3 % 4")[0], 1.0);
+        assert_eq!(numbers("x + 2 * y"), vec![0.0, 2.0, 1.0, 3.0, 1.5]);
+        assert_eq!(numbers("x > 0.7 or y == 0.5"), vec![0.0, 0.0, 1.0, 1.0, 1.0]);
+        assert_eq!(numbers("not x > 0.7 and y < 1"), vec![1.0, 0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(numbers("label == \"B\" || label = 'E'"), vec![0.0, 1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(numbers("if(label != \"C\", max(x, y, 0.25), -1)"), vec![0.25, 1.0, -1.0, 1.0, 0.5]);
+        assert_eq!(numbers("log(exp(2)) + log(8, 2) + sqrt(abs(-16)) + round(`x` * 3)")[2], 12.0);
+        assert_eq!(numbers("missing(x / x)"), vec![1.0, 1.0, 0.0, 0.0, 0.0]);
+        assert_eq!(numbers("len(label) + true")[0], 2.0);
+        assert_eq!(numbers("label in [\"A\", 'D'] or x + y in [1]"), vec![1.0, 1.0, 1.0, 1.0, 1.0]);
+        assert_eq!(numbers("label not in [\"A\", \"D\"] and not x in [0.5]"), vec![0.0, 1.0, 1.0, 0.0, 0.0]);
+        assert_eq!(numbers("between(y, 0.5, 1)"), vec![0.0, 1.0, 0.0, 1.0, 1.0]);
+        assert_eq!(numbers("x in [0, y, 0.5 + 0.5] and y not in [-0, 0 / 0]"), vec![0.0, 1.0, 0.0, 1.0, 1.0]);
+        assert_eq!(quote_column("price_2"), "price_2");
+        assert_eq!(quote_column("unit price"), "`unit price`");
+        assert_eq!(quote_column("In"), "`In`");
+        assert_eq!(quote_text("say \"hi\""), "\"say \\\"hi\\\"\"");
+
+        assert!(Expression::parse("x + ").is_err());
+        assert!(Expression::parse("(x + 1").is_err());
+        assert!(Expression::parse("x < y < 1").is_err());
+        assert!(Expression::parse("x $ 1").is_err());
+        assert!(Expression::parse("x in 1").is_err());
+        assert!(Expression::parse("in + 1").is_err());
+        assert!(Expression::parse("\"open").is_err());
+        let error = Expression::parse("x + * 2").unwrap_err().to_string();
+        assert_eq!(error, "Unexpected '*' at position 5");
+        let data = test_point_data().unwrap();
+        assert!(Expression::parse("z + 1").unwrap().evaluate(&data).is_err());
+        assert!(Expression::parse("label * 2").unwrap().evaluate(&data).is_err());
+        assert!(Expression::parse("cube(x)").unwrap().evaluate(&data).is_err());
+        assert!(Expression::parse("pow(x)").unwrap().evaluate(&data).is_err());
+        assert_eq!(Expression::parse("if(x > y, x, z)").unwrap().columns(), vec!["x", "y", "z"]);
+    }
+
+    #[test]
+    fn test_categorical() {
+        let mut data = test_point_data().unwrap();
+        let label = data.aux.remove("label").unwrap();
+        data.categorical.insert("label".into(), Categorical::from_values(label.iter().map(|x| x.as_str())));
+        let numbers = |expression: &str| Expression::parse(expression).unwrap().evaluate(&data).unwrap();
+        assert_eq!(numbers("label == \"B\" or 'E' == label"), Values::Numbers(vec![0.0, 1.0, 0.0, 0.0, 1.0]));
+        assert_eq!(numbers("label != \"B\""), Values::Numbers(vec![1.0, 0.0, 1.0, 1.0, 1.0]));
+        assert_eq!(numbers("label in ['A', \"D\", \"Z\"]"), Values::Numbers(vec![1.0, 0.0, 0.0, 1.0, 0.0]));
+        assert_eq!(numbers("label not in []"), Values::Numbers(vec![1.0; 5]));
+        assert!(Expression::parse("label in ['A', 1]").unwrap().evaluate(&data).is_err());
+        assert!(Expression::parse("x in ['A']").unwrap().evaluate(&data).is_err());
+        data.categorical.get_mut("label").unwrap().set(1, "");
+        let numbers = |expression: &str| Expression::parse(expression).unwrap().evaluate(&data).unwrap();
+        assert_eq!(numbers("label != 'A'"), Values::Numbers(vec![0.0, 0.0, 1.0, 1.0, 1.0]));
+        assert_eq!(numbers("label != ''"), Values::Numbers(vec![0.0; 5]));
+        assert_eq!(numbers("label == ''"), Values::Numbers(vec![0.0, 1.0, 0.0, 0.0, 0.0]));
+    }
+
+    #[test]
+    fn test_derived_column() {
+        let mut data = test_point_data().unwrap();
+        DerivedColumn::new("ratio", "y / (x + 1)").add_to(&mut data).unwrap();
+        assert_eq!(data.data["ratio"], vec![0.0, 1.0, 0.0, 0.5, 0.5 / 1.5]);
+        DerivedColumn::new("flag", "x == y").add_to(&mut data).unwrap();
+        assert_eq!(data.get("flag", 3), "true");
+        DerivedColumn::new("group", "if(x < 0.5, \"left\", \"right\")").add_to(&mut data).unwrap();
+        assert_eq!(data.aux["group"][0], "left");
+        assert_eq!(data.column_type("group"), Some(ColumnType::Text));
+        assert_eq!(data.all_headers, vec!["x", "y", "label", "ratio", "flag", "group"]);
+        assert!(DerivedColumn::new("ratio", "x").add_to(&mut data).is_err());
+        assert!(DerivedColumn::new(" ", "x").add_to(&mut data).is_err());
+        assert!(DerivedColumn::new("bad", "x +").add_to(&mut data).is_err());
+        assert!(!data.contains_column("bad"));
+    }
+
     // these tests probably could be more robust
     #[test]
     fn test_polynomial() {
@@ -1,122 +1,343 @@
 #![allow(dead_code)]
-use crate::column_filter::Operator;
+use crate::colormap::Rgb;
+use crate::column_filter::{Interpretation, Operator};
+use crate::evaluate::{quote_column, quote_text, BinaryOperator, Expression, Values};
 use crate::pointdata::PointData;
+use anyhow::*;
 use bitvector::*;
-use macroquad::prelude::*;
+use std::collections::BTreeSet;
 //use std::cmp::Ordering::*;
 
-const BAND: &str = "↔";
+pub const BAND: &str = "↔";
+pub const POLYGON: &str = "⬠";
+/// Colors of the highlight groups in the order of creation.
+pub const GROUP_COLORS: [[u8; 3]; 8] = [
+    [255, 0, 0],
+    [0, 255, 0],
+    [255, 255, 0],
+    [255, 0, 255],
+    [255, 128, 0],
+    [255, 255, 255],
+    [128, 0, 255],
+    [0, 128, 64],
+];
 
 pub trait HighlightFilter {
     fn filter(&self, data: &PointData) -> BitVector;
-    fn interface(&mut self, data: &PointData, ui: &mut egui::Ui, id: usize);
 }
 
-#[derive(Debug, Clone, PartialOrd, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialOrd, PartialEq)]
 pub enum HighlightFilterVariants {
     Selection(String, String),
+    /// Points with a value different from the given one,
+    /// missing values (empty texts and numbers) are excluded like in the `!=` of queries.
+    NotEqual(String, String),
+    /// Points with one of the values.
+    InSet(String, Vec<String>),
+    /// Points with a value (as text) matching a pattern.
+    Pattern(String, String, Interpretation),
+    /// Points with a missing value.
+    Missing(String),
+    /// Points with a value.
+    NotMissing(String),
     GreaterThan(String, f64),
     LessThan(String, f64),
     Band(String, f64, f64),
+    /// Points of two columns (x, y) inside a polygon given by its vertices in data coordinates.
+    Polygon(String, String, Vec<(f64, f64)>),
+    /// Text query, e.g. `(age > 30 and country in ["DE", "AT"]) or score < 0.1`,
+    /// see `evaluate::Expression`.
+    Query(String),
+    /// Nested filters combined by their own operator.
+    Group(CombinedHighlightFilter),
+    /// Points not matching the filter.
+    Not(Box<HighlightFilterVariants>),
+    /// Filter kept in a group but not applied.
+    Disabled(Box<HighlightFilterVariants>),
     Empty,
 }
 
+/// Points matching a text query.
+pub fn query_filter(query: &str, data: &PointData) -> Result<BitVector> {
+    let mut bv = BitVector::new(data.len());
+    match Expression::parse(query)?.evaluate(data)? {
+        Values::Numbers(values) => {
+            for (i, x) in values.iter().enumerate() {
+                if *x != 0.0 && !x.is_nan() {
+                    bv.insert(i);
+                }
+            }
+        }
+        Values::Texts(_) => bail!("The query must be a condition, not a text"),
+    }
+    Ok(bv)
+}
+
+/// Rows not in a set of rows.
+fn complement(bv: &BitVector, rows: usize) -> BitVector {
+    let mut complement = BitVector::new(rows);
+    for i in 0..rows {
+        if !bv.contains(i) {
+            complement.insert(i);
+        }
+    }
+    complement
+}
+
+/// Number of a constant expression.
+fn constant(expression: &Expression) -> Option<f64> {
+    match expression {
+        Expression::Number(x) => Some(*x),
+        Expression::Negative(x) => constant(x).map(|x| -x),
+        _ => None,
+    }
+}
+
 impl HighlightFilterVariants {
-    fn delete_button(&mut self, ui: &mut egui::Ui) {
-        if ui.button("🗙").clicked() {
-            *self = HighlightFilterVariants::Empty;
+    /// Filter of a clause of a group with the enabled and negated flags.
+    pub fn clause(filter: HighlightFilterVariants, enabled: bool, negated: bool) -> Self {
+        let filter = match filter {
+            HighlightFilterVariants::Empty => return filter,
+            _ if negated => HighlightFilterVariants::Not(Box::new(filter)),
+            _ => filter,
+        };
+        if enabled {
+            filter
+        } else {
+            HighlightFilterVariants::Disabled(Box::new(filter))
+        }
+    }
+    /// Split a clause into the filter and the enabled and negated flags (inverse of `clause`).
+    pub fn into_clause(self) -> (HighlightFilterVariants, bool, bool) {
+        match self {
+            HighlightFilterVariants::Disabled(filter) => {
+                let (filter, _, negated) = filter.into_clause();
+                (filter, false, negated)
+            }
+            HighlightFilterVariants::Not(filter) => (*filter, true, true),
+            filter => (filter, true, false),
+        }
+    }
+    /// Whether the clause takes part in its group;
+    /// groups without enabled clauses are skipped like the disabled clauses.
+    pub fn is_enabled(&self) -> bool {
+        match self {
+            HighlightFilterVariants::Disabled(_) | HighlightFilterVariants::Empty => false,
+            HighlightFilterVariants::Group(filter) => filter.is_active(),
+            HighlightFilterVariants::Not(filter) => filter.is_enabled(),
+            _ => true,
         }
     }
 
-    fn selection_ui(
-        column: &str,
-        value: &str,
-        data: &PointData,
-        ui: &mut egui::Ui,
-        id: usize,
-    ) -> (String, String) {
-        ui.label("=");
-        let mut highlight_column = column.to_string();
-        let mut highlight_value = value.to_owned();
-        egui::ComboBox::from_id_source(format!("Highlight column Selection {}", id))
-            .selected_text(column.to_string())
-            .show_ui(ui, |ui| {
-                ui.selectable_value(&mut highlight_column, "".to_string(), "");
-                for column in data.headers.iter() {
-                    ui.selectable_value(&mut highlight_column, column.to_string(), column);
+    /// Text query equivalent to the filter;
+    /// none for polygons, patterns, disabled filters and filters without a column.
+    pub fn to_query(&self) -> Option<String> {
+        match self {
+            HighlightFilterVariants::Selection(column, value) if !column.is_empty() => Some(
+                format!("{} == {}", quote_column(column), quote_text(value)),
+            ),
+            HighlightFilterVariants::NotEqual(column, value) if !column.is_empty() => Some(
+                format!("{} != {}", quote_column(column), quote_text(value)),
+            ),
+            HighlightFilterVariants::InSet(column, values)
+                if !column.is_empty() && !values.is_empty() =>
+            {
+                let values = values
+                    .iter()
+                    .map(|value| quote_text(value))
+                    .collect::<Vec<_>>();
+                Some(format!("{} in [{}]", quote_column(column), values.join(", ")))
+            }
+            HighlightFilterVariants::Missing(column) if !column.is_empty() => {
+                Some(format!("missing({})", quote_column(column)))
+            }
+            HighlightFilterVariants::NotMissing(column) if !column.is_empty() => {
+                Some(format!("not missing({})", quote_column(column)))
+            }
+            HighlightFilterVariants::LessThan(column, value) if !column.is_empty() => {
+                Some(format!("{} < {}", quote_column(column), value))
+            }
+            HighlightFilterVariants::GreaterThan(column, value) if !column.is_empty() => {
+                Some(format!("{} > {}", quote_column(column), value))
+            }
+            HighlightFilterVariants::Band(column, value, width) if !column.is_empty() => {
+                Some(format!(
+                    "between({}, {}, {})",
+                    quote_column(column),
+                    value - 0.5 * width,
+                    value + 0.5 * width
+                ))
+            }
+            HighlightFilterVariants::Query(query) if !query.trim().is_empty() => {
+                Some(query.to_owned())
+            }
+            HighlightFilterVariants::Group(filter) => filter.to_query(),
+            HighlightFilterVariants::Not(filter) => {
+                filter.to_query().map(|query| format!("not ({})", query))
+            }
+            _ => None,
+        }
+    }
+
+    /// Filter equivalent to a query made of simple conditions, e.g. `x < 3 and not y > 1`.
+    pub fn from_expression(expression: &Expression) -> Option<HighlightFilterVariants> {
+        match expression {
+            Expression::Binary(BinaryOperator::And, _, _)
+            | Expression::Binary(BinaryOperator::Or, _, _) => {
+                let filter = CombinedHighlightFilter::from_expression(expression)?;
+                Some(HighlightFilterVariants::Group(filter))
+            }
+            Expression::Not(filter) => match filter.as_ref() {
+                Expression::Function(name, arguments) if name == "missing" => {
+                    match arguments.as_slice() {
+                        [Expression::Column(column)] => {
+                            Some(HighlightFilterVariants::NotMissing(column.to_owned()))
+                        }
+                        _ => None,
+                    }
                 }
-            });
-        egui::ComboBox::from_id_source(format!("Highlight value Selection {}", id))
-            .selected_text(value.to_string())
-            .show_ui(ui, |ui| {
-                ui.selectable_value(&mut highlight_value, "".to_string(), "");
-                for value in data.unique_values(&column).iter() {
-                    ui.selectable_value(&mut highlight_value, value.to_string(), value);
+                _ => Some(HighlightFilterVariants::Not(Box::new(
+                    HighlightFilterVariants::from_expression(filter)?,
+                ))),
+            },
+            Expression::Binary(operator, a, b) => match (operator, a.as_ref(), b.as_ref()) {
+                (BinaryOperator::Equal, Expression::Column(column), Expression::Text(value)) => {
+                    Some(HighlightFilterVariants::Selection(
+                        column.to_owned(),
+                        value.to_owned(),
+                    ))
                 }
-            });
-        (highlight_column, highlight_value)
-    }
-    fn threshold_ui(
-        less: bool,
-        column: &str,
-        value: f64,
-        data: &PointData,
-        ui: &mut egui::Ui,
-        id: usize,
-    ) -> (bool, String, f64) {
-        let is_less = if less {
-            !ui.button("<").clicked()
-        } else {
-            ui.button(">").clicked()
-        };
-
-        let mut highlight_column = column.to_string();
-        let mut highlight_value = value;
-        egui::ComboBox::from_id_source(format!("Highlight threshold {}", id))
-            .selected_text(column.to_string())
-            .show_ui(ui, |ui| {
-                ui.selectable_value(&mut highlight_column, "".to_string(), "");
-                for column in data.headers.iter() {
-                    ui.selectable_value(&mut highlight_column, column.to_string(), column);
+                (BinaryOperator::Equal, Expression::Column(column), value) => {
+                    Some(HighlightFilterVariants::Selection(
+                        column.to_owned(),
+                        constant(value)?.to_string(),
+                    ))
                 }
-            });
-        ui.add(egui::DragValue::new(&mut highlight_value).speed(0.1));
-        (is_less, highlight_column, highlight_value)
-    }
-
-    fn band_ui(
-        column: &str,
-        value: f64,
-        width: f64,
-        data: &PointData,
-        ui: &mut egui::Ui,
-        id: usize,
-    ) -> (String, f64, f64) {
-        ui.label(BAND);
-
-        let mut highlight_column = column.to_string();
-        let mut highlight_value = value;
-        let mut highlight_width = width;
-        egui::ComboBox::from_id_source(format!("Highlight band {}", id))
-            .selected_text(column.to_string())
-            .show_ui(ui, |ui| {
-                ui.selectable_value(&mut highlight_column, "".to_string(), "");
-                for column in data.headers.iter() {
-                    ui.selectable_value(&mut highlight_column, column.to_string(), column);
+                (BinaryOperator::NotEqual, Expression::Column(column), Expression::Text(value)) => {
+                    Some(HighlightFilterVariants::NotEqual(
+                        column.to_owned(),
+                        value.to_owned(),
+                    ))
                 }
-            });
-        ui.add(egui::DragValue::new(&mut highlight_value).speed(0.1));
-        ui.end_row();
-        ui.label("");
-        ui.label("Width:");
-        ui.add(
-            egui::DragValue::new(&mut highlight_width)
-                .speed(0.1)
-                .clamp_range(0.0..=f64::MAX),
-        );
+                (BinaryOperator::NotEqual, Expression::Column(column), value) => {
+                    Some(HighlightFilterVariants::NotEqual(
+                        column.to_owned(),
+                        constant(value)?.to_string(),
+                    ))
+                }
+                (BinaryOperator::Less, Expression::Column(column), value) => Some(
+                    HighlightFilterVariants::LessThan(column.to_owned(), constant(value)?),
+                ),
+                (BinaryOperator::Greater, Expression::Column(column), value) => Some(
+                    HighlightFilterVariants::GreaterThan(column.to_owned(), constant(value)?),
+                ),
+                _ => None,
+            },
+            Expression::In(column, values) => match column.as_ref() {
+                Expression::Column(column) => {
+                    let values = values
+                        .iter()
+                        .map(|value| match value {
+                            Expression::Text(value) => Some(value.to_owned()),
+                            value => constant(value).map(|x| x.to_string()),
+                        })
+                        .collect::<Option<Vec<_>>>()?;
+                    Some(HighlightFilterVariants::InSet(column.to_owned(), values))
+                }
+                _ => None,
+            },
+            Expression::Function(name, arguments) if name == "missing" => {
+                match arguments.as_slice() {
+                    [Expression::Column(column)] => {
+                        Some(HighlightFilterVariants::Missing(column.to_owned()))
+                    }
+                    _ => None,
+                }
+            }
+            Expression::Function(name, arguments) if name == "between" => {
+                match arguments.as_slice() {
+                    [Expression::Column(column), low, high] => {
+                        let (low, high) = (constant(low)?, constant(high)?);
+                        Some(HighlightFilterVariants::Band(
+                            column.to_owned(),
+                            0.5 * (low + high),
+                            high - low,
+                        ))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Values of a numeric column or of a text column with numbers, NaN for missing values.
+fn numeric_values(data: &PointData, column: &str) -> Option<Vec<f64>> {
+    if data.data.contains_key(column) {
+        Some(data.data[column].clone())
+    } else {
+        data.text_as_numbers(column)
+            .map(|values| values.into_iter().map(|x| x.unwrap_or(f64::NAN)).collect())
+    }
+}
+
+/// Points with a value (formatted as in `unique_values`, empty if missing) accepted by a test;
+/// the test is evaluated once per category of a categorical column.
+fn text_filter(data: &PointData, column: &str, accept: impl Fn(&str) -> bool) -> BitVector {
+    let mut bv = BitVector::new(data.len());
+    if let Some(values) = data.data.get(column) {
+        for (i, x) in values.iter().enumerate() {
+            if accept(&data.format_value(column, *x)) {
+                bv.insert(i);
+            }
+        }
+    } else if let Some(categorical) = data.categorical.get(column) {
+        let accepted = categorical
+            .categories
+            .iter()
+            .map(|x| accept(x))
+            .collect::<Vec<_>>();
+        for (i, code) in categorical.codes.iter().enumerate() {
+            if accepted[*code as usize] {
+                bv.insert(i);
+            }
+        }
+    } else if let Some(values) = data.aux.get(column) {
+        for (i, x) in values.iter().enumerate() {
+            if accept(x) {
+                bv.insert(i);
+            }
+        }
+    }
+    bv
+}
 
-        (highlight_column, highlight_value, highlight_width)
+/// Test if a point is inside a polygon (even-odd rule), points on the edges are inside.
+pub fn inside_polygon(x: f64, y: f64, polygon: &[(f64, f64)]) -> bool {
+    let mut inside = false;
+    for (i, &(x1, y1)) in polygon.iter().enumerate() {
+        let (x2, y2) = polygon[(i + 1) % polygon.len()];
+        if (y1 > y) != (y2 > y) {
+            let xcross = x1 + (y - y1) * (x2 - x1) / (y2 - y1);
+            if x == xcross {
+                return true;
+            }
+            if x < xcross {
+                inside = !inside;
+            }
+        }
+        // horizontal edges, vertices and edges crossed by the ray exactly at the point
+        let on_segment = (x - x1) * (y2 - y1) == (y - y1) * (x2 - x1)
+            && x >= x1.min(x2)
+            && x <= x1.max(x2)
+            && y >= y1.min(y2)
+            && y <= y1.max(y2);
+        if on_segment {
+            return true;
+        }
     }
+    inside
 }
 
 impl HighlightFilter for HighlightFilterVariants {
@@ -126,13 +347,22 @@ impl HighlightFilter for HighlightFilterVariants {
         match self {
             HighlightFilterVariants::Selection(column, value) => {
                 if data.data.contains_key(column) {
-                    if let Ok(value) = value.parse::<f64>() {
+                    if let Some(value) = data.parse_value(column, value) {
                         for (i, x) in data.data[column].iter().enumerate() {
                             if *x == value {
                                 bv.insert(i);
                             }
                         }
                     }
+                } else if let Some(categorical) = data.categorical.get(column) {
+                    // compare the dictionary codes instead of the strings
+                    if let Some(code) = categorical.code(value) {
+                        for (i, x) in categorical.codes.iter().enumerate() {
+                            if *x == code {
+                                bv.insert(i);
+                            }
+                        }
+                    }
                 } else {
                     if data.aux.contains_key(column) {
                         for (i, x) in data.aux[column].iter().enumerate() {
@@ -143,6 +373,67 @@ impl HighlightFilter for HighlightFilterVariants {
                     }
                 }
             }
+            HighlightFilterVariants::NotEqual(column, value) => {
+                if let Some(values) = data.data.get(column) {
+                    let value = data.parse_value(column, value);
+                    for (i, x) in values.iter().enumerate() {
+                        if !x.is_nan() && Some(*x) != value {
+                            bv.insert(i);
+                        }
+                    }
+                } else {
+                    bv = text_filter(data, column, |x| !x.is_empty() && x != value);
+                }
+            }
+            HighlightFilterVariants::InSet(column, set) => {
+                if let Some(values) = data.data.get(column) {
+                    let mut set = set
+                        .iter()
+                        .filter_map(|value| data.parse_value(column, value))
+                        .filter(|x| !x.is_nan())
+                        .collect::<Vec<_>>();
+                    set.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                    for (i, x) in values.iter().enumerate() {
+                        if !x.is_nan()
+                            && set.binary_search_by(|y| y.partial_cmp(x).unwrap()).is_ok()
+                        {
+                            bv.insert(i);
+                        }
+                    }
+                } else {
+                    let set = set.iter().map(|x| x.as_str()).collect::<BTreeSet<_>>();
+                    bv = text_filter(data, column, |x| set.contains(x));
+                }
+            }
+            HighlightFilterVariants::Pattern(column, pattern, interpretation) => {
+                // an invalid regular expression matches nothing, the error is shown in the window
+                bv = interpretation
+                    .matcher(pattern)
+                    .map(|matcher| text_filter(data, column, matcher))
+                    .unwrap_or(bv);
+            }
+            HighlightFilterVariants::Missing(column) => {
+                if let Some(values) = data.data.get(column) {
+                    for (i, x) in values.iter().enumerate() {
+                        if x.is_nan() {
+                            bv.insert(i);
+                        }
+                    }
+                } else {
+                    bv = text_filter(data, column, |x| x.is_empty());
+                }
+            }
+            HighlightFilterVariants::NotMissing(column) => {
+                if let Some(values) = data.data.get(column) {
+                    for (i, x) in values.iter().enumerate() {
+                        if !x.is_nan() {
+                            bv.insert(i);
+                        }
+                    }
+                } else {
+                    bv = text_filter(data, column, |x| !x.is_empty());
+                }
+            }
             HighlightFilterVariants::LessThan(column, value) => {
                 if data.data.contains_key(column) {
                     for (i, x) in data.data[column].iter().enumerate() {
@@ -150,13 +441,11 @@ impl HighlightFilter for HighlightFilterVariants {
                             bv.insert(i);
                         }
                     }
-                } else {
-                    if data.aux.contains_key(column) {
-                        for (i, x) in data.aux[column].iter().enumerate() {
-                            if let Ok(x) = x.parse::<f64>() {
-                                if x < *value {
-                                    bv.insert(i);
-                                }
+                } else if let Some(values) = data.text_as_numbers(column) {
+                    for (i, x) in values.into_iter().enumerate() {
+                        if let Some(x) = x {
+                            if x < *value {
+                                bv.insert(i);
                             }
                         }
                     }
@@ -169,13 +458,11 @@ impl HighlightFilter for HighlightFilterVariants {
                             bv.insert(i);
                         }
                     }
-                } else {
-                    if data.aux.contains_key(column) {
-                        for (i, x) in data.aux[column].iter().enumerate() {
-                            if let Ok(x) = x.parse::<f64>() {
-                                if x > *value {
-                                    bv.insert(i);
-                                }
+                } else if let Some(values) = data.text_as_numbers(column) {
+                    for (i, x) in values.into_iter().enumerate() {
+                        if let Some(x) = x {
+                            if x > *value {
+                                bv.insert(i);
                             }
                         }
                     }
@@ -188,86 +475,58 @@ impl HighlightFilter for HighlightFilterVariants {
                             bv.insert(i);
                         }
                     }
-                } else {
-                    if data.aux.contains_key(column) {
-                        for (i, x) in data.aux[column].iter().enumerate() {
-                            if let Ok(x) = x.parse::<f64>() {
-                                if x >= *value - 0.5 * width && x <= *value + 0.5 * width {
-                                    bv.insert(i);
-                                }
+                } else if let Some(values) = data.text_as_numbers(column) {
+                    for (i, x) in values.into_iter().enumerate() {
+                        if let Some(x) = x {
+                            if x >= *value - 0.5 * width && x <= *value + 0.5 * width {
+                                bv.insert(i);
                             }
                         }
                     }
                 }
             }
-            HighlightFilterVariants::Empty => {}
-        }
-        //       println!(" - return {:?} BV:{} DATA:{}",self,bv.capacity(),data.len());
-        bv
-    }
-
-    fn interface(&mut self, data: &PointData, ui: &mut egui::Ui, id: usize) {
-        match self {
-            HighlightFilterVariants::Selection(column, value) => {
-                let (new_column, new_value) = HighlightFilterVariants::selection_ui(column, value, data, ui, id);
-                *self = HighlightFilterVariants::Selection(new_column, new_value);
-                self.delete_button(ui);
+            HighlightFilterVariants::Polygon(xcolumn, ycolumn, polygon) => {
+                if polygon.len() >= 3 {
+                    if let (Some(xs), Some(ys)) =
+                        (numeric_values(data, xcolumn), numeric_values(data, ycolumn))
+                    {
+                        for (i, (&x, &y)) in xs.iter().zip(ys.iter()).enumerate() {
+                            if inside_polygon(x, y, polygon) {
+                                bv.insert(i);
+                            }
+                        }
+                    }
+                }
             }
-            HighlightFilterVariants::LessThan(column, value) => {
-                let (is_less, new_column, new_value) = HighlightFilterVariants::threshold_ui(
-                    true,
-                    &column.to_string(),
-                    *value,
-                    data,
-                    ui,
-                    id,
-                );
-                *self = if is_less {
-                    HighlightFilterVariants::LessThan(new_column, new_value)
-                } else {
-                    HighlightFilterVariants::GreaterThan(new_column, new_value)
-                };
-                self.delete_button(ui);
+            HighlightFilterVariants::Query(query) => {
+                // an invalid query matches nothing, the error is shown in the filter window
+                bv = query_filter(query, data).unwrap_or(bv);
             }
-            HighlightFilterVariants::GreaterThan(column, value) => {
-                let (is_less, new_column, new_value) = HighlightFilterVariants::threshold_ui(
-                    false,
-                    &column.to_string(),
-                    *value,
-                    data,
-                    ui,
-                    id,
-                );
-                *self = if is_less {
-                    HighlightFilterVariants::LessThan(new_column, new_value)
-                } else {
-                    HighlightFilterVariants::GreaterThan(new_column, new_value)
-                };
-                self.delete_button(ui);
+            HighlightFilterVariants::Group(filter) => {
+                bv = filter.filter(data);
             }
-            HighlightFilterVariants::Band(column, value, width) => {
-                let (new_column, new_value, new_width) = HighlightFilterVariants::band_ui(
-                    &column.to_string(),
-                    *value,
-                    *width,
-                    data,
-                    ui,
-                    id,
-                );
-                *self = HighlightFilterVariants::Band(new_column, new_value, new_width);
-                self.delete_button(ui);
-            }
-            HighlightFilterVariants::Empty => {}
+            HighlightFilterVariants::Not(filter) => {
+                bv = complement(&filter.filter(data), data.len());
+            }
+            HighlightFilterVariants::Disabled(_) | HighlightFilterVariants::Empty => {}
         }
+        //       println!(" - return {:?} BV:{} DATA:{}",self,bv.capacity(),data.len());
+        bv
     }
 }
 
-#[derive(Debug, Clone, PartialOrd, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialOrd, PartialEq)]
 pub struct CombinedHighlightFilter {
     pub operator: Operator,
     pub filters: Vec<HighlightFilterVariants>,
 }
 
+impl Default for CombinedHighlightFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl CombinedHighlightFilter {
     pub fn new() -> Self {
         CombinedHighlightFilter {
@@ -275,7 +534,7 @@ impl CombinedHighlightFilter {
             filters: Vec::new(),
         }
     }
-    fn tidy(&mut self) {
+    pub fn tidy(&mut self) {
         let mut i = 0;
         while i < self.filters.len() {
             if self.filters[i] == HighlightFilterVariants::Empty {
@@ -285,72 +544,536 @@ impl CombinedHighlightFilter {
             }
         }
     }
+
+    /// Some of the filters are enabled.
+    pub fn is_active(&self) -> bool {
+        self.filters.iter().any(|filter| filter.is_enabled())
+    }
+
+    /// Text query equivalent to the enabled filters,
+    /// none if a filter has no query (e.g. a polygon).
+    pub fn to_query(&self) -> Option<String> {
+        let enabled = self
+            .filters
+            .iter()
+            .filter(|filter| filter.is_enabled())
+            .collect::<Vec<_>>();
+        let parts = enabled
+            .iter()
+            .map(|filter| match filter {
+                HighlightFilterVariants::Query(_) | HighlightFilterVariants::Group(_)
+                    if enabled.len() > 1 =>
+                {
+                    filter.to_query().map(|query| format!("({})", query))
+                }
+                _ => filter.to_query(),
+            })
+            .collect::<Option<Vec<_>>>()?;
+        if parts.is_empty() {
+            return None;
+        }
+        Some(parts.join(match self.operator {
+            Operator::And => " and ",
+            Operator::Or => " or ",
+        }))
+    }
+
+    /// Filters of a query made of simple conditions joined by `and`, `or` and `not`,
+    /// nested `and` and `or` become groups.
+    pub fn from_expression(expression: &Expression) -> Option<CombinedHighlightFilter> {
+        fn terms<'a>(
+            expression: &'a Expression,
+            operator: BinaryOperator,
+            result: &mut Vec<&'a Expression>,
+        ) {
+            match expression {
+                Expression::Binary(x, a, b) if *x == operator => {
+                    terms(a, operator, result);
+                    terms(b, operator, result);
+                }
+                _ => result.push(expression),
+            }
+        }
+        let (operator, binary_operator) = match expression {
+            Expression::Binary(BinaryOperator::Or, _, _) => (Operator::Or, BinaryOperator::Or),
+            _ => (Operator::And, BinaryOperator::And),
+        };
+        let mut parts = Vec::new();
+        terms(expression, binary_operator, &mut parts);
+        let filters = parts
+            .into_iter()
+            .map(HighlightFilterVariants::from_expression)
+            .collect::<Option<Vec<_>>>()?;
+        Some(CombinedHighlightFilter { operator, filters })
+    }
+
+    /// Filter of a text query: separate filters if the query is made of simple conditions
+    /// (see `from_expression`), a single query filter otherwise.
+    pub fn from_query(query: &str) -> Result<CombinedHighlightFilter> {
+        let expression = Expression::parse(query)?;
+        Ok(
+            CombinedHighlightFilter::from_expression(&expression).unwrap_or_else(|| {
+                CombinedHighlightFilter {
+                    operator: Operator::And,
+                    filters: vec![HighlightFilterVariants::Query(query.to_owned())],
+                }
+            }),
+        )
+    }
 }
 
-impl HighlightFilter for CombinedHighlightFilter {
-    fn filter(&self, data: &PointData) -> BitVector {
+impl CombinedHighlightFilter {
+    /// Combine the points of the enabled filters (out of `rows` rows) evaluated by `evaluate`.
+    pub fn combine(
+        &self,
+        rows: usize,
+        mut evaluate: impl FnMut(&HighlightFilterVariants) -> BitVector,
+    ) -> BitVector {
+        let mut bv = BitVector::new(rows);
         match self.operator {
             Operator::And => {
-                let mut bv = BitVector::new(data.len());
-                for i in 0..data.len() {
+                for i in 0..rows {
                     bv.insert(i);
                 }
-                for f in self.filters.iter() {
-                    bv.intersection_inplace(&f.filter(data));
+                for f in self.filters.iter().filter(|f| f.is_enabled()) {
+                    bv.intersection_inplace(&evaluate(f));
                 }
-                bv
             }
             Operator::Or => {
-                let mut bv = BitVector::new(data.len());
-                for f in self.filters.iter() {
-                    bv.union_inplace(&f.filter(data));
+                for f in self.filters.iter().filter(|f| f.is_enabled()) {
+                    bv.union_inplace(&evaluate(f));
                 }
-                bv
             }
         }
+        bv
+    }
+}
+
+impl HighlightFilter for CombinedHighlightFilter {
+    fn filter(&self, data: &PointData) -> BitVector {
+        self.combine(data.len(), |f| f.filter(data))
+    }
+}
+
+/// Points matching the filters evaluated before, so that a change of a filter evaluates
+/// only the changed clauses. The cache must be cleared when the data change.
+#[derive(Clone, Default)]
+pub struct FilterCache {
+    /// Clauses, their points and whether they were used since the last `retain_used`.
+    entries: Vec<(HighlightFilterVariants, BitVector, bool)>,
+}
+
+impl FilterCache {
+    pub fn new() -> FilterCache {
+        FilterCache {
+            entries: Vec::new(),
+        }
     }
-    fn interface(&mut self, data: &PointData, ui: &mut egui::Ui, id: usize) {
-        for (i, f) in self.filters.iter_mut().enumerate() {
-            f.interface(data, ui, 103 * id + i);
-            ui.end_row();
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    /// Forget the clauses not used since the last call.
+    pub fn retain_used(&mut self) {
+        self.entries.retain(|(_, _, used)| *used);
+        for (_, _, used) in self.entries.iter_mut() {
+            *used = false;
         }
-        self.tidy();
-        ui.label("Add");
-        ui.horizontal(|ui| {
-            if ui.button("=").clicked() {
-                self.filters.push(HighlightFilterVariants::Selection(
-                    "".to_string(),
-                    "".to_string(),
-                ))
+    }
+    /// Points matching a filter (see `HighlightFilter::filter`), groups and negations
+    /// are combined from the cached points of their clauses.
+    pub fn filter(&mut self, filter: &HighlightFilterVariants, data: &PointData) -> BitVector {
+        match filter {
+            HighlightFilterVariants::Group(filter) => self.combined(filter, data),
+            HighlightFilterVariants::Not(filter) => {
+                complement(&self.filter(filter, data), data.len())
             }
-            if ui.button("<").clicked() {
-                self.filters
-                    .push(HighlightFilterVariants::LessThan("".to_string(), 0.0))
-            }
-            if ui.button(">").clicked() {
-                self.filters
-                    .push(HighlightFilterVariants::GreaterThan("".to_string(), 0.0))
-            }
-            if ui.button(BAND).clicked() {
-                self.filters
-                    .push(HighlightFilterVariants::Band("".to_string(), 0.0, 0.0))
-            }
-        });
-        ui.horizontal(|ui| {
-            ui.label("Operator: ");
-            match self.operator {
-                Operator::And => {
-                    if ui.button("AND").clicked() {
-                        self.operator = Operator::Or;
-                    }
-                }
-                Operator::Or => {
-                    if ui.button("OR").clicked() {
-                        self.operator = Operator::And;
-                    }
+            HighlightFilterVariants::Disabled(_) | HighlightFilterVariants::Empty => {
+                BitVector::new(data.len())
+            }
+            _ => {
+                if let Some(entry) = self.entries.iter_mut().find(|entry| &entry.0 == filter) {
+                    entry.2 = true;
+                    return entry.1.clone();
                 }
+                let bv = filter.filter(data);
+                self.entries.push((filter.clone(), bv.clone(), true));
+                bv
             }
-        });
-        ui.end_row();
+        }
+    }
+    /// Points matching a combined filter, see `filter`.
+    pub fn combined(&mut self, filter: &CombinedHighlightFilter, data: &PointData) -> BitVector {
+        filter.combine(data.len(), |f| self.filter(f, data))
+    }
+    /// Points of a highlight group (see `HighlightGroup::filter`), see `filter`.
+    pub fn group(&mut self, group: &HighlightGroup, data: &PointData) -> BitVector {
+        if !group.filter.is_active() {
+            BitVector::new(data.len())
+        } else {
+            self.combined(&group.filter, data)
+        }
+    }
+}
+
+/// Named group of highlighted points with its own color.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HighlightGroup {
+    pub name: String,
+    pub color: Rgb,
+    pub filter: CombinedHighlightFilter,
+}
+
+impl HighlightGroup {
+    /// Empty group with the name and color for the given position in the list of groups.
+    pub fn new(index: usize) -> HighlightGroup {
+        HighlightGroup {
+            name: format!("Group {}", index + 1),
+            color: Rgb(GROUP_COLORS[index % GROUP_COLORS.len()]),
+            filter: CombinedHighlightFilter::new(),
+        }
+    }
+    /// The groups of a new view: a single empty group.
+    pub fn default_groups() -> Vec<HighlightGroup> {
+        vec![HighlightGroup::new(0)]
+    }
+}
+
+impl HighlightFilter for HighlightGroup {
+    /// Points matching the filter; a group without any enabled filter is empty.
+    fn filter(&self, data: &PointData) -> BitVector {
+        if !self.filter.is_active() {
+            BitVector::new(data.len())
+        } else {
+            self.filter.filter(data)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pointdata::{test_point_data, Categorical};
+
+    #[test]
+    fn test_selection() {
+        let mut data = test_point_data().unwrap();
+        let filter = HighlightFilterVariants::Selection("label".into(), "B".into());
+        assert_eq!(filter.filter(&data).iter().collect::<Vec<_>>(), vec![1]);
+
+        let label = data.aux.remove("label").unwrap();
+        data.categorical.insert(
+            "label".into(),
+            Categorical::from_values(label.iter().map(|x| x.as_str())),
+        );
+        assert_eq!(filter.filter(&data).iter().collect::<Vec<_>>(), vec![1]);
+        let filter = HighlightFilterVariants::GreaterThan("x".into(), 0.7);
+        assert_eq!(filter.filter(&data).iter().collect::<Vec<_>>(), vec![2, 3]);
+    }
+
+    #[test]
+    fn test_polygon() {
+        let triangle = [(0.0, 0.0), (2.0, 0.0), (0.0, 2.0)];
+        assert!(inside_polygon(0.5, 0.5, &triangle));
+        assert!(inside_polygon(1.0, 1.0, &triangle));
+        assert!(inside_polygon(1.0, 0.0, &triangle));
+        assert!(inside_polygon(0.0, 0.0, &triangle));
+        assert!(!inside_polygon(1.5, 1.5, &triangle));
+        assert!(!inside_polygon(-0.1, 0.5, &triangle));
+        assert!(!inside_polygon(f64::NAN, 0.5, &triangle));
+
+        let data = test_point_data().unwrap();
+        let filter = HighlightFilterVariants::Polygon(
+            "x".into(),
+            "y".into(),
+            vec![(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)],
+        );
+        assert_eq!(filter.filter(&data).iter().collect::<Vec<_>>(), vec![0, 1, 2, 4]);
+        let filter = HighlightFilterVariants::Polygon(
+            "x".into(),
+            "y".into(),
+            vec![(0.2, 0.2), (1.2, 0.2), (1.2, 1.2), (0.2, 1.2)],
+        );
+        assert_eq!(filter.filter(&data).iter().collect::<Vec<_>>(), vec![3, 4]);
+        let filter = HighlightFilterVariants::Polygon("x".into(), "missing".into(), vec![]);
+        assert_eq!(filter.filter(&data).iter().count(), 0);
+    }
+
+    #[test]
+    fn test_query() {
+        let data = test_point_data().unwrap();
+        let query = |query: &str| {
+            query_filter(query, &data)
+                .unwrap()
+                .iter()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(query("(x > 0.7 and label in [\"C\", \"E\"]) or y < 0.1"), vec![0, 2]);
+        assert_eq!(query("not label == 'B'"), vec![0, 2, 3, 4]);
+        assert!(query_filter("x > ", &data).is_err());
+        assert!(query_filter("label", &data).is_err());
+        assert!(query_filter("z < 1", &data).is_err());
+        let filter = HighlightFilterVariants::Query("x >= 1".into());
+        assert_eq!(filter.filter(&data).iter().collect::<Vec<_>>(), vec![2, 3]);
+        let filter = HighlightFilterVariants::Query("x >>".into());
+        assert_eq!(filter.filter(&data).iter().count(), 0);
+    }
+
+    #[test]
+    fn test_text_filters() {
+        let csv = "n,name\n1,apple\n,banana\n3,\n1,cherry\n";
+        let data = PointData::from_csv(&mut std::io::Cursor::new(csv)).unwrap();
+        let matching =
+            |filter: HighlightFilterVariants| filter.filter(&data).iter().collect::<Vec<_>>();
+        let names =
+            |values: &[&str]| values.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            matching(HighlightFilterVariants::InSet(
+                "name".into(),
+                names(&["apple", "cherry"])
+            )),
+            vec![0, 3]
+        );
+        assert_eq!(
+            matching(HighlightFilterVariants::InSet("n".into(), names(&["3", "1"]))),
+            vec![0, 2, 3]
+        );
+        assert_eq!(matching(HighlightFilterVariants::NotEqual("n".into(), "1".into())), vec![2]);
+        assert_eq!(
+            matching(HighlightFilterVariants::NotEqual("name".into(), "apple".into())),
+            vec![1, 3]
+        );
+        let query = |query: &str| query_filter(query, &data).unwrap().iter().collect::<Vec<_>>();
+        assert_eq!(query("name != \"apple\""), vec![1, 3]);
+        assert_eq!(query("name != \"banana\" and n != 3"), vec![0, 3]);
+        let pattern = |pattern: &str, interpretation| {
+            matching(HighlightFilterVariants::Pattern(
+                "name".into(),
+                pattern.into(),
+                interpretation,
+            ))
+        };
+        assert_eq!(pattern("an", Interpretation::Contains), vec![1]);
+        assert_eq!(pattern("ch", Interpretation::Prefix), vec![3]);
+        assert_eq!(pattern("e", Interpretation::Postfix), vec![0]);
+        assert_eq!(pattern("^[ab].*[ae]$", Interpretation::Regex), vec![0, 1]);
+        assert_eq!(pattern("(", Interpretation::Regex), Vec::<usize>::new());
+        assert_eq!(matching(HighlightFilterVariants::Missing("n".into())), vec![1]);
+        assert_eq!(matching(HighlightFilterVariants::Missing("name".into())), vec![2]);
+        assert_eq!(
+            matching(HighlightFilterVariants::NotMissing("name".into())),
+            vec![0, 1, 3]
+        );
+
+        // the same points as the equivalent queries
+        let filter = CombinedHighlightFilter {
+            operator: Operator::Or,
+            filters: vec![
+                HighlightFilterVariants::InSet("name".into(), names(&["apple", "a \"b\""])),
+                HighlightFilterVariants::NotEqual("n".into(), "1".into()),
+                HighlightFilterVariants::Missing("name".into()),
+                HighlightFilterVariants::NotMissing("n".into()),
+            ],
+        };
+        let query = filter.to_query().unwrap();
+        assert_eq!(
+            query,
+            "name in [\"apple\", \"a \\\"b\\\"\"] or n != \"1\" \
+             or missing(name) or not missing(n)"
+        );
+        assert_eq!(CombinedHighlightFilter::from_query(&query).unwrap(), filter);
+        for filter in filter.filters.iter() {
+            let query = filter.to_query().unwrap();
+            assert_eq!(
+                query_filter(&query, &data).unwrap().iter().collect::<Vec<_>>(),
+                filter.filter(&data).iter().collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn test_query_round_trip() {
+        let data = test_point_data().unwrap();
+        let filter = CombinedHighlightFilter {
+            operator: Operator::Or,
+            filters: vec![
+                HighlightFilterVariants::Selection("label".into(), "say \"B\"".into()),
+                HighlightFilterVariants::LessThan("x".into(), -0.5),
+                HighlightFilterVariants::GreaterThan("y".into(), 0.75),
+                HighlightFilterVariants::Band("x".into(), 0.5, 0.25),
+            ],
+        };
+        let query = filter.to_query().unwrap();
+        assert_eq!(
+            query,
+            "label == \"say \\\"B\\\"\" or x < -0.5 or y > 0.75 or between(x, 0.375, 0.625)"
+        );
+        assert_eq!(CombinedHighlightFilter::from_query(&query).unwrap(), filter);
+        assert_eq!(
+            query_filter(&query, &data).unwrap().iter().collect::<Vec<_>>(),
+            filter.filter(&data).iter().collect::<Vec<_>>()
+        );
+
+        // nested conditions become groups
+        let query = "x > 0.7 and (y < 0.1 or not (label == \"D\"))";
+        let filter = CombinedHighlightFilter::from_query(query).unwrap();
+        assert_eq!(filter.filters.len(), 2);
+        assert!(matches!(&filter.filters[1], HighlightFilterVariants::Group(group)
+            if group.operator == Operator::Or
+                && matches!(group.filters[1], HighlightFilterVariants::Not(_))));
+        assert_eq!(filter.filter(&data).iter().collect::<Vec<_>>(), vec![2]);
+        assert_eq!(filter.to_query().unwrap(), query);
+
+        // other queries stay text queries
+        let query = "x + y >= 1 and label != \"D\"";
+        let filter = CombinedHighlightFilter::from_query(query).unwrap();
+        assert_eq!(filter.filters, vec![HighlightFilterVariants::Query(query.into())]);
+        assert_eq!(filter.filter(&data).iter().collect::<Vec<_>>(), vec![1, 2, 4]);
+        assert_eq!(filter.to_query().unwrap(), query);
+        assert!(CombinedHighlightFilter::from_query("x <").is_err());
+
+        let mut filter = CombinedHighlightFilter::new();
+        assert_eq!(filter.to_query(), None);
+        filter.filters.push(HighlightFilterVariants::Query("x < 1 or y < 1".into()));
+        filter.filters.push(HighlightFilterVariants::GreaterThan("unit price".into(), 2.0));
+        assert_eq!(filter.to_query().unwrap(), "(x < 1 or y < 1) and `unit price` > 2");
+        filter
+            .filters
+            .push(HighlightFilterVariants::Polygon("x".into(), "y".into(), vec![]));
+        assert_eq!(filter.to_query(), None);
+    }
+
+    #[test]
+    fn test_nested() {
+        let data = test_point_data().unwrap();
+        let less = HighlightFilterVariants::LessThan("x".into(), 0.7);
+        let filter = CombinedHighlightFilter {
+            operator: Operator::And,
+            filters: vec![
+                HighlightFilterVariants::Group(CombinedHighlightFilter {
+                    operator: Operator::Or,
+                    filters: vec![
+                        HighlightFilterVariants::Selection("label".into(), "A".into()),
+                        HighlightFilterVariants::GreaterThan("y".into(), 0.7),
+                    ],
+                }),
+                HighlightFilterVariants::clause(less.clone(), true, true),
+            ],
+        };
+        let matching =
+            |filter: &CombinedHighlightFilter| filter.filter(&data).iter().collect::<Vec<_>>();
+        assert_eq!(matching(&filter), vec![3]);
+        assert_eq!(
+            filter.to_query().unwrap(),
+            "(label == \"A\" or y > 0.7) and not (x < 0.7)"
+        );
+
+        // disabled clauses are ignored
+        let mut disabled = filter.clone();
+        disabled.filters[1] = HighlightFilterVariants::clause(less.clone(), false, true);
+        assert_eq!(matching(&disabled), vec![0, 1, 3]);
+        assert_eq!(disabled.to_query().unwrap(), "label == \"A\" or y > 0.7");
+        assert_eq!(disabled.filters[1].clone().into_clause(), (less.clone(), false, true));
+        disabled.filters[0] = HighlightFilterVariants::clause(less, false, false);
+        let group = HighlightGroup {
+            filter: disabled,
+            ..HighlightGroup::new(0)
+        };
+        assert_eq!(group.filter(&data).iter().count(), 0);
+        assert_eq!(
+            HighlightFilterVariants::clause(HighlightFilterVariants::Empty, false, true),
+            HighlightFilterVariants::Empty
+        );
+    }
+
+    #[test]
+    fn test_empty_groups() {
+        let data = test_point_data().unwrap();
+        let selection = HighlightFilterVariants::Selection("label".into(), "A".into());
+        for &operator in [Operator::And, Operator::Or].iter() {
+            let flipped = match operator {
+                Operator::And => Operator::Or,
+                Operator::Or => Operator::And,
+            };
+            // as added by the "Group" button
+            let empty = HighlightFilterVariants::Group(CombinedHighlightFilter {
+                operator: flipped,
+                filters: vec![],
+            });
+            let disabled = HighlightFilterVariants::Group(CombinedHighlightFilter {
+                operator: flipped,
+                filters: vec![HighlightFilterVariants::clause(selection.clone(), false, false)],
+            });
+            let mut filter = CombinedHighlightFilter {
+                operator,
+                filters: vec![empty.clone(), disabled.clone()],
+            };
+            assert!(!filter.is_active());
+            filter
+                .filters
+                .push(HighlightFilterVariants::Not(Box::new(empty.clone())));
+            assert!(!filter.is_active());
+            filter.filters.push(selection.clone());
+            assert!(filter.is_active());
+            assert_eq!(filter.filter(&data).iter().collect::<Vec<_>>(), vec![0]);
+            assert_eq!(filter.to_query().unwrap(), "label == \"A\"");
+        }
+    }
+
+    #[test]
+    fn test_filter_cache() {
+        let data = test_point_data().unwrap();
+        let less = HighlightFilterVariants::LessThan("x".into(), 0.7);
+        let label = HighlightFilterVariants::InSet("label".into(), vec!["A".into(), "D".into()]);
+        let mut group = HighlightGroup::new(0);
+        group.filter = CombinedHighlightFilter {
+            operator: Operator::Or,
+            filters: vec![
+                HighlightFilterVariants::clause(less.clone(), true, true),
+                HighlightFilterVariants::Group(CombinedHighlightFilter {
+                    operator: Operator::And,
+                    filters: vec![less.clone(), label],
+                }),
+            ],
+        };
+        let mut cache = FilterCache::new();
+        let matching = |bv: BitVector| bv.iter().collect::<Vec<_>>();
+        assert_eq!(matching(cache.group(&group, &data)), vec![0, 2, 3]);
+        assert_eq!(matching(group.filter(&data)), vec![0, 2, 3]);
+        // the same clause is evaluated once
+        assert_eq!(cache.len(), 2);
+
+        // only the changed clause is evaluated again, unused clauses are forgotten
+        cache.retain_used();
+        group.filter.filters[0] = HighlightFilterVariants::GreaterThan("y".into(), 0.7);
+        assert_eq!(matching(cache.group(&group, &data)), vec![0, 1, 3]);
+        assert_eq!(cache.len(), 3);
+        cache.retain_used();
+        assert_eq!(cache.len(), 3);
+        cache.retain_used();
+        assert!(cache.is_empty());
+
+        group.filter.filters[0] = HighlightFilterVariants::clause(less, false, false);
+        group.filter.filters.pop();
+        assert_eq!(cache.group(&group, &data).iter().count(), 0);
+    }
+
+    #[test]
+    fn test_group() {
+        let data = test_point_data().unwrap();
+        let mut group = HighlightGroup::new(1);
+        assert_eq!(group.name, "Group 2");
+        assert_eq!(group.color, Rgb([0, 255, 0]));
+        assert_eq!(group.filter(&data).iter().count(), 0);
+        group
+            .filter
+            .filters
+            .push(HighlightFilterVariants::GreaterThan("x".into(), 0.7));
+        assert_eq!(group.filter(&data).iter().collect::<Vec<_>>(), vec![2, 3]);
     }
 }
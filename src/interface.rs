@@ -0,0 +1,778 @@
+use pointcloud_viewer::column_filter::{Interpretation, Operator};
+use pointcloud_viewer::highlight::*;
+use pointcloud_viewer::datetime::{format_timestamp, parse_timestamp};
+use pointcloud_viewer::evaluate::Expression;
+use pointcloud_viewer::pointdata::{ColumnType, PointData};
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+/// Maximal height of a list of values of a column.
+const VALUE_LIST_HEIGHT: f32 = 200.0;
+
+/// Egui editor of a filter.
+pub trait Interface {
+    fn interface(&mut self, data: &PointData, ui: &mut egui::Ui, id: usize);
+}
+
+/// Continue a filter on the next grid row, below its first widgets.
+fn next_row(ui: &mut egui::Ui) {
+    ui.end_row();
+    ui.label("");
+    ui.label("");
+}
+
+fn delete_button(filter: &mut HighlightFilterVariants, ui: &mut egui::Ui) {
+    if ui.button("🗙").clicked() {
+        *filter = HighlightFilterVariants::Empty;
+    }
+}
+
+/// Distinct values of a column with their lowercase forms used for searching.
+struct ColumnValues {
+    values: Vec<String>,
+    lowercase: Vec<String>,
+}
+
+/// Column, search text and data generation of the cached matching values.
+type MatchesKey = (String, String, usize);
+
+/// Distinct values of a column (see `PointData::unique_values`), cached in the egui memory
+/// until the data change (see `PointData::generation`).
+fn unique_values(column: &str, data: &PointData, ui: &egui::Ui) -> Arc<ColumnValues> {
+    let id = egui::Id::new(("Highlight values", column));
+    let cached = ui.data().get_temp::<(usize, Arc<ColumnValues>)>(id);
+    match cached {
+        Some((generation, values)) if generation == data.generation => values,
+        _ => {
+            let values = data.unique_values(column);
+            let lowercase = values.iter().map(|x| x.to_lowercase()).collect();
+            let values = Arc::new(ColumnValues { values, lowercase });
+            ui.data().insert_temp(id, (data.generation, values.clone()));
+            values
+        }
+    }
+}
+
+/// Values of a column containing the searched text (ignoring the case).
+/// They are cached for the search field `id` and recomputed only when the search changes.
+fn matching_values(
+    column: &str,
+    search: &str,
+    data: &PointData,
+    ui: &egui::Ui,
+    id: egui::Id,
+) -> Arc<Vec<String>> {
+    let id = id.with("Matching values");
+    let key = (column.to_owned(), search.to_owned(), data.generation);
+    let cached = ui.data().get_temp::<(MatchesKey, Arc<Vec<String>>)>(id);
+    match cached {
+        Some((cached_key, matches)) if cached_key == key => matches,
+        _ => {
+            let search = search.to_lowercase();
+            let values = unique_values(column, data, ui);
+            let matches = values
+                .values
+                .iter()
+                .zip(values.lowercase.iter())
+                .filter(|(_, lowercase)| lowercase.contains(&search))
+                .map(|(value, _)| value.to_owned())
+                .collect::<Vec<_>>();
+            let matches = Arc::new(matches);
+            ui.data().insert_temp(id, (key, matches.clone()));
+            matches
+        }
+    }
+}
+
+/// Scrollable list of values; only the visible rows are laid out, so long lists stay fast.
+fn value_list_ui(
+    values: &[String],
+    ui: &mut egui::Ui,
+    id: egui::Id,
+    mut value_ui: impl FnMut(&mut egui::Ui, &str),
+) {
+    let row_height = ui.spacing().interact_size.y;
+    egui::ScrollArea::vertical()
+        .id_source(id)
+        .max_height(VALUE_LIST_HEIGHT)
+        .show_rows(ui, row_height, values.len(), |ui, rows| {
+            for value in values[rows].iter() {
+                value_ui(ui, value);
+            }
+        });
+}
+
+/// Editor of an (in)equality; the value is typed or picked from the matching values of the column.
+fn selection_ui(
+    equal: bool,
+    column: &str,
+    value: &str,
+    data: &PointData,
+    ui: &mut egui::Ui,
+    id: usize,
+) -> (bool, String, String) {
+    let is_equal = if equal {
+        !ui.button("=").clicked()
+    } else {
+        ui.button("≠").clicked()
+    };
+    let mut highlight_column = column.to_string();
+    let mut highlight_value = value.to_owned();
+    column_ui(
+        &mut highlight_column,
+        data,
+        ui,
+        egui::Id::new(("Highlight column Selection", id)),
+    );
+    let response = ui.add(egui::TextEdit::singleline(&mut highlight_value).desired_width(160.0));
+    let popup_id = egui::Id::new(("Highlight value Selection", id));
+    if response.gained_focus() || response.changed() {
+        ui.memory().open_popup(popup_id);
+    }
+    let mut chosen = None;
+    egui::popup_below_widget(ui, popup_id, &response, |ui| {
+        let values = matching_values(&highlight_column, &highlight_value, data, ui, popup_id);
+        value_list_ui(&values, ui, popup_id, |ui, value| {
+            if ui
+                .selectable_label(value == highlight_value, value)
+                .clicked()
+            {
+                chosen = Some(value.to_owned());
+            }
+        });
+    });
+    if let Some(value) = chosen {
+        highlight_value = value;
+    }
+    (is_equal, highlight_column, highlight_value)
+}
+
+/// Editor of a set of values: a searchable list of the values of the column with checkboxes.
+fn in_set_ui(
+    column: &str,
+    values: &[String],
+    data: &PointData,
+    ui: &mut egui::Ui,
+    id: usize,
+) -> (String, Vec<String>) {
+    ui.label("∈");
+    let mut highlight_column = column.to_string();
+    let mut highlight_values = values.iter().cloned().collect::<BTreeSet<_>>();
+    column_ui(
+        &mut highlight_column,
+        data,
+        ui,
+        egui::Id::new(("Highlight column InSet", id)),
+    );
+    egui::CollapsingHeader::new(format!("{} values", values.len()))
+        .id_source(("Highlight values InSet", id))
+        .show(ui, |ui| {
+            let search_id = egui::Id::new(("Highlight search InSet", id));
+            let mut search = ui.data().get_temp::<String>(search_id).unwrap_or_default();
+            let shown = matching_values(&highlight_column, &search, data, ui, search_id);
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::TextEdit::singleline(&mut search)
+                        .hint_text("Search")
+                        .desired_width(120.0),
+                );
+                if ui.button("All").on_hover_text("Select the listed values").clicked() {
+                    highlight_values.extend(shown.iter().cloned());
+                }
+                if ui.button("None").on_hover_text("Unselect the listed values").clicked() {
+                    for value in shown.iter() {
+                        highlight_values.remove(value);
+                    }
+                }
+            });
+            ui.data().insert_temp(search_id, search);
+            value_list_ui(&shown, ui, search_id, |ui, value| {
+                let mut selected = highlight_values.contains(value);
+                if ui.checkbox(&mut selected, value).changed() {
+                    if selected {
+                        highlight_values.insert(value.to_owned());
+                    } else {
+                        highlight_values.remove(value);
+                    }
+                }
+            });
+        });
+    (highlight_column, highlight_values.into_iter().collect())
+}
+
+/// Editor of a text pattern; errors of a regular expression are shown under it.
+fn pattern_ui(
+    column: &str,
+    pattern: &str,
+    interpretation: Interpretation,
+    data: &PointData,
+    ui: &mut egui::Ui,
+    id: usize,
+) -> (String, String, Interpretation) {
+    ui.label("~");
+    let mut highlight_column = column.to_string();
+    let mut highlight_pattern = pattern.to_owned();
+    let mut highlight_interpretation = interpretation;
+    column_ui(
+        &mut highlight_column,
+        data,
+        ui,
+        egui::Id::new(("Highlight column Pattern", id)),
+    );
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_id_source(("Highlight interpretation Pattern", id))
+            .selected_text(interpretation.name())
+            .show_ui(ui, |ui| {
+                for x in Interpretation::ALL.iter() {
+                    ui.selectable_value(&mut highlight_interpretation, *x, x.name());
+                }
+            });
+        ui.add(egui::TextEdit::singleline(&mut highlight_pattern).desired_width(120.0));
+    });
+    if let Err(error) = highlight_interpretation.matcher(&highlight_pattern) {
+        next_row(ui);
+        ui.colored_label(egui::Color32::RED, format!("{:#}", error));
+    }
+    (highlight_column, highlight_pattern, highlight_interpretation)
+}
+
+/// Editor of a test of missing values.
+fn missing_ui(
+    missing: bool,
+    column: &str,
+    data: &PointData,
+    ui: &mut egui::Ui,
+    id: usize,
+) -> (bool, String) {
+    let is_missing = if missing {
+        !ui.button("∅")
+            .on_hover_text("Missing value, click for present values")
+            .clicked()
+    } else {
+        ui.button("≠∅")
+            .on_hover_text("Present value, click for missing values")
+            .clicked()
+    };
+    let mut highlight_column = column.to_string();
+    column_ui(
+        &mut highlight_column,
+        data,
+        ui,
+        egui::Id::new(("Highlight column Missing", id)),
+    );
+    (is_missing, highlight_column)
+}
+
+fn is_datetime(data: &PointData, column: &str) -> bool {
+    data.column_type(column) == Some(ColumnType::DateTime)
+}
+
+/// Editor of a threshold value; datetime values are edited as text and kept while being typed.
+fn value_ui(column: &str, value: &mut f64, data: &PointData, ui: &mut egui::Ui, id: egui::Id) {
+    if is_datetime(data, column) {
+        let mut text = ui
+            .data()
+            .get_temp::<String>(id)
+            .unwrap_or_else(|| format_timestamp(*value));
+        let response = ui
+            .add(
+                egui::TextEdit::singleline(&mut text)
+                    .id(id)
+                    .desired_width(160.0),
+            )
+            .on_hover_text("Date or datetime, e.g. 2023-05-17 or 2023-05-17 13:45:00");
+        if let Some(timestamp) = parse_timestamp(&text) {
+            *value = timestamp;
+        }
+        if response.has_focus() {
+            ui.data().insert_temp(id, text);
+        } else {
+            ui.data().remove::<String>(id);
+        }
+    } else {
+        ui.add(egui::DragValue::new(value).speed(0.1));
+    }
+}
+
+fn threshold_ui(
+    less: bool,
+    column: &str,
+    value: f64,
+    data: &PointData,
+    ui: &mut egui::Ui,
+    id: usize,
+) -> (bool, String, f64) {
+    let is_less = if less {
+        !ui.button("<").clicked()
+    } else {
+        ui.button(">").clicked()
+    };
+
+    let mut highlight_column = column.to_string();
+    let mut highlight_value = value;
+    egui::ComboBox::from_id_source(format!("Highlight threshold {}", id))
+        .selected_text(column.to_string())
+        .show_ui(ui, |ui| {
+            ui.selectable_value(&mut highlight_column, "".to_string(), "");
+            for column in data.headers.iter() {
+                ui.selectable_value(&mut highlight_column, column.to_string(), column);
+            }
+        });
+    value_ui(
+        &highlight_column,
+        &mut highlight_value,
+        data,
+        ui,
+        egui::Id::new(("Highlight threshold value", id)),
+    );
+    (is_less, highlight_column, highlight_value)
+}
+
+fn band_ui(
+    column: &str,
+    value: f64,
+    width: f64,
+    data: &PointData,
+    ui: &mut egui::Ui,
+    id: usize,
+) -> (String, f64, f64) {
+    ui.label(BAND);
+
+    let mut highlight_column = column.to_string();
+    let mut highlight_value = value;
+    let mut highlight_width = width;
+    egui::ComboBox::from_id_source(format!("Highlight band {}", id))
+        .selected_text(column.to_string())
+        .show_ui(ui, |ui| {
+            ui.selectable_value(&mut highlight_column, "".to_string(), "");
+            for column in data.headers.iter() {
+                ui.selectable_value(&mut highlight_column, column.to_string(), column);
+            }
+        });
+    value_ui(
+        &highlight_column,
+        &mut highlight_value,
+        data,
+        ui,
+        egui::Id::new(("Highlight band value", id)),
+    );
+    next_row(ui);
+    ui.label("Width:");
+    let width = egui::DragValue::new(&mut highlight_width)
+        .speed(0.1)
+        .clamp_range(0.0..=f64::MAX);
+    if is_datetime(data, &highlight_column) {
+        // the width of a datetime band is a duration
+        ui.add(width.speed(60.0).suffix(" s"));
+    } else {
+        ui.add(width);
+    }
+
+    (highlight_column, highlight_value, highlight_width)
+}
+
+fn column_ui(column: &mut String, data: &PointData, ui: &mut egui::Ui, id: egui::Id) {
+    egui::ComboBox::from_id_source(id)
+        .selected_text(column.to_string())
+        .show_ui(ui, |ui| {
+            ui.selectable_value(column, "".to_string(), "");
+            for header in data.headers.iter() {
+                ui.selectable_value(column, header.to_string(), header);
+            }
+        });
+}
+
+/// Editor of a polygon: the x and y columns and a collapsible list of the vertices.
+fn polygon_ui(
+    xcolumn: &str,
+    ycolumn: &str,
+    polygon: &[(f64, f64)],
+    data: &PointData,
+    ui: &mut egui::Ui,
+    id: usize,
+) -> (String, String, Vec<(f64, f64)>) {
+    ui.label(POLYGON);
+
+    let mut highlight_xcolumn = xcolumn.to_string();
+    let mut highlight_ycolumn = ycolumn.to_string();
+    let mut highlight_polygon = polygon.to_vec();
+    ui.horizontal(|ui| {
+        column_ui(
+            &mut highlight_xcolumn,
+            data,
+            ui,
+            egui::Id::new(("Highlight polygon x", id)),
+        );
+        ui.label("×");
+        column_ui(
+            &mut highlight_ycolumn,
+            data,
+            ui,
+            egui::Id::new(("Highlight polygon y", id)),
+        );
+    });
+    next_row(ui);
+    egui::CollapsingHeader::new(format!("{} vertices", polygon.len()))
+        .id_source(("Highlight polygon vertices", id))
+        .show(ui, |ui| {
+            let mut remove = None;
+            for (i, (x, y)) in highlight_polygon.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    value_ui(
+                        &highlight_xcolumn,
+                        x,
+                        data,
+                        ui,
+                        egui::Id::new(("Highlight polygon vertex x", id, i)),
+                    );
+                    value_ui(
+                        &highlight_ycolumn,
+                        y,
+                        data,
+                        ui,
+                        egui::Id::new(("Highlight polygon vertex y", id, i)),
+                    );
+                    if ui.button("🗙").clicked() {
+                        remove = Some(i);
+                    }
+                });
+            }
+            if let Some(i) = remove {
+                highlight_polygon.remove(i);
+            }
+            if ui.button("Add vertex").clicked() {
+                let vertex = highlight_polygon.last().cloned().unwrap_or((0.0, 0.0));
+                highlight_polygon.push(vertex);
+            }
+        });
+    (highlight_xcolumn, highlight_ycolumn, highlight_polygon)
+}
+
+/// Editor of a text query with the error of the query below it.
+/// Syntax and columns of a query, the query is evaluated only by the filter.
+fn check_query(query: &str, data: &PointData) -> anyhow::Result<()> {
+    let expression = Expression::parse(query)?;
+    for column in expression.columns() {
+        if !data.all_headers.iter().any(|x| x == column) {
+            anyhow::bail!(
+                "Column '{}' not found; available columns: {}",
+                column,
+                data.all_headers.join(", ")
+            );
+        }
+    }
+    Ok(())
+}
+
+fn query_ui(query: &mut String, data: &PointData, ui: &mut egui::Ui, id: usize) {
+    ui.label("Query");
+    ui.add(egui::TextEdit::singleline(query).desired_width(300.0))
+        .on_hover_text(
+            "e.g. (age > 30 and country in [\"DE\", \"AT\"]) or score < 0.1\n\
+             Operators: == != < <= > >= in [...] not in [...] and or not, between(x, low, high)\n\
+             Texts in quotes \"...\", column names with spaces in backticks `...`\n\
+             Missing values are empty texts or NaN, != excludes them",
+        );
+    // the query is checked again only when it or the data change
+    let id = egui::Id::new(("Highlight query", id, data.generation));
+    let checked = ui.data().get_temp::<(String, Option<String>)>(id);
+    let error = match checked {
+        Some((checked, error)) if checked == *query => error,
+        _ => {
+            let error = if query.trim().is_empty() {
+                None
+            } else {
+                check_query(query, data).err().map(|error| format!("{:#}", error))
+            };
+            ui.data().insert_temp(id, (query.clone(), error.clone()));
+            error
+        }
+    };
+    if let Some(error) = error {
+        next_row(ui);
+        ui.colored_label(egui::Color32::RED, error);
+    }
+}
+
+impl Interface for HighlightFilterVariants {
+    fn interface(&mut self, data: &PointData, ui: &mut egui::Ui, id: usize) {
+        match self {
+            HighlightFilterVariants::Selection(column, value) => {
+                let (is_equal, new_column, new_value) =
+                    selection_ui(true, column, value, data, ui, id);
+                *self = if is_equal {
+                    HighlightFilterVariants::Selection(new_column, new_value)
+                } else {
+                    HighlightFilterVariants::NotEqual(new_column, new_value)
+                };
+                delete_button(self, ui);
+            }
+            HighlightFilterVariants::NotEqual(column, value) => {
+                let (is_equal, new_column, new_value) =
+                    selection_ui(false, column, value, data, ui, id);
+                *self = if is_equal {
+                    HighlightFilterVariants::Selection(new_column, new_value)
+                } else {
+                    HighlightFilterVariants::NotEqual(new_column, new_value)
+                };
+                delete_button(self, ui);
+            }
+            HighlightFilterVariants::InSet(column, values) => {
+                let (new_column, new_values) = in_set_ui(column, values, data, ui, id);
+                *self = HighlightFilterVariants::InSet(new_column, new_values);
+                delete_button(self, ui);
+            }
+            HighlightFilterVariants::Pattern(column, pattern, interpretation) => {
+                let (new_column, new_pattern, new_interpretation) =
+                    pattern_ui(column, pattern, *interpretation, data, ui, id);
+                *self =
+                    HighlightFilterVariants::Pattern(new_column, new_pattern, new_interpretation);
+                delete_button(self, ui);
+            }
+            HighlightFilterVariants::Missing(column) => {
+                let (is_missing, new_column) = missing_ui(true, column, data, ui, id);
+                *self = if is_missing {
+                    HighlightFilterVariants::Missing(new_column)
+                } else {
+                    HighlightFilterVariants::NotMissing(new_column)
+                };
+                delete_button(self, ui);
+            }
+            HighlightFilterVariants::NotMissing(column) => {
+                let (is_missing, new_column) = missing_ui(false, column, data, ui, id);
+                *self = if is_missing {
+                    HighlightFilterVariants::Missing(new_column)
+                } else {
+                    HighlightFilterVariants::NotMissing(new_column)
+                };
+                delete_button(self, ui);
+            }
+            HighlightFilterVariants::LessThan(column, value) => {
+                let (is_less, new_column, new_value) = threshold_ui(
+                    true,
+                    &column.to_string(),
+                    *value,
+                    data,
+                    ui,
+                    id,
+                );
+                *self = if is_less {
+                    HighlightFilterVariants::LessThan(new_column, new_value)
+                } else {
+                    HighlightFilterVariants::GreaterThan(new_column, new_value)
+                };
+                delete_button(self, ui);
+            }
+            HighlightFilterVariants::GreaterThan(column, value) => {
+                let (is_less, new_column, new_value) = threshold_ui(
+                    false,
+                    &column.to_string(),
+                    *value,
+                    data,
+                    ui,
+                    id,
+                );
+                *self = if is_less {
+                    HighlightFilterVariants::LessThan(new_column, new_value)
+                } else {
+                    HighlightFilterVariants::GreaterThan(new_column, new_value)
+                };
+                delete_button(self, ui);
+            }
+            HighlightFilterVariants::Band(column, value, width) => {
+                let (new_column, new_value, new_width) = band_ui(
+                    &column.to_string(),
+                    *value,
+                    *width,
+                    data,
+                    ui,
+                    id,
+                );
+                *self = HighlightFilterVariants::Band(new_column, new_value, new_width);
+                delete_button(self, ui);
+            }
+            HighlightFilterVariants::Polygon(xcolumn, ycolumn, polygon) => {
+                let (new_xcolumn, new_ycolumn, new_polygon) =
+                    polygon_ui(xcolumn, ycolumn, polygon, data, ui, id);
+                *self = HighlightFilterVariants::Polygon(new_xcolumn, new_ycolumn, new_polygon);
+                delete_button(self, ui);
+            }
+            HighlightFilterVariants::Query(query) => {
+                query_ui(query, data, ui, id);
+                delete_button(self, ui);
+            }
+            HighlightFilterVariants::Group(filter) => {
+                ui.group(|ui| {
+                    egui::Grid::new(("Highlight nested filter grid", id)).show(ui, |ui| {
+                        filter.interface(data, ui, id);
+                    });
+                });
+                delete_button(self, ui);
+            }
+            // the clause controls are shown by the group
+            HighlightFilterVariants::Not(filter) | HighlightFilterVariants::Disabled(filter) => {
+                filter.interface(data, ui, id);
+            }
+            HighlightFilterVariants::Empty => {}
+        }
+    }
+}
+
+impl Interface for CombinedHighlightFilter {
+    fn interface(&mut self, data: &PointData, ui: &mut egui::Ui, id: usize) {
+        let count = self.filters.len();
+        let mut moved = None;
+        for (i, f) in self.filters.iter_mut().enumerate() {
+            let (mut filter, mut enabled, mut negated) =
+                std::mem::replace(f, HighlightFilterVariants::Empty).into_clause();
+            ui.horizontal(|ui| {
+                let handle = ui.label("☰");
+                let handle = ui
+                    .interact(
+                        handle.rect,
+                        egui::Id::new(("Highlight clause", id, i)),
+                        egui::Sense::drag(),
+                    )
+                    .on_hover_text("Drag to reorder");
+                if handle.dragged() {
+                    if let Some(pointer) = ui.ctx().pointer_interact_pos() {
+                        if pointer.y < handle.rect.top() && i > 0 {
+                            moved = Some((i, i - 1));
+                        } else if pointer.y > handle.rect.bottom() && i + 1 < count {
+                            moved = Some((i, i + 1));
+                        }
+                    }
+                }
+                ui.checkbox(&mut enabled, "")
+                    .on_hover_text("Apply the filter");
+                ui.toggle_value(&mut negated, "NOT")
+                    .on_hover_text("Highlight the points not matching the filter");
+            });
+            filter.interface(data, ui, id.wrapping_mul(103).wrapping_add(i));
+            *f = HighlightFilterVariants::clause(filter, enabled, negated);
+            ui.end_row();
+        }
+        if let Some((i, j)) = moved {
+            self.filters.swap(i, j);
+            ui.memory()
+                .set_dragged_id(egui::Id::new(("Highlight clause", id, j)));
+        }
+        self.tidy();
+        ui.label("");
+        ui.label("Add");
+        ui.horizontal(|ui| {
+            if ui.button("=").clicked() {
+                self.filters.push(HighlightFilterVariants::Selection(
+                    "".to_string(),
+                    "".to_string(),
+                ))
+            }
+            if ui.button("≠").clicked() {
+                self.filters.push(HighlightFilterVariants::NotEqual(
+                    "".to_string(),
+                    "".to_string(),
+                ))
+            }
+            if ui.button("∈").on_hover_text("One of several values").clicked() {
+                self.filters
+                    .push(HighlightFilterVariants::InSet("".to_string(), Vec::new()))
+            }
+            if ui.button("~").on_hover_text("Text pattern").clicked() {
+                self.filters.push(HighlightFilterVariants::Pattern(
+                    "".to_string(),
+                    "".to_string(),
+                    Interpretation::Contains,
+                ))
+            }
+            if ui.button("∅").on_hover_text("Missing value").clicked() {
+                self.filters
+                    .push(HighlightFilterVariants::Missing("".to_string()))
+            }
+            if ui.button("<").clicked() {
+                self.filters
+                    .push(HighlightFilterVariants::LessThan("".to_string(), 0.0))
+            }
+            if ui.button(">").clicked() {
+                self.filters
+                    .push(HighlightFilterVariants::GreaterThan("".to_string(), 0.0))
+            }
+            if ui.button(BAND).clicked() {
+                self.filters
+                    .push(HighlightFilterVariants::Band("".to_string(), 0.0, 0.0))
+            }
+            if ui.button("Query").clicked() {
+                self.filters
+                    .push(HighlightFilterVariants::Query("".to_string()))
+            }
+            if ui.button(POLYGON).clicked() {
+                self.filters.push(HighlightFilterVariants::Polygon(
+                    "".to_string(),
+                    "".to_string(),
+                    Vec::new(),
+                ))
+            }
+            if ui.button("Group").clicked() {
+                let operator = match self.operator {
+                    Operator::And => Operator::Or,
+                    Operator::Or => Operator::And,
+                };
+                self.filters
+                    .push(HighlightFilterVariants::Group(CombinedHighlightFilter {
+                        operator,
+                        filters: Vec::new(),
+                    }))
+            }
+        });
+        ui.end_row();
+        let single_query = matches!(
+            self.filters.as_slice(),
+            [HighlightFilterVariants::Query(_)]
+        );
+        // disabled filters have no query, keep them
+        let all_enabled = self.filters.iter().all(|filter| filter.is_enabled());
+        let query = self.to_query().filter(|_| !single_query && all_enabled);
+        let filters = match self.filters.as_slice() {
+            [HighlightFilterVariants::Query(query)] => CombinedHighlightFilter::from_query(query)
+                .ok()
+                .filter(|filter| filter.filters != self.filters),
+            _ => None,
+        };
+        ui.horizontal(|ui| {
+            ui.label("Operator: ");
+            match self.operator {
+                Operator::And => {
+                    if ui.button("AND").clicked() {
+                        self.operator = Operator::Or;
+                    }
+                }
+                Operator::Or => {
+                    if ui.button("OR").clicked() {
+                        self.operator = Operator::And;
+                    }
+                }
+            }
+            if let Some(query) = query {
+                if ui
+                    .button("To query")
+                    .on_hover_text("Replace the filters by an equivalent text query")
+                    .clicked()
+                {
+                    *self = CombinedHighlightFilter {
+                        operator: Operator::And,
+                        filters: vec![HighlightFilterVariants::Query(query)],
+                    };
+                }
+            }
+            if let Some(filters) = filters {
+                if ui
+                    .button("To filters")
+                    .on_hover_text("Split the query into separate filters")
+                    .clicked()
+                {
+                    *self = filters;
+                }
+            }
+        });
+        ui.end_row();
+    }
+}
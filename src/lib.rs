@@ -0,0 +1,21 @@
+//! Core of the Pointcloud viewer: data loading, transformations, highlight filters,
+//! density meshes and statistics. The GUI lives in the `pointcloud-viewer` binary.
+#[macro_use]
+extern crate serde_derive;
+#[cfg(feature = "arrow")]
+pub mod arrow_data;
+pub mod axis;
+pub mod cli;
+pub mod colormap;
+pub mod column_filter;
+pub mod csv_loader;
+pub mod datetime;
+pub mod erf;
+pub mod evaluate;
+pub mod highlight;
+pub mod measures;
+pub mod mesh;
+pub mod pipeline;
+pub mod pointdata;
+pub mod session;
+pub mod transform;
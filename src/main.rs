@@ -1,68 +1,393 @@
 #![allow(dead_code)]
-#[macro_use]
-extern crate serde_derive;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use egui::containers::ScrollArea;
 use macroquad::prelude::*;
-use std::collections::HashMap;
-mod column_filter;
-mod highlight;
-mod measures;
-mod mesh;
-mod pipeline;
-mod pointdata;
-mod transform;
-mod erf;
-mod evaluate;
-use column_filter::*;
-use highlight::*;
-use mesh::HighlightType;
-use pipeline::*;
+mod interface;
+mod open_dialog;
+use interface::*;
+use open_dialog::OpenFileDialog;
+use pointcloud_viewer::cli::*;
+use pointcloud_viewer::colormap::Colormap;
+use pointcloud_viewer::column_filter::*;
+use pointcloud_viewer::csv_loader::{CsvLoader, CsvOptions};
+use pointcloud_viewer::evaluate::{DerivedColumn, FUNCTIONS};
+use pointcloud_viewer::highlight::*;
+use pointcloud_viewer::measures;
+use pointcloud_viewer::mesh::{HighlightComposition, HighlightType, ValueStatistic};
+use pointcloud_viewer::pipeline::*;
+use pointcloud_viewer::pointdata::{FileFormat, PointData};
+use pointcloud_viewer::session::{Session, StatisticsSettings};
 
 
-fn window_conf() -> Conf {
+/// Number of CSV rows parsed per frame while loading.
+const LOADING_CHUNK_ROWS: usize = 50000;
+/// Approximate number of ticks on the axes.
+const AXIS_TICKS: usize = 8;
+/// Number of colors and ticks of the color-bar legend.
+const COLOR_BAR_STEPS: usize = 64;
+const COLOR_BAR_TICKS: usize = 5;
+
+/// Mouse tool selecting points on the plot into the selected highlight group.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SelectionTool {
+    Off,
+    Rectangle,
+    Lasso,
+}
+
+impl SelectionTool {
+    /// Polygon of the selection from the points dragged by the mouse.
+    fn polygon(&self, points: &[(f32, f32)]) -> Vec<(f32, f32)> {
+        match (self, points) {
+            (SelectionTool::Rectangle, [(x1, y1), .., (x2, y2)]) => {
+                vec![(*x1, *y1), (*x2, *y1), (*x2, *y2), (*x1, *y2)]
+            }
+            _ => points.to_vec(),
+        }
+    }
+}
+
+/// Area of a polygon, used to ignore clicks without dragging.
+fn polygon_area(polygon: &[(f32, f32)]) -> f32 {
+    let mut area = 0.0;
+    for (i, &(x1, y1)) in polygon.iter().enumerate() {
+        let (x2, y2) = polygon[(i + 1) % polygon.len()];
+        area += x1 * y2 - x2 * y1;
+    }
+    (area / 2.0).abs()
+}
+
+fn window_conf(arguments: &Arguments) -> Conf {
     Conf {
         window_title: "Pointcloud Viewer".to_owned(),
         fullscreen: false,
         window_resizable: true,
-        window_width: 1200,
-        window_height: 820,
+        window_width: arguments.window_width,
+        window_height: arguments.window_height,
         ..Default::default()
     }
 }
 
-#[macroquad::main(window_conf)]
-async fn main() -> Result<()> {
+fn exit_with_error(error: anyhow::Error) -> ! {
+    eprintln!("Error: {:#}", error);
+    std::process::exit(1);
+}
+
+fn main() {
+    let mut arguments = match Arguments::from_env() {
+        Ok(arguments) => arguments,
+        Err(error) => {
+            eprintln!("Error: {}\n\n{}", error, USAGE);
+            std::process::exit(2);
+        }
+    };
+    if arguments.help {
+        println!("{}", USAGE);
+        return;
+    }
+    let session = match arguments.load_session() {
+        Ok(session) => session,
+        Err(error) => exit_with_error(error),
+    };
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Err(error) = arguments.check_input() {
+        exit_with_error(error);
+    }
+    if let Some(output) = arguments.output.as_deref() {
+        if let Err(error) = arguments.render(session.as_ref(), output) {
+            exit_with_error(error);
+        }
+        return;
+    }
+    macroquad::Window::from_config(window_conf(&arguments), async move {
+        if let Err(error) = viewer(arguments, session).await {
+            exit_with_error(error);
+        }
+    });
+}
+
+/// Load a CSV file in chunks, one chunk per frame, showing the progress.
+/// When the loading is cancelled, the rows loaded so far are used.
+async fn load_csv_with_progress<R: std::io::Read + std::io::Seek>(
+    path: &str,
+    reader: R,
+    size: u64,
+    options: &CsvOptions,
+) -> Result<PointData> {
+    let mut loader = CsvLoader::with_options(reader, options)?.with_size(size);
+    let mut cancelled = false;
+    while !cancelled && !loader.read_chunk(LOADING_CHUNK_ROWS)? {
+        clear_background(Color::from_rgba(0x12, 0x12, 0x12, 0xff));
+        egui_macroquad::ui(|egui_ctx| {
+            egui::Window::new("Loading")
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .collapsible(false)
+                .resizable(false)
+                .show(egui_ctx, |ui| {
+                    ui.label(path);
+                    ui.add(
+                        egui::ProgressBar::new(loader.progress().unwrap_or(0.0) as f32)
+                            .show_percentage(),
+                    );
+                    ui.label(format!("{} rows", loader.len()));
+                    if ui
+                        .button("Cancel")
+                        .on_hover_text("Stop loading and show the rows loaded so far")
+                        .clicked()
+                    {
+                        cancelled = true;
+                    }
+                });
+        });
+        egui_macroquad::draw();
+        next_frame().await;
+    }
+    Ok(loader.finish())
+}
+
+/// Load a file into the pipeline; CSV files are loaded with a progress window,
+/// streamed from the file on native builds.
+async fn load_into_pipeline(
+    pipeline: &mut Pipeline,
+    path: &str,
+    options: &CsvOptions,
+    columns: Option<&[&str]>,
+) -> Result<()> {
+    if FileFormat::from_path(path) == FileFormat::Csv {
+        #[cfg(not(target_arch = "wasm32"))]
+        let (reader, size) = {
+            let file = std::fs::File::open(path)
+                .with_context(|| format!("Failed to open '{}'", path))?;
+            let size = file.metadata()?.len();
+            (std::io::BufReader::new(file), size)
+        };
+        #[cfg(target_arch = "wasm32")]
+        let (reader, size) = {
+            let content = load_file(path)
+                .await
+                .with_context(|| format!("Failed to load '{}'", path))?;
+            let size = content.len() as u64;
+            (std::io::Cursor::new(content), size)
+        };
+        let mut point_data = load_csv_with_progress(path, reader, size, options)
+            .await
+            .with_context(|| format!("Failed to load '{}'", path))?;
+        if let Some(columns) = columns {
+            point_data.select_columns(columns)?;
+        }
+        pipeline.set_point_data(point_data);
+    } else {
+        #[cfg(not(target_arch = "wasm32"))]
+        pipeline
+            .load(path, options, columns)
+            .with_context(|| format!("Failed to load '{}'", path))?;
+        #[cfg(target_arch = "wasm32")]
+        {
+            let content = load_file(path)
+                .await
+                .with_context(|| format!("Failed to load '{}'", path))?;
+            pipeline
+                .load_file_content(path, content, options, columns)
+                .with_context(|| format!("Failed to load '{}'", path))?;
+        }
+    }
+    Ok(())
+}
+
+/// Load a session file with its data file and restore the view.
+async fn restore_session(pipeline: &mut Pipeline, path: &str) -> Result<Session> {
+    let session = Session::load(path)?;
+    load_into_pipeline(
+        pipeline,
+        &session.input,
+        &session.csv_options,
+        session.columns().as_deref(),
+    )
+    .await?;
+    session.apply(pipeline)?;
+    Ok(session)
+}
+
+/// Draw the axis ticks and their labels inside the edges of the plot.
+fn draw_axes(pipeline: &Pipeline, margin: f32, width: f32, height: f32) {
+    let font_size = 16.0;
+    let color = Color::from_rgba(200, 200, 200, 255);
+    for tick in pipeline.x_ticks(AXIS_TICKS) {
+        let x = margin + tick.position as f32 * width;
+        draw_line(x, margin + height - 6.0, x, margin + height, 1.0, color);
+        let text_width = measure_text(&tick.label, None, font_size as u16, 1.0).width;
+        let x = (x - text_width / 2.0)
+            .min(margin + width - text_width - 2.0)
+            .max(margin + 2.0);
+        draw_text(&tick.label, x, margin + height - 10.0, font_size, color);
+    }
+    for tick in pipeline.y_ticks(AXIS_TICKS) {
+        let y = margin + tick.position as f32 * height;
+        draw_line(margin, y, margin + 6.0, y, 1.0, color);
+        // leave space for the column name and the x axis labels
+        if y > margin + 40.0 && y < margin + height - 24.0 {
+            draw_text(&tick.label, margin + 10.0, y + 5.0, font_size, color);
+        }
+    }
+}
+
+/// Draw the color-bar legend of the densities in the top right corner of the plot.
+fn draw_color_bar(pipeline: &Pipeline, margin: f32, width: f32) {
+    let font_size = 16.0;
+    let color = Color::from_rgba(200, 200, 200, 255);
+    let color_bar = pipeline.color_bar(COLOR_BAR_STEPS, COLOR_BAR_TICKS);
+    let (bar_width, bar_height) = (12.0, 160.0);
+    let x = margin + width - bar_width - 10.0;
+    let top = margin + 34.0;
+    draw_rectangle(
+        x - 64.0,
+        top - 28.0,
+        bar_width + 72.0,
+        bar_height + 38.0,
+        Color::from_rgba(0x12, 0x12, 0x12, 0xc0),
+    );
+    draw_text("Density", x - 56.0, top - 10.0, font_size, color);
+    let step = bar_height / color_bar.colors.len() as f32;
+    for (i, [red, green, blue]) in color_bar.colors.iter().enumerate() {
+        let y = top + bar_height - (i + 1) as f32 * step;
+        draw_rectangle(
+            x,
+            y,
+            bar_width,
+            step + 0.5,
+            Color::from_rgba(*red, *green, *blue, 255),
+        );
+    }
+    for tick in color_bar.ticks.iter() {
+        let y = top + bar_height * (1.0 - tick.position as f32);
+        draw_line(x - 4.0, y, x, y, 1.0, color);
+        let text_width = measure_text(&tick.label, None, font_size as u16, 1.0).width;
+        draw_text(&tick.label, x - 6.0 - text_width, y + 5.0, font_size, color);
+    }
+}
+
+async fn viewer(arguments: Arguments, session: Option<Session>) -> Result<()> {
     let mut pipeline = Pipeline::new();
+    let mut texture: Option<Texture2D> = None;
     let mut mouse_origin = None;
-    pipeline.load("data.csv").await?;
+    load_into_pipeline(
+        &mut pipeline,
+        &arguments.input,
+        &arguments.csv_options,
+        arguments.columns().as_deref(),
+    )
+    .await?;
+    if let Some(session) = &session {
+        session.apply(&mut pipeline)?;
+    }
+    arguments.apply(&mut pipeline)?;
+    let colormaps = arguments.colormaps()?;
     //    println!("{}", pipeline.point_data.to_csv_simple());
     let margin = 6.0f32;
     let size_x = pipeline.parameters.mesh_width as f32;
     let size_y = pipeline.parameters.mesh_height as f32;
-    let mut statistics = None;
+    let mut statistics: Option<Vec<Vec<String>>> = None;
+    // the statistics are computed once after the pipeline run, when they are missing or dirty
+    let mut statistics_dirty = false;
     let mut enable_data_display = true;
-    let mut enable_statistics = false;
     let mut enable_column_selector = false;
     let mut column_selection = String::new();
     let mut enable_highlight = false;
-    let mut highlight_filter = CombinedHighlightFilter::new();
-    let mut stat_all = true;
-    let mut stat_highlighted = true;
-    let mut stat_non_highlighted = true;
+    let mut enable_row_filter = false;
+    let mut selected_group = 0;
+    let mut selection_tool = SelectionTool::Off;
+    let mut selection: Vec<(f32, f32)> = Vec::new();
+    let mut pointer_over_ui = false;
+    let mut enable_open_dialog = false;
+    let mut open_dialog = OpenFileDialog::new(&arguments.input, &arguments.csv_options);
+    let mut input = arguments.input.clone();
+    let mut csv_options = arguments.csv_options.clone();
+    let mut columns = arguments.columns.clone();
+    let mut enable_session = false;
+    let mut session_path = arguments
+        .session
+        .clone()
+        .unwrap_or_else(|| "session.yaml".to_owned());
+    let mut session_message: Option<Result<String, String>> = None;
+    let mut enable_new_column = false;
+    let mut new_column = DerivedColumn::new("", "");
+    let mut new_column_message: Option<Result<String, String>> = None;
+    let mut enable_statistics = false;
+    let mut statistics_settings = StatisticsSettings::new();
     let measure_names = measures::NumericStatistics::new().all_measure_names();
-    let mut enable_measure:HashMap<String, bool> = HashMap::new();
     let mut selected_x=-1.0f64;
     let mut selected_y=-1.0f64;
-
-    for measure in measure_names.iter() {
-        enable_measure.insert(measure.to_string(), measure=="Mean");
+    if let Some(session) = session {
+        statistics_settings = session.statistics;
+        enable_statistics = statistics_settings.enabled;
     }
 
     loop {
         //        clear_background(DARKBLUE);
         clear_background(Color::from_rgba(0x12, 0x12, 0x12, 0xff));
+        let mut open_file = false;
+        let mut save_session = false;
+        let mut load_session = false;
         egui_macroquad::ui(|egui_ctx| {
+            pointer_over_ui = egui_ctx.is_pointer_over_area();
+            open_file = open_dialog.show(egui_ctx, &mut enable_open_dialog);
+            egui::Window::new("Session")
+                .open(&mut enable_session)
+                .collapsible(false)
+                .show(egui_ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("File");
+                        ui.add(egui::TextEdit::singleline(&mut session_path).desired_width(300.0))
+                            .on_hover_text("Saved as JSON if the name ends with .json, as YAML otherwise");
+                    });
+                    ui.horizontal(|ui| {
+                        save_session = ui.button("Save").clicked();
+                        load_session = ui.button("Load").clicked();
+                    });
+                    match &session_message {
+                        Some(Ok(message)) => {
+                            ui.label(message);
+                        }
+                        Some(Err(error)) => {
+                            ui.colored_label(egui::Color32::RED, error);
+                        }
+                        None => {}
+                    }
+                });
+            egui::Window::new("New column")
+                .open(&mut enable_new_column)
+                .collapsible(false)
+                .show(egui_ctx, |ui| {
+                    egui::Grid::new("New column grid").show(ui, |ui| {
+                        ui.label("Name");
+                        ui.add(egui::TextEdit::singleline(&mut new_column.name).desired_width(300.0));
+                        ui.end_row();
+                        ui.label("Expression");
+                        ui.add(egui::TextEdit::multiline(&mut new_column.expression).desired_width(300.0).desired_rows(2))
+                            .on_hover_text("e.g. log(price / area) or if(country == \"DE\", 1, 0)\n\
+                                Operators: + - * / % ^ == != < <= > >= and or not\n\
+                                Texts in quotes \"...\", other column names in backticks `...`");
+                        ui.end_row();
+                    });
+                    ui.label(format!("Functions: {}", FUNCTIONS.join(", ")));
+                    if ui.button("Create").clicked() {
+                        new_column_message = Some(
+                            pipeline
+                                .add_derived_column(new_column.clone())
+                                .map(|()| format!("Created '{}'", new_column.name.trim()))
+                                .map_err(|error| format!("{:#}", error)),
+                        );
+                    }
+                    match &new_column_message {
+                        Some(Ok(message)) => {
+                            ui.label(message);
+                        }
+                        Some(Err(error)) => {
+                            ui.colored_label(egui::Color32::RED, error);
+                        }
+                        None => {}
+                    }
+                });
             egui::Window::new("Select columns")
                 .open(&mut enable_column_selector)
                 .default_pos((2.0 * margin + size_x, 320.0))
@@ -88,6 +413,24 @@ async fn main() -> Result<()> {
                     //ui.label("Test");
 
                     ui.horizontal(|ui| {
+                        if ui
+                            .add(egui::SelectableLabel::new(enable_open_dialog, "Open file"))
+                            .clicked()
+                        {
+                            enable_open_dialog = !enable_open_dialog;
+                        };
+                        if ui
+                            .add(egui::SelectableLabel::new(enable_session, "Session"))
+                            .clicked()
+                        {
+                            enable_session = !enable_session;
+                        };
+                        if ui
+                            .add(egui::SelectableLabel::new(enable_new_column, "New column"))
+                            .clicked()
+                        {
+                            enable_new_column = !enable_new_column;
+                        };
                         if ui
                             .add(egui::SelectableLabel::new(
                                 enable_column_selector,
@@ -97,6 +440,12 @@ async fn main() -> Result<()> {
                         {
                             enable_column_selector = !enable_column_selector;
                         };
+                        if ui
+                            .add(egui::SelectableLabel::new(enable_row_filter, "Row filter"))
+                            .clicked()
+                        {
+                            enable_row_filter = !enable_row_filter;
+                        };
                         if ui
                             .add(egui::SelectableLabel::new(enable_highlight, "Highlight"))
                             .clicked()
@@ -117,11 +466,13 @@ async fn main() -> Result<()> {
                             .clicked()
                         {
                             enable_statistics = !enable_statistics;
-                            if enable_statistics && statistics.is_none() {
-                                statistics = Some(pipeline.statistics(selected_x, selected_y));
-                            }
                         };
                     });
+                    ui.label(format!(
+                        "{} of {} rows shown",
+                        pipeline.shown_count(),
+                        pipeline.point_data.len()
+                    ));
                     ui.separator();
                     egui::Grid::new("Coordinates grid").show(ui, |ui| {
                         if ui.button("Zoom all").clicked() {
@@ -222,6 +573,20 @@ async fn main() -> Result<()> {
                                 }
                                 pipeline.set_weight_column(weight_column);
                             });
+                        egui::ComboBox::from_label("Color by")
+                            .selected_text(pipeline.color_column())
+                            .show_ui(ui, |ui| {
+                                let mut color_column = pipeline.color_column().to_owned();
+                                ui.selectable_value(&mut color_column, "".to_string(), "");
+                                for column in pipeline.point_data.headers.iter() {
+                                    ui.selectable_value(
+                                        &mut color_column,
+                                        column.to_string(),
+                                        column,
+                                    );
+                                }
+                                pipeline.set_color_column(color_column);
+                            });
                         ui.end_row();
                         let mut gaussian_points = pipeline.gaussian_points();
                         ui.checkbox(&mut gaussian_points, "Gaussian points");
@@ -230,7 +595,16 @@ async fn main() -> Result<()> {
                         let mut point_sigma = pipeline.point_sigma();
                         ui.add(egui::Slider::new(&mut point_sigma, 0.0..=10.0));
                         pipeline.set_point_sigma(point_sigma);
+                        ui.end_row();
 
+                        ui.label("");
+                        let mut blur_mesh = pipeline.blur_mesh();
+                        ui.add_enabled(
+                            pipeline.gaussian_points(),
+                            egui::Checkbox::new(&mut blur_mesh, "Blur binned points"),
+                        )
+                        .on_hover_text("Faster for many points, but without subpixel positions");
+                        pipeline.set_blur_mesh(blur_mesh);
                         ui.end_row();
                         ui.label("Brighthess:");
                         let mut density_multiplier = pipeline.density_multiplier();
@@ -243,6 +617,87 @@ async fn main() -> Result<()> {
                         ui.add(egui::Slider::new(&mut contrast, 0.1..=10.0));
                         pipeline.set_contrast(contrast);
                         ui.end_row();
+
+                        let mut colormap = pipeline.colormap().clone();
+                        egui::ComboBox::from_label("Colormap")
+                            .selected_text(&colormap.name)
+                            .show_ui(ui, |ui| {
+                                for available in colormaps.iter() {
+                                    if ui
+                                        .selectable_label(
+                                            available.name == colormap.name,
+                                            &available.name,
+                                        )
+                                        .clicked()
+                                    {
+                                        colormap = Colormap {
+                                            reversed: colormap.reversed,
+                                            ..available.clone()
+                                        };
+                                    }
+                                }
+                            });
+                        ui.checkbox(&mut colormap.reversed, "Reversed");
+                        pipeline.set_colormap(colormap);
+                        ui.end_row();
+
+                        egui::ComboBox::from_label("Value")
+                            .selected_text(pipeline.value_column())
+                            .show_ui(ui, |ui| {
+                                let mut value_column = pipeline.value_column().to_owned();
+                                ui.selectable_value(&mut value_column, "".to_string(), "");
+                                for column in pipeline.point_data.headers.iter() {
+                                    ui.selectable_value(
+                                        &mut value_column,
+                                        column.to_string(),
+                                        column,
+                                    );
+                                }
+                                pipeline.set_value_column(value_column);
+                            });
+                        egui::ComboBox::from_label("Statistic")
+                            .selected_text(pipeline.value_statistic().text())
+                            .show_ui(ui, |ui| {
+                                let mut value_statistic = pipeline.value_statistic();
+                                for statistic in [
+                                    ValueStatistic::Mean,
+                                    ValueStatistic::Median,
+                                    ValueStatistic::Max,
+                                ]
+                                .iter()
+                                {
+                                    ui.selectable_value(
+                                        &mut value_statistic,
+                                        *statistic,
+                                        statistic.text(),
+                                    );
+                                }
+                                pipeline.set_value_statistic(value_statistic);
+                            });
+                        ui.end_row();
+
+                        let mut value_colormap = pipeline.value_colormap().clone();
+                        egui::ComboBox::from_label("Value colormap")
+                            .selected_text(&value_colormap.name)
+                            .show_ui(ui, |ui| {
+                                for available in colormaps.iter() {
+                                    if ui
+                                        .selectable_label(
+                                            available.name == value_colormap.name,
+                                            &available.name,
+                                        )
+                                        .clicked()
+                                    {
+                                        value_colormap = Colormap {
+                                            reversed: value_colormap.reversed,
+                                            ..available.clone()
+                                        };
+                                    }
+                                }
+                            });
+                        ui.checkbox(&mut value_colormap.reversed, "Reversed values");
+                        pipeline.set_value_colormap(value_colormap);
+                        ui.end_row();
                     });
 
                     //                    dbg!(&ui.input().pointer.hover_pos());
@@ -282,7 +737,7 @@ async fn main() -> Result<()> {
                 .open(&mut enable_statistics)
                 .default_pos((2.0 * margin + size_x, 380.0))
                 .show(egui_ctx, |ui| {
-                    if ui.input().pointer.any_released() {
+                    if ui.input().pointer.any_released() && selection_tool == SelectionTool::Off {
                         if let (Some(origin), Some(release)) =
                             (mouse_origin, ui.input().pointer.interact_pos())
                         {
@@ -298,7 +753,7 @@ async fn main() -> Result<()> {
                                 pipeline.relative_offset(dx, dy);
                                 selected_x = x2 as f64;
                                 selected_y = y2 as f64;
-                                statistics = Some(pipeline.statistics(selected_x, selected_y));
+                                statistics_dirty = true;
                             }
                         }
                     }
@@ -308,28 +763,35 @@ async fn main() -> Result<()> {
                         stat = s.clone();
                     }
                     egui::Grid::new("Statistics config").show(ui, |ui|{
-                        ui.checkbox(&mut stat_all, ALL);
-                        ui.checkbox(&mut stat_highlighted, HIGHLIGHTED);
-                        ui.checkbox(&mut stat_non_highlighted, NON_HIGHLIGHTED);
+                        ui.checkbox(&mut statistics_settings.all, ALL);
+                        ui.checkbox(&mut statistics_settings.highlighted, HIGHLIGHTED);
+                        ui.checkbox(&mut statistics_settings.non_highlighted, NON_HIGHLIGHTED);
                         ui.end_row();
                         for (i,name) in measure_names.iter().enumerate() {
-                            let flag = enable_measure.entry(name.to_string()).or_insert(true);
-                            ui.checkbox(&mut *flag, name);
+                            let enabled = statistics_settings.measures.contains(name);
+                            let mut flag = enabled;
+                            ui.checkbox(&mut flag, name);
+                            if flag && !enabled {
+                                statistics_settings.measures.push(name.to_string());
+                            } else if !flag && enabled {
+                                statistics_settings.measures.retain(|x| x != name);
+                            }
                             if i%4==3{
                                 ui.end_row();
                             }
-                            if !*flag{
+                            if !flag{
                                 stat = stat.iter().filter(|x| &x[1]!=name).map(|x| x.to_owned()).collect();
                             }
                         }
                     });
-                    if !stat_all{
+                    if !statistics_settings.all{
                         stat = stat.iter().filter(|x| x[0]!=ALL).map(|x| x.to_owned()).collect();
                     }
-                    if !stat_highlighted{
-                        stat = stat.iter().filter(|x| x[0]!=HIGHLIGHTED).map(|x| x.to_owned()).collect();
+                    if !statistics_settings.highlighted{
+                        // columns of the highlight groups
+                        stat = stat.iter().filter(|x| x[0]==ALL || x[0]==NON_HIGHLIGHTED).map(|x| x.to_owned()).collect();
                     }
-                    if !stat_non_highlighted{
+                    if !statistics_settings.non_highlighted{
                         stat = stat.iter().filter(|x| x[0]!=NON_HIGHLIGHTED).map(|x| x.to_owned()).collect();
                     }
                     
@@ -350,14 +812,92 @@ async fn main() -> Result<()> {
                             });
                     });
                 });
+            if !pipeline.color_column().is_empty() {
+                egui::Window::new("Legend")
+                    .default_pos((2.0 * margin + size_x, 260.0))
+                    .show(egui_ctx, |ui| {
+                        ui.label(pipeline.color_column());
+                        if !pipeline.color_by_categories() {
+                            ui.label(format!(
+                                "More than {} values, choose a column with fewer categories",
+                                MAX_COLOR_CATEGORIES
+                            ));
+                        }
+                        egui::Grid::new("Legend grid").show(ui, |ui| {
+                            for category in pipeline.categories.clone() {
+                                let mut visible = category.visible;
+                                ui.checkbox(&mut visible, "");
+                                let [red, green, blue] = category.color.0;
+                                ui.colored_label(egui::Color32::from_rgb(red, green, blue), "■");
+                                ui.label(&category.name);
+                                ui.label(category.count.to_string());
+                                pipeline.set_category_visible(&category.name, visible);
+                                ui.end_row();
+                            }
+                        });
+                    });
+            }
+            egui::Window::new("Row Filter")
+                .open(&mut enable_row_filter)
+                .default_pos((2.0 * margin + size_x, 280.0))
+                .show(egui_ctx, |ui| {
+                    ui.label(format!(
+                        "{} of {} rows shown",
+                        pipeline.shown_count(),
+                        pipeline.point_data.len()
+                    ));
+                    ui.label("Rows not matching the filter are removed from the plot and the statistics.");
+                    ui.separator();
+                    let mut row_filter = pipeline.row_filter.clone();
+                    egui::Grid::new("Row filter grid").show(ui, |ui| {
+                        // distinct from the ids of the highlight groups
+                        row_filter.interface(&pipeline.point_data, ui, usize::MAX);
+                    });
+                    if row_filter != pipeline.row_filter {
+                        pipeline.set_row_filter(row_filter);
+                    }
+                });
             egui::Window::new("Highlight Filter")
                 .open(&mut enable_highlight)
                 .default_pos((2.0 * margin + size_x, 320.0))
                 .show(egui_ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Select on plot:");
+                        ui.radio_value(&mut selection_tool, SelectionTool::Off, "Off");
+                        ui.radio_value(&mut selection_tool, SelectionTool::Rectangle, "Rectangle");
+                        ui.radio_value(&mut selection_tool, SelectionTool::Lasso, "Lasso");
+                    });
+                    let mut groups = pipeline.highlight_groups.clone();
+                    let mut remove_group = None;
+                    egui::Grid::new("Highlight groups grid").show(ui, |ui| {
+                        for (i, group) in groups.iter_mut().enumerate() {
+                            ui.radio_value(&mut selected_group, i, "");
+                            ui.text_edit_singleline(&mut group.name);
+                            ui.color_edit_button_srgb(&mut group.color.0);
+                            if ui.button("🗙").clicked() {
+                                remove_group = Some(i);
+                            }
+                            ui.end_row();
+                        }
+                    });
+                    if let Some(i) = remove_group {
+                        groups.remove(i);
+                    }
+                    if ui.button("Add group").clicked() {
+                        groups.push(HighlightGroup::new(groups.len()));
+                        selected_group = groups.len() - 1;
+                    }
+                    selected_group = selected_group.min(groups.len().max(1) - 1);
+                    ui.separator();
                     egui::Grid::new("Highlight filter grid").show(ui, |ui| {
-                        highlight_filter.interface(&pipeline.point_data, ui, 0);
-                        pipeline.set_highlights(highlight_filter.filter(&pipeline.point_data));
-                        ui.end_row();
+                        if let Some(group) = groups.get_mut(selected_group) {
+                            group.filter.interface(&pipeline.point_data, ui, selected_group);
+                            ui.end_row();
+                        }
+                        // the statistics are updated only when the highlights or their names change
+                        if pipeline.set_highlight_groups(groups) {
+                            statistics_dirty = true;
+                        }
                         ui.label("");
                         let mut highlight_type = pipeline.highlight_type();
                         ui.radio_value(&mut highlight_type, HighlightType::Highlight, "Highlight");
@@ -379,12 +919,89 @@ async fn main() -> Result<()> {
                             "Non-highlighted only",
                         );
                         pipeline.set_highlight_type(highlight_type);
-                        statistics = Some(pipeline.statistics(selected_x, selected_y));  
+                        ui.end_row();
+                        ui.label("Groups:");
+                        let mut composition = pipeline.highlight_composition();
+                        ui.radio_value(&mut composition, HighlightComposition::Additive, "Additive");
+                        ui.radio_value(&mut composition, HighlightComposition::Dominance, "Dominance");
+                        pipeline.set_highlight_composition(composition);
                     });
                 });
         });
-        pipeline.run();
-        if let Some(texture) = pipeline.texture {
+        if save_session {
+            let session = Session {
+                input: input.clone(),
+                csv_options: csv_options.clone(),
+                columns: columns.clone(),
+                statistics: StatisticsSettings {
+                    enabled: enable_statistics,
+                    ..statistics_settings.clone()
+                },
+                ..Session::from_pipeline(&pipeline)
+            };
+            session_message = Some(
+                session
+                    .save(&session_path)
+                    .map(|()| format!("Saved '{}'", session_path))
+                    .map_err(|error| format!("{:#}", error)),
+            );
+        }
+        if load_session {
+            match restore_session(&mut pipeline, &session_path).await {
+                Ok(session) => {
+                    input = session.input;
+                    csv_options = session.csv_options;
+                    columns = session.columns;
+                    open_dialog = OpenFileDialog::new(&input, &csv_options);
+                    statistics_settings = session.statistics;
+                    enable_statistics = statistics_settings.enabled;
+                    statistics = None;
+                    session_message = Some(Ok(format!("Loaded '{}'", session_path)));
+                }
+                Err(error) => session_message = Some(Err(format!("{:#}", error))),
+            }
+        }
+        if open_file {
+            let path = open_dialog.path.clone();
+            let options = open_dialog.options.clone();
+            match load_into_pipeline(&mut pipeline, &path, &options, None).await {
+                Ok(()) => {
+                    input = path;
+                    csv_options = options;
+                    columns = None;
+                    open_dialog.error = None;
+                    enable_open_dialog = false;
+                    pipeline.set_highlight_groups(HighlightGroup::default_groups());
+                    pipeline.set_row_filter(CombinedHighlightFilter::new());
+                    statistics = None;
+                    enable_statistics = false;
+                }
+                Err(error) => open_dialog.error = Some(format!("{:#}", error)),
+            }
+        }
+        // the statistics depend on the data, the highlights and the index mesh (selected row),
+        // not on the colors
+        if matches!(pipeline.stage, Stage::Stage0NewData | Stage::Stage1XYI) {
+            statistics_dirty = true;
+        }
+        if pipeline.run() {
+            if let Some(old_texture) = texture {
+                old_texture.delete();
+            }
+            texture = Some(Texture2D::from_rgba8(
+                pipeline.mesh.width as u16,
+                pipeline.mesh.height as u16,
+                &pipeline.mesh.rgba8,
+            ));
+        }
+        if statistics_dirty {
+            statistics = None;
+            statistics_dirty = false;
+        }
+        if enable_statistics && statistics.is_none() {
+            statistics = Some(pipeline.statistics(selected_x, selected_y));
+        }
+        if let Some(texture) = texture {
             draw_texture(
                 texture,
                 margin,
@@ -393,6 +1010,61 @@ async fn main() -> Result<()> {
             );
             draw_text(pipeline.xcolumn(),2.0*margin + texture.width()/2.0, texture.height()+24.0, 24.0, Color::from_rgba(255, 255, 255, 255));
             draw_text(pipeline.ycolumn(),2.0*margin, 2.0*margin+24.0, 24.0, Color::from_rgba(255, 255, 255, 255));
+            draw_axes(&pipeline, margin, texture.width(), texture.height());
+            draw_color_bar(&pipeline, margin, texture.width());
+
+            if selection_tool != SelectionTool::Off {
+                let (x, y) = mouse_position();
+                let on_plot = x >= margin
+                    && y >= margin
+                    && x <= margin + texture.width()
+                    && y <= margin + texture.height();
+                if is_mouse_button_pressed(MouseButton::Left) {
+                    selection.clear();
+                    if on_plot && !pointer_over_ui {
+                        selection.push((x, y));
+                    }
+                } else if is_mouse_button_down(MouseButton::Left) && !selection.is_empty() {
+                    let (last_x, last_y) = selection[selection.len() - 1];
+                    if selection_tool == SelectionTool::Rectangle {
+                        selection.truncate(1);
+                        selection.push((x, y));
+                    } else if (x - last_x).abs() + (y - last_y).abs() >= 2.0 {
+                        selection.push((x, y));
+                    }
+                }
+                let polygon = selection_tool.polygon(&selection);
+                let color = Color::from_rgba(255, 255, 255, 255);
+                for (i, &(x1, y1)) in polygon.iter().enumerate() {
+                    let (x2, y2) = polygon[(i + 1) % polygon.len()];
+                    draw_line(x1, y1, x2, y2, 1.0, color);
+                }
+                if is_mouse_button_released(MouseButton::Left) && polygon_area(&polygon) >= 4.0 {
+                    let positions = polygon
+                        .iter()
+                        .map(|&(x, y)| {
+                            (
+                                ((x - margin) / texture.width()) as f64,
+                                ((y - margin) / texture.height()) as f64,
+                            )
+                        })
+                        .collect::<Vec<_>>();
+                    if let Some(filter) = pipeline.selection_filter(&positions) {
+                        let mut groups = pipeline.highlight_groups.clone();
+                        if groups.is_empty() {
+                            groups = HighlightGroup::default_groups();
+                        }
+                        selected_group = selected_group.min(groups.len() - 1);
+                        groups[selected_group].filter.filters.push(filter);
+                        if pipeline.set_highlight_groups(groups) {
+                            statistics_dirty = true;
+                        }
+                    }
+                }
+                if is_mouse_button_released(MouseButton::Left) {
+                    selection.clear();
+                }
+            }
         }
         egui_macroquad::draw();
         // Draw things after egui
@@ -5,6 +5,7 @@ extern crate serde_yaml;
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct NumericStatistics {
     count: usize,
+    missing: usize,
     sum_of_values: f64,
     sum_of_values2: f64,
     sum_of_values3: f64,
@@ -14,10 +15,17 @@ pub struct NumericStatistics {
     maximum: Option<f64>,
 }
 
+impl Default for NumericStatistics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl NumericStatistics {
     pub fn new() -> NumericStatistics {
         NumericStatistics {
             count: 0,
+            missing: 0,
             sum_of_values: 0.0,
             sum_of_values2: 0.0,
             sum_of_values3: 0.0,
@@ -31,6 +39,7 @@ impl NumericStatistics {
     pub fn all_measure_names(&self)->Vec<String> {
         let mut v=Vec::new();
         v.push("Count".to_owned());
+        v.push("Missing".to_owned());
         v.push("Minimum".to_owned());
         v.push("Maximum".to_owned());
         v.push("Mean".to_owned());
@@ -44,6 +53,7 @@ impl NumericStatistics {
     pub fn all_measure_values(&self)->Vec<String> {
         let mut v=Vec::new();
         v.push(format!("{}",self.count));
+        v.push(format!("{}",self.missing));
         let mut push = |x:Option<f64>|{
             if let Some(xx)=x{
                 v.push(format!("{:.3}",xx));
@@ -68,6 +78,13 @@ impl NumericStatistics {
 
     pub fn add_weighted(&mut self, x: &[f64], weight: &[f64]) {
         for (xi, wi) in x.iter().zip(weight.iter()) {
+            if xi.is_nan() {
+                self.missing+=1;
+                continue;
+            }
+            if wi.is_nan() {
+                continue;
+            }
             let wx = wi * xi;
             let wx2 = wx * xi;
             let wx3 = wx2 * xi;
@@ -100,6 +117,13 @@ impl NumericStatistics {
             }
             let xi=x[i];
             let wi=weight[i];
+            if xi.is_nan() {
+                self.missing+=1;
+                continue;
+            }
+            if wi.is_nan() {
+                continue;
+            }
             let wx = wi * xi;
             let wx2 = wx * xi;
             let wx3 = wx2 * xi;
@@ -126,6 +150,10 @@ impl NumericStatistics {
    
     pub fn add(&mut self, x: &[f64]) {
         for xi in x.iter() {
+            if xi.is_nan() {
+                self.missing+=1;
+                continue;
+            }
             let wx = xi;
             let wx2 = wx * xi;
             let wx3 = wx2 * xi;
@@ -157,6 +185,7 @@ impl NumericStatistics {
         self.sum_of_values4 += analyzer.sum_of_values4;
         self.sum_of_weights += analyzer.sum_of_weights;
         self.count += analyzer.count;
+        self.missing += analyzer.missing;
 
         if let Some(x) = self.minimum {
             if let Some(y) = analyzer.minimum {
@@ -174,6 +203,16 @@ impl NumericStatistics {
             self.maximum = analyzer.maximum;
         }
     }
+    /// Number of values with a non-missing value (NaN is treated as missing).
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Number of missing (NaN) values.
+    pub fn missing(&self) -> usize {
+        self.missing
+    }
+
     pub fn mean(&self) -> Option<f64> {
         if self.sum_of_weights == 0.0 {
             None
@@ -226,3 +265,27 @@ impl NumericStatistics {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_missing() {
+        let mut stat = NumericStatistics::new();
+        stat.add_weighted(&[1.0, f64::NAN, 3.0], &[1.0, 1.0, 1.0]);
+        assert_eq!(stat.count(), 2);
+        assert_eq!(stat.missing(), 1);
+        assert_eq!(stat.mean(), Some(2.0));
+        let mut selection = NumericStatistics::new();
+        selection.add_weighted_selection(
+            &[1.0, f64::NAN, 3.0],
+            &[1.0, 1.0, 1.0],
+            vec![1, 2].into_iter(),
+        );
+        assert_eq!(selection.missing(), 1);
+        stat.add_analyzer(&selection);
+        assert_eq!(stat.missing(), 2);
+        assert_eq!(stat.all_measure_values()[1], "2");
+    }
+}
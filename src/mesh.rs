@@ -1,6 +1,16 @@
 #![allow(dead_code)]
+use crate::colormap::{Colormap, Rgb};
+use crate::transform::Transform;
+use anyhow::Result;
+use std::io::Write;
+use std::str::FromStr;
 
-#[derive(Debug, Clone, PartialOrd, PartialEq, Copy)]
+/// Layer accumulating weight × value of the value column, see `Mesh::layer_mut`.
+pub const VALUE_LAYER: usize = usize::MAX;
+/// Smallest number of points rasterized by a thread, fewer points are not worth a thread.
+const MIN_POINTS_PER_THREAD: usize = 20000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialOrd, PartialEq, Copy)]
 pub enum HighlightType {
     Highlight,
     NoHighlight,
@@ -8,11 +18,63 @@ pub enum HighlightType {
     NonHighlightedOnly,
 }
 
-#[derive(Debug, Clone)]
+/// How the layers of several highlight groups are combined in a pixel.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Copy)]
+pub enum HighlightComposition {
+    /// The colors of all the groups are added.
+    Additive,
+    /// Only the group with the highest density is shown.
+    Dominance,
+}
+
+/// Statistic of the value column shown in each cell when coloring by values.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Copy)]
+pub enum ValueStatistic {
+    Mean,
+    Median,
+    Max,
+}
+
+impl ValueStatistic {
+    pub fn text(&self) -> &str {
+        match self {
+            ValueStatistic::Mean => "Mean",
+            ValueStatistic::Median => "Median",
+            ValueStatistic::Max => "Max",
+        }
+    }
+}
+
+impl FromStr for ValueStatistic {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<ValueStatistic> {
+        match s {
+            "Mean" | "mean" => Ok(ValueStatistic::Mean),
+            "Median" | "median" => Ok(ValueStatistic::Median),
+            "Max" | "max" => Ok(ValueStatistic::Max),
+            _ => Err(anyhow::anyhow!(
+                "Unknown statistic '{}', expected mean, median or max",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
 pub struct Parameters {
     pub xcolumn: String,
     pub ycolumn: String,
     pub weight_column: String,
+    /// Column with categories drawn in their own colors, none if empty.
+    pub color_column: String,
+    /// Categories of the color column which are not drawn.
+    pub hidden_categories: Vec<String>,
+    /// Numeric column whose statistic is shown in each cell instead of the density, none if empty.
+    pub value_column: String,
+    pub value_statistic: ValueStatistic,
+    /// Colormap of the values, diverging by default.
+    pub value_colormap: Colormap,
 //    pub highlight_column: String,
 //    pub highlight_value: String,
     pub highlight_type: HighlightType,
@@ -24,8 +86,19 @@ pub struct Parameters {
     pub ymax: f64,
     pub gaussian_points: bool,
     pub point_sigma: f64,
+    /// Bin the points and blur the whole mesh instead of smearing every point,
+    /// the cost does not depend on the number of points.
+    pub blur_mesh: bool,
     pub density_multiplier: f64,
     pub contrast: f64,
+    pub colormap: Colormap,
+    pub highlight_composition: HighlightComposition,
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Parameters {
@@ -34,6 +107,11 @@ impl Parameters {
             xcolumn: "".into(),
             ycolumn: "".into(),
             weight_column: "".into(),
+            color_column: "".into(),
+            hidden_categories: Vec::new(),
+            value_column: "".into(),
+            value_statistic: ValueStatistic::Mean,
+            value_colormap: Colormap::coolwarm(),
 //            highlight_column: "".into(),
 //            highlight_value: "".into(),
             highlight_type: HighlightType::Highlight,
@@ -45,8 +123,11 @@ impl Parameters {
             ymax: 1.0,
             gaussian_points: false,
             point_sigma: 1.0,
+            blur_mesh: false,
             density_multiplier: 0.0,
             contrast: 1.0,
+            colormap: Colormap::blue_cyan(),
+            highlight_composition: HighlightComposition::Additive,
         }
     }
 
@@ -95,6 +176,44 @@ impl Parameters {
     }
 }
 
+/// Mapping between the densities in a mesh (weight per pixel) and the levels (0 to 1)
+/// of the processed mesh after the brightness, contrast and arctangent clamping.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DensityScale {
+    pub scale: f64,
+    pub contrast: f64,
+}
+
+impl Default for DensityScale {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DensityScale {
+    pub fn new() -> DensityScale {
+        DensityScale {
+            scale: 1.0,
+            contrast: 1.0,
+        }
+    }
+}
+
+impl Transform for DensityScale {
+    fn calibrate(&mut self, _values: &[f64]) {}
+    fn transform(&self, density: f64) -> Option<f64> {
+        let x = (density * self.scale).max(0.0).powf(self.contrast);
+        Some(x.atan() / 5.0f64.atan())
+    }
+    fn inverse(&self, level: f64) -> Option<f64> {
+        if (0.0..=1.0).contains(&level) {
+            Some((level * 5.0f64.atan()).tan().max(0.0).powf(1.0 / self.contrast) / self.scale)
+        } else {
+            None
+        }
+    }
+}
+
 pub struct Mesh {
     pub width: usize,
     pub height: usize,
@@ -102,12 +221,30 @@ pub struct Mesh {
     pub xmax: f64,
     pub ymin: f64,
     pub ymax: f64,
+    /// Layer 0 - the points which are not highlighted.
     pub mesh: Vec<f64>,
-    pub highlight_mesh: Vec<f64>,
+    /// Layers 1, 2... - one for each highlight group.
+    pub highlight_meshes: Vec<Vec<f64>>,
     pub index_mesh: Vec<usize>,
     pub processed_mesh: Vec<f64>,
-    pub processed_highlight_mesh: Vec<f64>,
+    pub processed_highlight_meshes: Vec<Vec<f64>>,
+    /// Sum of weight × value of the value column.
+    pub value_mesh: Vec<f64>,
+    /// Statistic of the values in each cell, NaN in empty cells.
+    pub processed_value_mesh: Vec<f64>,
+    /// Values at the ends of the value colormap.
+    pub value_range: (f64, f64),
     pub rgba8: Vec<u8>,
+    /// Scale of the densities in `processed_mesh`, see `DensityScale`.
+    pub density_scale: DensityScale,
+    /// Scale of the densities in `processed_highlight_meshes`, common to all the groups.
+    pub highlight_density_scale: DensityScale,
+}
+
+impl Default for Mesh {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Mesh {
@@ -120,21 +257,32 @@ impl Mesh {
             ymin: 0.0,
             ymax: 1.0,
             mesh: Vec::new(),
-            highlight_mesh: Vec::new(),
+            highlight_meshes: vec![Vec::new()],
             index_mesh: Vec::new(),
             processed_mesh: Vec::new(),
-            processed_highlight_mesh: Vec::new(),
+            processed_highlight_meshes: vec![Vec::new()],
+            value_mesh: Vec::new(),
+            processed_value_mesh: Vec::new(),
+            value_range: (0.0, 1.0),
             rgba8: Vec::new(),
+            density_scale: DensityScale::new(),
+            highlight_density_scale: DensityScale::new(),
         }
     }
 
     pub fn resize(&mut self, width: usize, height: usize) -> &mut Self {
         let size = width * height;
         self.mesh.resize(size, 0.0);
-        self.highlight_mesh.resize(size, 0.0);
+        for layer in self.highlight_meshes.iter_mut() {
+            layer.resize(size, 0.0);
+        }
         self.index_mesh.resize(size, 0);
         self.processed_mesh.resize(size, 0.0);
-        self.processed_highlight_mesh.resize(size, 0.0);
+        for layer in self.processed_highlight_meshes.iter_mut() {
+            layer.resize(size, 0.0);
+        }
+        self.value_mesh.resize(size, 0.0);
+        self.processed_value_mesh.resize(size, 0.0);
         self.rgba8.resize(4 * size, 0);
         self.width = width;
         self.height = height;
@@ -145,7 +293,7 @@ impl Mesh {
         for i in self.mesh.iter_mut() {
             *i = 0.0;
         }
-        for i in self.highlight_mesh.iter_mut() {
+        for i in self.highlight_meshes.iter_mut().flatten() {
             *i = 0.0;
         }
         for i in self.index_mesh.iter_mut() {
@@ -154,11 +302,40 @@ impl Mesh {
         for i in self.processed_mesh.iter_mut() {
             *i = 0.0;
         }
-        for i in self.processed_highlight_mesh.iter_mut() {
+        for i in self.processed_highlight_meshes.iter_mut().flatten() {
             *i = 0.0;
         }
+        for i in self.value_mesh.iter_mut() {
+            *i = 0.0;
+        }
+        for i in self.processed_value_mesh.iter_mut() {
+            *i = 0.0;
+        }
+        self
+    }
+
+    /// Number of highlight layers.
+    pub fn highlight_layers(&self) -> usize {
+        self.highlight_meshes.len()
+    }
+    /// Set the number of highlight layers, new layers are empty.
+    pub fn set_highlight_layers(&mut self, layers: usize) -> &mut Self {
+        let size = self.width * self.height;
+        self.highlight_meshes.resize(layers, vec![0.0; size]);
+        self.processed_highlight_meshes.resize(layers, vec![0.0; size]);
         self
     }
+    /// Mesh of a layer: 0 is `mesh`, `VALUE_LAYER` is `value_mesh`
+    /// and other n > 0 is the highlight layer n-1.
+    pub fn layer_mut(&mut self, layer: usize) -> &mut Vec<f64> {
+        if layer == 0 {
+            &mut self.mesh
+        } else if layer == VALUE_LAYER {
+            &mut self.value_mesh
+        } else {
+            &mut self.highlight_meshes[layer - 1]
+        }
+    }
     pub fn get_index(&self, x: usize, y: usize) -> Option<usize> {
         if x < self.width && y < self.height {
             let index = self.index_mesh[x + self.width * y];
@@ -185,7 +362,21 @@ impl Mesh {
         }
     }
 
-    pub fn point(&mut self, x: f64, y: f64, weight: f64, index: usize, highlight: bool) {
+    /// Index of the cell containing a point, None outside of the mesh.
+    pub fn cell(&self, x: f64, y: f64) -> Option<usize> {
+        let fx = (x - self.xmin) / (self.xmax - self.xmin);
+        let fy = (y - self.ymin) / (self.ymax - self.ymin);
+        if fx >= 0.0 && fy >= 0.0 {
+            let ix = (fx * (self.width as f64)) as usize;
+            let iy = (fy * (self.height as f64)) as usize;
+            if ix < self.width && iy < self.height {
+                return Some(ix + iy * self.width);
+            }
+        }
+        None
+    }
+
+    pub fn point(&mut self, x: f64, y: f64, weight: f64, index: usize, layer: usize) {
         let fx = (x - self.xmin) / (self.xmax - self.xmin);
         let fy = (y - self.ymin) / (self.ymax - self.ymin);
         if fx >= 0.0 && fy >= 0.0 {
@@ -193,11 +384,9 @@ impl Mesh {
             let iy = (fy * (self.height as f64)) as usize;
             if ix < self.width && iy < self.height {
                 //                println!("  -> mesh {} {}",ix,iy);
-                if highlight {
-                    self.highlight_mesh[ix + iy * self.width] += weight;
-                } else {
-                    self.mesh[ix + iy * self.width] += weight;
-                }
+                let width = self.width;
+                let mesh = self.layer_mut(layer);
+                mesh[ix + iy * width] += weight;
                 self.index_mesh[ix + iy * self.width] = index;
             }
         }
@@ -209,7 +398,7 @@ impl Mesh {
         y: f64,
         weight: f64,
         index: usize,
-        highlight: bool,
+        layer: usize,
     ) {
         let fx = (x - self.xmin) / (self.xmax - self.xmin);
         let fy = (y - self.ymin) / (self.ymax - self.ymin);
@@ -253,31 +442,19 @@ impl Mesh {
                 w21 *= w;
                 w22 *= w;
 
-                if highlight {
-                    self.highlight_mesh[ix - 1 + (iy - 1) * self.width] += w00;
-                    self.highlight_mesh[ix + (iy - 1) * self.width] += w10;
-                    self.highlight_mesh[ix + 1 + (iy - 1) * self.width] += w20;
-
-                    self.highlight_mesh[ix - 1 + iy * self.width] += w01;
-                    self.highlight_mesh[ix + iy * self.width] += w11;
-                    self.highlight_mesh[ix + 1 + iy * self.width] += w21;
-
-                    self.highlight_mesh[ix - 1 + (iy + 1) * self.width] += w02;
-                    self.highlight_mesh[ix + (iy + 1) * self.width] += w12;
-                    self.highlight_mesh[ix + 1 + (iy + 1) * self.width] += w22;
-                } else {
-                    self.mesh[ix - 1 + (iy - 1) * self.width] += w00;
-                    self.mesh[ix + (iy - 1) * self.width] += w10;
-                    self.mesh[ix + 1 + (iy - 1) * self.width] += w20;
-
-                    self.mesh[ix - 1 + iy * self.width] += w01;
-                    self.mesh[ix + iy * self.width] += w11;
-                    self.mesh[ix + 1 + iy * self.width] += w21;
-
-                    self.mesh[ix - 1 + (iy + 1) * self.width] += w02;
-                    self.mesh[ix + (iy + 1) * self.width] += w12;
-                    self.mesh[ix + 1 + (iy + 1) * self.width] += w22;
-                }
+                let width = self.width;
+                let mesh = self.layer_mut(layer);
+                mesh[ix - 1 + (iy - 1) * width] += w00;
+                mesh[ix + (iy - 1) * width] += w10;
+                mesh[ix + 1 + (iy - 1) * width] += w20;
+
+                mesh[ix - 1 + iy * width] += w01;
+                mesh[ix + iy * width] += w11;
+                mesh[ix + 1 + iy * width] += w21;
+
+                mesh[ix - 1 + (iy + 1) * width] += w02;
+                mesh[ix + (iy + 1) * width] += w12;
+                mesh[ix + 1 + (iy + 1) * width] += w22;
                 self.index_mesh[ix + iy * self.width] = index;
             }
         }
@@ -289,7 +466,7 @@ impl Mesh {
         y: f64,
         weight: f64,
         index: usize,
-        highlight: bool,
+        layer: usize,
         sigma: f64,
     ) {
         let fx = (x - self.xmin) / (self.xmax - self.xmin);
@@ -401,67 +578,37 @@ impl Mesh {
                 w43 *= w;
                 w44 *= w;
 
-                if highlight {
-                    self.highlight_mesh[ix - 2 + (iy - 2) * self.width] += w00;
-                    self.highlight_mesh[ix - 1 + (iy - 2) * self.width] += w10;
-                    self.highlight_mesh[ix + (iy - 2) * self.width] += w20;
-                    self.highlight_mesh[ix + 1 + (iy - 2) * self.width] += w30;
-                    self.highlight_mesh[ix + 2 + (iy - 2) * self.width] += w40;
-
-                    self.highlight_mesh[ix - 2 + (iy - 1) * self.width] += w01;
-                    self.highlight_mesh[ix - 1 + (iy - 1) * self.width] += w11;
-                    self.highlight_mesh[ix + (iy - 1) * self.width] += w21;
-                    self.highlight_mesh[ix + 1 + (iy - 1) * self.width] += w31;
-                    self.highlight_mesh[ix + 2 + (iy - 1) * self.width] += w41;
-
-                    self.highlight_mesh[ix - 2 + iy * self.width] += w02;
-                    self.highlight_mesh[ix - 1 + iy * self.width] += w12;
-                    self.highlight_mesh[ix + iy * self.width] += w22;
-                    self.highlight_mesh[ix + 1 + iy * self.width] += w32;
-                    self.highlight_mesh[ix + 2 + iy * self.width] += w42;
-
-                    self.highlight_mesh[ix - 2 + (iy + 1) * self.width] += w03;
-                    self.highlight_mesh[ix - 1 + (iy + 1) * self.width] += w13;
-                    self.highlight_mesh[ix + (iy + 1) * self.width] += w23;
-                    self.highlight_mesh[ix + 1 + (iy + 1) * self.width] += w33;
-                    self.highlight_mesh[ix + 2 + (iy + 1) * self.width] += w43;
-
-                    self.highlight_mesh[ix - 2 + (iy + 2) * self.width] += w04;
-                    self.highlight_mesh[ix - 1 + (iy + 2) * self.width] += w14;
-                    self.highlight_mesh[ix + (iy + 2) * self.width] += w24;
-                    self.highlight_mesh[ix + 1 + (iy + 2) * self.width] += w34;
-                    self.highlight_mesh[ix + 2 + (iy + 2) * self.width] += w44;
-                } else {
-                    self.mesh[ix - 2 + (iy - 2) * self.width] += w00;
-                    self.mesh[ix - 1 + (iy - 2) * self.width] += w10;
-                    self.mesh[ix + (iy - 2) * self.width] += w20;
-                    self.mesh[ix + 1 + (iy - 2) * self.width] += w30;
-                    self.mesh[ix + 2 + (iy - 2) * self.width] += w40;
-
-                    self.mesh[ix - 2 + (iy - 1) * self.width] += w01;
-                    self.mesh[ix - 1 + (iy - 1) * self.width] += w11;
-                    self.mesh[ix + (iy - 1) * self.width] += w21;
-                    self.mesh[ix + 1 + (iy - 1) * self.width] += w31;
-                    self.mesh[ix + 2 + (iy - 1) * self.width] += w41;
-
-                    self.mesh[ix - 2 + iy * self.width] += w02;
-                    self.mesh[ix - 1 + iy * self.width] += w12;
-                    self.mesh[ix + iy * self.width] += w22;
-                    self.mesh[ix + 1 + iy * self.width] += w32;
-                    self.mesh[ix + 2 + iy * self.width] += w42;
-
-                    self.mesh[ix - 2 + (iy + 1) * self.width] += w03;
-                    self.mesh[ix - 1 + (iy + 1) * self.width] += w13;
-                    self.mesh[ix + (iy + 1) * self.width] += w23;
-                    self.mesh[ix + 1 + (iy + 1) * self.width] += w33;
-                    self.mesh[ix + 2 + (iy + 1) * self.width] += w43;
-
-                    self.mesh[ix - 2 + (iy + 2) * self.width] += w04;
-                    self.mesh[ix - 1 + (iy + 2) * self.width] += w14;
-                    self.mesh[ix + (iy + 2) * self.width] += w24;
-                    self.mesh[ix + 1 + (iy + 2) * self.width] += w34;
-                    self.mesh[ix + 2 + (iy + 2) * self.width] += w44;
-                }
+                let width = self.width;
+                let mesh = self.layer_mut(layer);
+                mesh[ix - 2 + (iy - 2) * width] += w00;
+                mesh[ix - 1 + (iy - 2) * width] += w10;
+                mesh[ix + (iy - 2) * width] += w20;
+                mesh[ix + 1 + (iy - 2) * width] += w30;
+                mesh[ix + 2 + (iy - 2) * width] += w40;
+
+                mesh[ix - 2 + (iy - 1) * width] += w01;
+                mesh[ix - 1 + (iy - 1) * width] += w11;
+                mesh[ix + (iy - 1) * width] += w21;
+                mesh[ix + 1 + (iy - 1) * width] += w31;
+                mesh[ix + 2 + (iy - 1) * width] += w41;
+
+                mesh[ix - 2 + iy * width] += w02;
+                mesh[ix - 1 + iy * width] += w12;
+                mesh[ix + iy * width] += w22;
+                mesh[ix + 1 + iy * width] += w32;
+                mesh[ix + 2 + iy * width] += w42;
+
+                mesh[ix - 2 + (iy + 1) * width] += w03;
+                mesh[ix - 1 + (iy + 1) * width] += w13;
+                mesh[ix + (iy + 1) * width] += w23;
+                mesh[ix + 1 + (iy + 1) * width] += w33;
+                mesh[ix + 2 + (iy + 1) * width] += w43;
+
+                mesh[ix - 2 + (iy + 2) * width] += w04;
+                mesh[ix - 1 + (iy + 2) * width] += w14;
+                mesh[ix + (iy + 2) * width] += w24;
+                mesh[ix + 1 + (iy + 2) * width] += w34;
+                mesh[ix + 2 + (iy + 2) * width] += w44;
                 self.index_mesh[ix + iy * self.width] = index;
             }
         }
@@ -473,7 +620,7 @@ impl Mesh {
         y: f64,
         weight: f64,
         index: usize,
-        highlight: bool,
+        layer: usize,
         sigma: f64,
     ) {
         let fx = (x - self.xmin) / (self.xmax - self.xmin);
@@ -481,7 +628,7 @@ impl Mesh {
         let two_sigma = 2.0 * sigma;
         let n = (2.0 * sigma) as usize;
         if n <= 2 {
-            self.point_gaussian5x5(x, y, weight, index, highlight, sigma);
+            self.point_gaussian5x5(x, y, weight, index, layer, sigma);
             return;
         }
         let dim = 2 * n + 1;
@@ -515,32 +662,17 @@ impl Mesh {
                     *value *= weight;
                 }
 
-                if highlight {
-                    for i in 0..dim {
-                        let ii = ((ix + i) as isize) - (n as isize);
-                        if ii >= 0 && (ii as usize) < self.width {
-                            for j in 0..dim {
-                                let jj = ((iy + j) as isize) - (n as isize);
-                                if jj >= 0 && (jj as usize) < self.height {
-                                    //                                println!("ix:{} i:{} iy:{} j:{} n:{} ii:{} jj:{}",ix,i,iy,j,n,ii,jj);
-                                    self.highlight_mesh
-                                        [(ii as usize) + (jj as usize) * self.width] +=
-                                        w[i + dim * j];
-                                }
-                            }
-                        }
-                    }
-                } else {
-                    for i in 0..dim {
-                        let ii = ((ix + i) as isize) - (n as isize);
-                        if ii >= 0 && (ii as usize) < self.width {
-                            for j in 0..dim {
-                                let jj = ((iy + j) as isize) - (n as isize);
-                                if jj >= 0 && (jj as usize) < self.height {
-                                    //                                println!("ix:{} i:{} iy:{} j:{} n:{} ii:{} jj:{}",ix,i,iy,j,n,ii,jj);
-                                    self.mesh[(ii as usize) + (jj as usize) * self.width] +=
-                                        w[i + dim * j];
-                                }
+                let (width, height) = (self.width, self.height);
+                let mesh = self.layer_mut(layer);
+                for i in 0..dim {
+                    let ii = ((ix + i) as isize) - (n as isize);
+                    if ii >= 0 && (ii as usize) < width {
+                        for j in 0..dim {
+                            let jj = ((iy + j) as isize) - (n as isize);
+                            if jj >= 0 && (jj as usize) < height {
+                                //                                println!("ix:{} i:{} iy:{} j:{} n:{} ii:{} jj:{}",ix,i,iy,j,n,ii,jj);
+                                mesh[(ii as usize) + (jj as usize) * width] +=
+                                    w[i + dim * j];
                             }
                         }
                     }
@@ -550,10 +682,11 @@ impl Mesh {
         }
     }
 
-    fn atan_clamp_mesh(mesh: &mut Vec<f64>, brightness:f64, contrast:f64) {
+    /// Clamp meshes with a common scale given by the mean of the non-zero densities.
+    fn atan_clamp_meshes(meshes: &mut [Vec<f64>], brightness:f64, contrast:f64) -> DensityScale {
         let mut sum=0.0f64;
         let mut count=1.0;
-        for &value in mesh.iter() {
+        for &value in meshes.iter().flatten() {
             if value>0.0{
                 sum+=value;
                 count+=1.0;
@@ -567,19 +700,25 @@ impl Mesh {
 
         let linear_brightness = brightness.exp()/mean/1.0f64.exp();
         let normalization=1.0/(5.0f64.atan());
-        for i in 0..mesh.len() {
-            let x = mesh[i]*linear_brightness;
+        for value in meshes.iter_mut().flatten() {
+            let x = *value*linear_brightness;
             let x = if x<0.0 {0.0} else {x};
             let x = x.powf(contrast);
             let x = x.atan()*normalization;
-            mesh[i] = x;
+            *value = x;
         }
+        DensityScale{scale:linear_brightness, contrast}
     }
     pub fn clamp_processed_mesh(&mut self, brightness:f64, contrast: f64) {
-        Self::atan_clamp_mesh(&mut self.processed_mesh, brightness, contrast);
+        self.density_scale = Self::atan_clamp_meshes(
+            std::slice::from_mut(&mut self.processed_mesh),
+            brightness,
+            contrast,
+        );
     }
     pub fn clamp_processed_highlight_mesh(&mut self, brightness:f64, contrast: f64) {
-        Self::atan_clamp_mesh(&mut self.processed_highlight_mesh, brightness, contrast);
+        self.highlight_density_scale =
+            Self::atan_clamp_meshes(&mut self.processed_highlight_meshes, brightness, contrast);
     }
 
     pub fn normalize_processed_mesh(&mut self) {
@@ -596,15 +735,15 @@ impl Mesh {
     }
 
     pub fn normalize_processed_highlight_mesh(&mut self) {
-        let mut maximum = self.processed_highlight_mesh[0];
-        for &value in self.processed_highlight_mesh.iter() {
+        let mut maximum = 0.0f64;
+        for &value in self.processed_highlight_meshes.iter().flatten() {
             maximum = maximum.max(value);
         }
         if maximum == 0.0 {
             maximum = 1.0;
         }
-        for i in 0..self.processed_highlight_mesh.len() {
-            self.processed_highlight_mesh[i] /= maximum;
+        for value in self.processed_highlight_meshes.iter_mut().flatten() {
+            *value /= maximum;
         }
     }
 
@@ -614,8 +753,8 @@ impl Mesh {
         }
     }
     pub fn multiply_processed_highlight_mesh(&mut self, value: f64) {
-        for i in 0..self.processed_highlight_mesh.len() {
-            self.processed_highlight_mesh[i] *= value;
+        for x in self.processed_highlight_meshes.iter_mut().flatten() {
+            *x *= value;
         }
     }
 
@@ -626,7 +765,7 @@ impl Mesh {
         self
     }
     pub fn clean_processed_highlight_mesh(&mut self) -> &mut Self {
-        for i in self.processed_highlight_mesh.iter_mut() {
+        for i in self.processed_highlight_meshes.iter_mut().flatten() {
             *i = 0.0;
         }
         self
@@ -648,15 +787,22 @@ impl Mesh {
         }
     }
     pub fn to_processed_highlight_mesh(&mut self) {
-        for i in 0..self.mesh.len() {
-            self.processed_highlight_mesh[i] = self.highlight_mesh[i];
+        for (processed, layer) in self
+            .processed_highlight_meshes
+            .iter_mut()
+            .zip(self.highlight_meshes.iter())
+        {
+            processed.copy_from_slice(layer);
         }
     }
     pub fn to_processed_mesh_sum_highlight(&mut self) {
-        for i in 0..self.mesh.len() {
-            self.processed_mesh[i] = self.mesh[i] + self.highlight_mesh[i];
-            self.processed_highlight_mesh[i] = 0.0;
+        self.to_processed_mesh();
+        for layer in self.highlight_meshes.iter() {
+            for (processed, value) in self.processed_mesh.iter_mut().zip(layer.iter()) {
+                *processed += value;
+            }
         }
+        self.clean_processed_highlight_mesh();
     }
 
     pub fn to_processed_mesh_smear(&mut self) {
@@ -682,82 +828,359 @@ impl Mesh {
         }
     }
 
-    pub fn to_rgba8_gray(&mut self) {
-        for (i, m) in self.processed_mesh.iter().enumerate() {
-            let value: u8 = if *m < 0.0 {
-                0
+    /// Kernel of the Gaussian smearing of `point_gaussian` along one axis, normalized to 1.
+    pub fn gaussian_kernel(sigma: f64) -> Vec<f64> {
+        if sigma <= 0.0 {
+            return vec![1.0];
+        }
+        let n = ((2.0 * sigma) as usize).max(2) as isize;
+        let kernel = (-n..=n)
+            .map(|i| (-((i * i) as f64) / (2.0 * sigma)).exp())
+            .collect::<Vec<_>>();
+        let sum = kernel.iter().sum::<f64>();
+        kernel.into_iter().map(|w| w / sum).collect()
+    }
+
+    /// Convolve a layer with the kernel along rows and then along columns.
+    /// The densities smeared outside of the mesh are lost, like in `point_gaussian`.
+    fn convolve(layer: &mut [f64], width: usize, height: usize, kernel: &[f64]) {
+        let n = kernel.len() / 2;
+        let mut line = Vec::with_capacity(width.max(height));
+        for row in layer.chunks_mut(width) {
+            line.clear();
+            line.extend_from_slice(row);
+            for (x, value) in row.iter_mut().enumerate() {
+                let from = x.saturating_sub(n);
+                let to = (x + n + 1).min(width);
+                *value = (from..to).map(|i| line[i] * kernel[i + n - x]).sum();
+            }
+        }
+        for x in 0..width {
+            line.clear();
+            line.extend((0..height).map(|y| layer[x + y * width]));
+            for y in 0..height {
+                let from = y.saturating_sub(n);
+                let to = (y + n + 1).min(height);
+                layer[x + y * width] = (from..to).map(|i| line[i] * kernel[i + n - y]).sum();
+            }
+        }
+    }
+
+    /// Blur all the layers by the Gaussian smearing of `point_gaussian`
+    /// with a separable convolution, the index mesh is kept.
+    pub fn blur(&mut self, sigma: f64) {
+        let kernel = Self::gaussian_kernel(sigma);
+        if kernel.len() == 1 {
+            return;
+        }
+        let (width, height) = (self.width, self.height);
+        for layer in std::iter::once(&mut self.mesh)
+            .chain(self.highlight_meshes.iter_mut())
+            .chain(std::iter::once(&mut self.value_mesh))
+        {
+            Self::convolve(layer, width, height, &kernel);
+        }
+    }
+
+    /// Mean value in each cell: the value layer divided by the weights in `mesh`.
+    /// The points have to be added to layer 0 and to `VALUE_LAYER` with weight × value.
+    pub fn to_processed_value_mesh_mean(&mut self) {
+        for ((processed, value), weight) in self
+            .processed_value_mesh
+            .iter_mut()
+            .zip(self.value_mesh.iter())
+            .zip(self.mesh.iter())
+        {
+            *processed = if *weight != 0.0 {
+                value / weight
             } else {
-                if *m >= 1.0 {
-                    255
-                } else {
-                    (255.0 * m) as u8
-                }
+                f64::NAN
             };
-            self.rgba8[4 * i] = value;
-            self.rgba8[4 * i + 1] = value;
-            self.rgba8[4 * i + 2] = value;
-            self.rgba8[4 * i + 3] = 255;
         }
     }
-    pub fn to_rgba8_blue_cyan(&mut self) {
-        for (i, m) in self.processed_mesh.iter().enumerate() {
-            let blue: u8 = if *m < 0.0 {
-                0
+
+    /// Statistic of the values of points (x, y, weight, value) falling into each cell.
+    /// Unlike the mean from the value layer, the points are not smeared.
+    pub fn to_processed_value_mesh_binned(
+        &mut self,
+        points: &[(f64, f64, f64, f64)],
+        statistic: ValueStatistic,
+    ) {
+        let mut cells = vec![Vec::new(); self.processed_value_mesh.len()];
+        for &(x, y, weight, value) in points {
+            if let Some(cell) = self.cell(x, y) {
+                if weight > 0.0 {
+                    cells[cell].push((value, weight));
+                }
+            }
+        }
+        for (processed, values) in self.processed_value_mesh.iter_mut().zip(cells.iter_mut()) {
+            *processed = if values.is_empty() {
+                f64::NAN
             } else {
-                if *m >= 0.5 {
-                    255
-                } else {
-                    (255.0 * m * 2.0) as u8
+                match statistic {
+                    ValueStatistic::Mean => {
+                        values.iter().map(|(v, w)| v * w).sum::<f64>()
+                            / values.iter().map(|(_, w)| w).sum::<f64>()
+                    }
+                    ValueStatistic::Median => {
+                        values.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+                        let half = 0.5 * values.iter().map(|(_, w)| w).sum::<f64>();
+                        let mut cumulative = 0.0;
+                        let mut median = values[0].0;
+                        for &(value, weight) in values.iter() {
+                            median = value;
+                            cumulative += weight;
+                            if cumulative >= half {
+                                break;
+                            }
+                        }
+                        median
+                    }
+                    ValueStatistic::Max => values
+                        .iter()
+                        .map(|(v, _)| *v)
+                        .fold(f64::NEG_INFINITY, f64::max),
                 }
             };
-            let green: u8 = if *m < 0.5 {
-                0
+        }
+    }
+
+    /// Set the value range from the processed value mesh,
+    /// symmetric around zero if there are both positive and negative values.
+    pub fn update_value_range(&mut self) {
+        let mut minimum = f64::INFINITY;
+        let mut maximum = f64::NEG_INFINITY;
+        for &value in self.processed_value_mesh.iter().filter(|x| x.is_finite()) {
+            minimum = minimum.min(value);
+            maximum = maximum.max(value);
+        }
+        self.value_range = if minimum > maximum {
+            (0.0, 1.0)
+        } else if minimum < 0.0 && maximum > 0.0 {
+            let extent = maximum.max(-minimum);
+            (-extent, extent)
+        } else if minimum == maximum {
+            (minimum - 0.5, maximum + 0.5)
+        } else {
+            (minimum, maximum)
+        };
+    }
+
+    /// Color the processed value mesh with a colormap over `value_range`, empty cells are black.
+    pub fn to_rgba8_values(&mut self, colormap: &Colormap) {
+        let lut = colormap.lut();
+        let (minimum, maximum) = self.value_range;
+        for (i, value) in self.processed_value_mesh.iter().enumerate() {
+            let [red, green, blue] = if value.is_nan() {
+                [0, 0, 0]
             } else {
-                if *m >= 1.0 {
-                    255
-                } else {
-                    (255.0 * (m - 0.5) * 2.0) as u8
-                }
+                lut[Colormap::lut_index((value - minimum) / (maximum - minimum))]
             };
-            self.rgba8[4 * i] = 0;
+            self.rgba8[4 * i] = red;
+            self.rgba8[4 * i + 1] = green;
+            self.rgba8[4 * i + 2] = blue;
+            self.rgba8[4 * i + 3] = 255;
+        }
+    }
+
+    pub fn to_rgba8_gray(&mut self) {
+        self.to_rgba8_colormap(&Colormap::gray());
+    }
+    pub fn to_rgba8_blue_cyan(&mut self) {
+        self.to_rgba8_colormap(&Colormap::blue_cyan());
+    }
+    /// Color the processed mesh with a colormap.
+    pub fn to_rgba8_colormap(&mut self, colormap: &Colormap) {
+        let lut = colormap.lut();
+        for (i, m) in self.processed_mesh.iter().enumerate() {
+            let [red, green, blue] = lut[Colormap::lut_index(*m)];
+            self.rgba8[4 * i] = red;
             self.rgba8[4 * i + 1] = green;
             self.rgba8[4 * i + 2] = blue;
             self.rgba8[4 * i + 3] = 255;
         }
     }
     pub fn add_rgba8_red_highlight(&mut self) {
-        for (i, m) in self.processed_highlight_mesh.iter().enumerate() {
-            let red: u8 = if *m < 0.0 {
-                0
-            } else {
-                if *m >= 1.0 {
-                    255
-                } else {
-                    (255.0 * m) as u8
+        let colors = vec![Rgb([255, 0, 0]); self.highlight_layers()];
+        self.add_rgba8_highlights(&colors, HighlightComposition::Additive);
+    }
+    /// Add the processed highlight layers to the image, each in its color.
+    pub fn add_rgba8_highlights(&mut self, colors: &[Rgb], composition: HighlightComposition) {
+        let layers = &self.processed_highlight_meshes;
+        let rgba8 = &mut self.rgba8;
+        for i in 0..self.processed_mesh.len() {
+            let mut add = |m: f64, color: &Rgb| {
+                for channel in 0..3 {
+                    let value = rgba8[4 * i + channel] as f64 + m * color.0[channel] as f64;
+                    rgba8[4 * i + channel] = value.min(255.0) as u8;
                 }
             };
-            self.rgba8[4 * i] = red;
+            let levels = layers
+                .iter()
+                .zip(colors.iter())
+                .map(|(layer, color)| (layer[i].clamp(0.0, 1.0), color));
+            match composition {
+                HighlightComposition::Additive => {
+                    for (m, color) in levels {
+                        add(m, color);
+                    }
+                }
+                HighlightComposition::Dominance => {
+                    let mut dominant = None;
+                    for (m, color) in levels {
+                        if m > 0.0 && !matches!(dominant, Some((maximum, _)) if maximum >= m) {
+                            dominant = Some((m, color));
+                        }
+                    }
+                    if let Some((m, color)) = dominant {
+                        add(m, color);
+                    }
+                }
+            }
         }
     }
 
-    pub fn add_points(&mut self, xyi: &[(f64, f64, f64, usize, bool)], antialiased: bool) {
+    /// Add points (x, y, weight, index + 1, layer), see `layer_mut`.
+    pub fn add_points(&mut self, xyi: &[(f64, f64, f64, usize, usize)], antialiased: bool) {
+        let threads = Self::threads(xyi.len());
         if antialiased {
-            for (x, y, w, index, highlight) in xyi {
-                self.point_antialiased(*x, *y, *w, *index, *highlight);
-            }
+            self.add_points_in_parallel(xyi, threads, |mesh, &(x, y, w, index, layer)| {
+                mesh.point_antialiased(x, y, w, index, layer)
+            });
+        } else {
+            self.add_points_in_parallel(xyi, threads, |mesh, &(x, y, w, index, layer)| {
+                mesh.point(x, y, w, index, layer)
+            });
+        }
+    }
+
+    pub fn add_points_gaussian(&mut self, xyi: &[(f64, f64, f64, usize, usize)], sigma: f64) {
+        let threads = Self::threads(xyi.len());
+        self.add_points_in_parallel(xyi, threads, |mesh, &(x, y, w, index, layer)| {
+            mesh.point_gaussian(x, y, w, index, layer, sigma)
+        });
+    }
+
+    /// Bin the points into cells and blur the mesh, faster than `add_points_gaussian`
+    /// for many points. The mesh has to be clean, otherwise its content is blurred as well.
+    pub fn add_points_blurred(&mut self, xyi: &[(f64, f64, f64, usize, usize)], sigma: f64) {
+        self.add_points(xyi, false);
+        self.blur(sigma);
+    }
+
+    /// Number of threads rasterizing the points, one on WebAssembly.
+    fn threads(points: usize) -> usize {
+        if cfg!(target_arch = "wasm32") {
+            1
         } else {
-            for (x, y, w, index, highlight) in xyi {
-                self.point(*x, *y, *w, *index, *highlight);
+            std::thread::available_parallelism()
+                .map(|threads| threads.get())
+                .unwrap_or(1)
+                .min(points / MIN_POINTS_PER_THREAD)
+                .max(1)
+        }
+    }
+
+    /// Empty mesh with the same size, range and layers to rasterize a part of the points.
+    fn partial_mesh(&self) -> Mesh {
+        let size = self.width * self.height;
+        Mesh {
+            width: self.width,
+            height: self.height,
+            xmin: self.xmin,
+            xmax: self.xmax,
+            ymin: self.ymin,
+            ymax: self.ymax,
+            mesh: vec![0.0; size],
+            highlight_meshes: vec![vec![0.0; size]; self.highlight_meshes.len()],
+            index_mesh: vec![0; size],
+            value_mesh: vec![0.0; size],
+            ..Mesh::new()
+        }
+    }
+
+    /// Add the densities of a partial mesh; its indices replace the indices of the points
+    /// added before, as if its points were added after them.
+    fn merge(&mut self, partial: &Mesh) {
+        for (value, x) in self.mesh.iter_mut().zip(partial.mesh.iter()) {
+            *value += x;
+        }
+        for (layer, partial_layer) in self
+            .highlight_meshes
+            .iter_mut()
+            .zip(partial.highlight_meshes.iter())
+        {
+            for (value, x) in layer.iter_mut().zip(partial_layer.iter()) {
+                *value += x;
+            }
+        }
+        for (value, x) in self.value_mesh.iter_mut().zip(partial.value_mesh.iter()) {
+            *value += x;
+        }
+        for (index, &i) in self.index_mesh.iter_mut().zip(partial.index_mesh.iter()) {
+            if i != 0 {
+                *index = i;
             }
         }
     }
 
-    pub fn add_points_gaussian(&mut self, xyi: &[(f64, f64, f64, usize, bool)], sigma: f64) {
-        for (x, y, w, index, highlight) in xyi {
-            self.point_gaussian(*x, *y, *w, *index, *highlight, sigma);
+    /// Rasterize consecutive parts of the points in parallel into partial meshes
+    /// and merge them in the order of the points, so that `index_mesh` refers to the last point
+    /// in each cell, the same as when rasterized by a single thread.
+    fn add_points_in_parallel<F>(
+        &mut self,
+        xyi: &[(f64, f64, f64, usize, usize)],
+        threads: usize,
+        rasterize: F,
+    ) where
+        F: Fn(&mut Mesh, &(f64, f64, f64, usize, usize)) + Sync,
+    {
+        if threads <= 1 || cfg!(target_arch = "wasm32") {
+            for point in xyi {
+                rasterize(self, point);
+            }
+            return;
+        }
+        let part = xyi.len().div_ceil(threads);
+        let rasterize = &rasterize;
+        let partial_meshes = std::thread::scope(|scope| {
+            let handles = xyi
+                .chunks(part.max(1))
+                .map(|points| {
+                    let mut partial = self.partial_mesh();
+                    scope.spawn(move || {
+                        for point in points {
+                            rasterize(&mut partial, point);
+                        }
+                        partial
+                    })
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("Rasterization thread failed"))
+                .collect::<Vec<_>>()
+        });
+        for partial in partial_meshes.iter() {
+            self.merge(partial);
         }
     }
 
+    /// Encode `rgba8` as a PNG image.
+    pub fn write_png<W: Write>(&self, writer: W) -> Result<()> {
+        let mut encoder = png::Encoder::new(writer, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut png_writer = encoder.write_header()?;
+        png_writer.write_image_data(&self.rgba8)?;
+        Ok(())
+    }
+    pub fn save_png(&self, path: &str) -> Result<()> {
+        let file = std::fs::File::create(path)?;
+        self.write_png(std::io::BufWriter::new(file))
+    }
+
     pub fn test_pattern(&mut self) {
         for y in 0..self.height {
             for x in 0..self.width {
@@ -770,3 +1193,76 @@ impl Mesh {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parallel_rasterization() {
+        let mut mesh = Mesh::new();
+        mesh.resize(50, 40);
+        mesh.set_highlight_layers(2);
+        // several points in most cells, in all the layers
+        let points = (0..5000)
+            .map(|i| {
+                let x = ((i * 37) % 1013) as f64 / 1013.0;
+                let y = ((i * 91) % 997) as f64 / 997.0;
+                (x, y, 1.0 + (i % 3) as f64, i + 1, i % 3)
+            })
+            .collect::<Vec<_>>();
+        let rasterize =
+            |mesh: &mut Mesh, &(x, y, w, index, layer): &(f64, f64, f64, usize, usize)| {
+                mesh.point_gaussian(x, y, w, index, layer, 2.0)
+            };
+        let mut serial = Mesh::new();
+        serial.resize(50, 40);
+        serial.set_highlight_layers(2);
+        serial.add_points_in_parallel(&points, 1, rasterize);
+        mesh.add_points_in_parallel(&points, 4, rasterize);
+
+        assert_eq!(mesh.index_mesh, serial.index_mesh);
+        let close =
+            |a: &[f64], b: &[f64]| a.iter().zip(b.iter()).all(|(x, y)| (x - y).abs() < 1e-9);
+        assert!(close(&mesh.mesh, &serial.mesh));
+        assert!(close(&mesh.highlight_meshes[1], &serial.highlight_meshes[1]));
+        let total = mesh
+            .mesh
+            .iter()
+            .chain(mesh.highlight_meshes.iter().flatten())
+            .sum::<f64>();
+        assert!(total > 9000.0 && total <= 10000.0 + 1e-6);
+    }
+
+    #[test]
+    fn test_blur() {
+        let kernel = Mesh::gaussian_kernel(3.0);
+        assert_eq!(kernel.len(), 13);
+        assert!((kernel.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+        assert_eq!(Mesh::gaussian_kernel(0.0), vec![1.0]);
+
+        let (width, height) = (40, 30);
+        let mut smeared = Mesh::new();
+        smeared.resize(width, height);
+        smeared.set_highlight_layers(1);
+        // at the corner of a cell, where point_gaussian centers the kernel
+        let (x, y) = (20.0 / width as f64, 10.0 / height as f64);
+        smeared.point_gaussian(x, y, 2.0, 7, 1, 3.0);
+        let mut blurred = Mesh::new();
+        blurred.resize(width, height);
+        blurred.set_highlight_layers(1);
+        blurred.add_points_blurred(&[(x, y, 2.0, 7, 1)], 3.0);
+
+        assert_eq!(blurred.index_mesh, smeared.index_mesh);
+        assert!(blurred.mesh.iter().all(|&value| value == 0.0));
+        let (a, b) = (&blurred.highlight_meshes[0], &smeared.highlight_meshes[0]);
+        assert!(a.iter().zip(b.iter()).all(|(a, b)| (a - b).abs() < 1e-12));
+        assert!((a.iter().sum::<f64>() - 2.0).abs() < 1e-9);
+
+        // near the edge the density outside of the mesh is lost
+        blurred.clean();
+        blurred.add_points_blurred(&[(0.0, 0.0, 1.0, 1, 0)], 3.0);
+        let total = blurred.mesh.iter().sum::<f64>();
+        assert!(total > 0.2 && total < 0.5);
+    }
+}
@@ -0,0 +1,144 @@
+#![allow(dead_code)]
+use pointcloud_viewer::csv_loader::{CsvOptions, Encoding};
+
+const DELIMITERS: [(char, &str); 5] = [
+    (',', "Comma"),
+    (';', "Semicolon"),
+    ('\t', "Tab"),
+    (' ', "Space"),
+    ('|', "Pipe"),
+];
+const QUOTES: [(char, &str); 2] = [('"', "Double quote"), ('\'', "Single quote")];
+const ESCAPES: [(Option<char>, &str); 2] = [(None, "None"), (Some('\\'), "Backslash")];
+const COMMENTS: [(Option<char>, &str); 4] = [
+    (None, "None"),
+    (Some('#'), "#"),
+    (Some('%'), "%"),
+    (Some(';'), ";"),
+];
+const ENCODINGS: [Encoding; 2] = [Encoding::Utf8, Encoding::Latin1];
+
+fn label<T: PartialEq>(choices: &[(T, &'static str)], value: &T) -> &'static str {
+    choices
+        .iter()
+        .find(|(x, _)| x == value)
+        .map(|(_, label)| *label)
+        .unwrap_or("Other")
+}
+
+fn choice_ui<T: PartialEq + Copy>(
+    ui: &mut egui::Ui,
+    name: &str,
+    choices: &[(T, &'static str)],
+    value: &mut T,
+) {
+    ui.label(name);
+    egui::ComboBox::from_id_source(name)
+        .selected_text(label(choices, value))
+        .show_ui(ui, |ui| {
+            for (choice, text) in choices.iter() {
+                ui.selectable_value(value, *choice, *text);
+            }
+        });
+    ui.end_row();
+}
+
+/// Dialog to choose a file and the CSV dialect.
+pub struct OpenFileDialog {
+    pub path: String,
+    pub options: CsvOptions,
+    pub error: Option<String>,
+}
+
+impl OpenFileDialog {
+    pub fn new(path: &str, options: &CsvOptions) -> OpenFileDialog {
+        OpenFileDialog {
+            path: path.to_owned(),
+            options: options.clone(),
+            error: None,
+        }
+    }
+
+    /// Numbers with a decimal comma need another delimiter than the comma.
+    fn avoid_comma_delimiter(&mut self) {
+        if self.options.decimal_comma && self.options.delimiter == ',' {
+            self.options.delimiter = ';';
+        }
+    }
+
+    /// Show the dialog, returns true when the file should be opened.
+    pub fn show(&mut self, egui_ctx: &egui::Context, open: &mut bool) -> bool {
+        let mut clicked = false;
+        egui::Window::new("Open file")
+            .open(open)
+            .collapsible(false)
+            .show(egui_ctx, |ui| {
+                egui::Grid::new("Open file grid").show(ui, |ui| {
+                    ui.label("File");
+                    let response =
+                        ui.add(egui::TextEdit::singleline(&mut self.path).desired_width(300.0));
+                    if response.changed() {
+                        self.options.delimiter = CsvOptions::for_path(&self.path).delimiter;
+                        self.avoid_comma_delimiter();
+                    }
+                    ui.end_row();
+                    choice_ui(ui, "Delimiter", &DELIMITERS, &mut self.options.delimiter);
+                    choice_ui(ui, "Quote", &QUOTES, &mut self.options.quote);
+                    choice_ui(ui, "Escape", &ESCAPES, &mut self.options.escape);
+                    choice_ui(ui, "Comment", &COMMENTS, &mut self.options.comment);
+                    ui.label("Skip rows");
+                    ui.add(egui::DragValue::new(&mut self.options.skip_rows).clamp_range(0..=1000));
+                    ui.end_row();
+                    ui.label("");
+                    ui.checkbox(&mut self.options.has_headers, "Header");
+                    ui.end_row();
+                    ui.label("");
+                    if ui
+                        .checkbox(&mut self.options.decimal_comma, "Decimal comma")
+                        .changed()
+                    {
+                        self.avoid_comma_delimiter();
+                    }
+                    ui.end_row();
+                    ui.label("");
+                    ui.checkbox(&mut self.options.parse_datetimes, "Parse dates");
+                    ui.end_row();
+                    ui.label("Null values");
+                    let mut null_values = self.options.null_values.join(",");
+                    if ui
+                        .add(egui::TextEdit::singleline(&mut null_values).desired_width(300.0))
+                        .on_hover_text("Comma-separated values treated as missing")
+                        .changed()
+                    {
+                        self.options.null_values =
+                            null_values.split(',').map(|x| x.to_owned()).collect();
+                    }
+                    ui.end_row();
+                    ui.label("Encoding");
+                    egui::ComboBox::from_id_source("Encoding")
+                        .selected_text(self.options.encoding.text())
+                        .show_ui(ui, |ui| {
+                            for encoding in ENCODINGS.iter() {
+                                ui.selectable_value(
+                                    &mut self.options.encoding,
+                                    *encoding,
+                                    encoding.text(),
+                                );
+                            }
+                        });
+                    ui.end_row();
+                });
+                ui.label("The CSV options are ignored for Parquet and Arrow files.");
+                if let Err(error) = self.options.validate() {
+                    ui.colored_label(egui::Color32::RED, error.to_string());
+                }
+                if let Some(error) = &self.error {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+                if ui.button("Open").clicked() {
+                    clicked = true;
+                }
+            });
+        clicked
+    }
+}
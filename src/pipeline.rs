@@ -1,19 +1,30 @@
 #![allow(dead_code)]
+use crate::axis::{ticks, Scale, Tick};
+use crate::colormap::{Colormap, Rgb};
+use crate::csv_loader::CsvOptions;
+use crate::datetime::format_timestamp;
+use crate::evaluate::DerivedColumn;
+use crate::highlight::{
+    CombinedHighlightFilter, FilterCache, HighlightFilterVariants, HighlightGroup,
+};
 use crate::measures::*;
 use crate::mesh;
-use crate::mesh::HighlightType;
+use crate::mesh::{HighlightComposition, HighlightType, ValueStatistic};
 use crate::pointdata::*;
 use crate::transform::*;
 use anyhow::*;
 use bitvector::*;
-use macroquad::prelude::*;
 use std::collections::BTreeSet;
-use std::convert::TryInto;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 pub const ALL:&str = "All";
 pub const HIGHLIGHTED:&str = "Highlighted";
 pub const NON_HIGHLIGHTED:&str = "Non-Highlighted";
+/// Maximal number of categories of a column used to color the points.
+pub const MAX_COLOR_CATEGORIES: usize = 20;
+/// Last `PointData::generation`, shared by all the pipelines to keep the generations unique.
+static DATA_GENERATION: AtomicUsize = AtomicUsize::new(0);
 
 pub trait SimpleTable {
     fn transpose(&self) -> Vec<Vec<String>>;
@@ -77,7 +88,7 @@ pub enum Stage {
     Stage1XYI,
     Stage2Mesh,
     Stage3ProcessedMesh,
-    Stage4Texture,
+    Stage4Image,
 }
 
 impl Stage {
@@ -99,12 +110,12 @@ impl Stage {
                 Stage::Stage2Mesh => stage,
                 _ => self,
             },
-            Stage::Stage4Texture => stage,
+            Stage::Stage4Image => stage,
         }
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Copy)]
 pub enum TransformationType {
     Linear,
     Logarithmic,
@@ -129,6 +140,33 @@ impl TransformationType {
             TransformationType::QuantileNormal => "Quantile Normal",
         }
     }
+    /// Distribution of the values along an axis with this transformation.
+    pub fn scale(&self) -> Scale {
+        match self {
+            TransformationType::Linear => Scale::Linear,
+            TransformationType::Logarithmic => Scale::Logarithmic,
+            _ => Scale::Nonlinear,
+        }
+    }
+}
+
+/// Color-bar legend of the density plot.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorBar {
+    /// Colors from the lowest to the highest density.
+    pub colors: Vec<[u8; 3]>,
+    /// Round densities (weight per pixel), the position is relative to the length of the bar.
+    pub ticks: Vec<Tick>,
+}
+
+/// Category of the column coloring the points, shown in the legend.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Category {
+    pub name: String,
+    pub color: Rgb,
+    /// Number of rows in the category.
+    pub count: usize,
+    pub visible: bool,
 }
 
 impl ToString for TransformationType {
@@ -141,9 +179,12 @@ impl FromStr for TransformationType {
     type Err = Error;
     fn from_str(s: &str) -> Result<TransformationType> {
         match s {
-            "Linear" => Ok(TransformationType::Linear),
-            "Logarithmic" => Ok(TransformationType::Logarithmic),
-            "Quantile" => Ok(TransformationType::Quantile),
+            "Linear" | "linear" => Ok(TransformationType::Linear),
+            "Logarithmic" | "logarithmic" | "log" => Ok(TransformationType::Logarithmic),
+            "Quantile" | "quantile" => Ok(TransformationType::Quantile),
+            "Quantile Normal" | "QuantileNormal" | "quantile-normal" => {
+                Ok(TransformationType::QuantileNormal)
+            }
             _ => Err(anyhow!("Failed to resolve transformation type '{}'", s)),
         }
     }
@@ -153,12 +194,31 @@ pub struct Pipeline {
     pub point_data: PointData,
     pub data_columns: Vec<String>,
     pub aux_columns: Vec<String>,
+    /// Columns added to the data from expressions, in the order of creation.
+    pub derived_columns: Vec<DerivedColumn>,
     //    pub highlightable_values: Vec<String>,
     pub parameters: mesh::Parameters,
     pub mesh: mesh::Mesh,
     pub unit_weights: Vec<f64>,
-    pub highlights: BitVector,
-    pub xyi: Vec<(f64, f64, f64, usize, bool)>,
+    /// Filter of the rows shown in the plot and the statistics, all rows are shown
+    /// if it has no enabled filter.
+    pub row_filter: CombinedHighlightFilter,
+    /// Rows matching the row filter.
+    pub shown_rows: BitVector,
+    pub highlight_groups: Vec<HighlightGroup>,
+    /// Points of each highlight group.
+    pub highlights: Vec<BitVector>,
+    /// Points of the clauses of the row filter and of the highlight groups.
+    pub row_filter_cache: FilterCache,
+    pub highlight_cache: FilterCache,
+    /// Categories of the color column, empty if the points are not colored by a column.
+    pub categories: Vec<Category>,
+    /// Index of the category of each row.
+    pub category_codes: Vec<Option<usize>>,
+    /// Transformed points (x, y, weight, index + 1, mesh layer); the layer of a highlighted point
+    /// is 1 + the index of the first group containing it, see `mesh::Mesh::layer_mut`.
+    /// When coloring by a column, the layer is 1 + the index of the category instead.
+    pub xyi: Vec<(f64, f64, f64, usize, usize)>,
     pub txtype: TransformationType,
     pub tytype: TransformationType,
     pub tx: Box<dyn Transform>,
@@ -167,21 +227,34 @@ pub struct Pipeline {
     pub aspect_ratio: f64,
     pub ox: f64,
     pub oy: f64,
-    pub texture: Option<Texture2D>,
     pub stage: Stage,
 }
 
+impl Default for Pipeline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Pipeline {
     pub fn new() -> Pipeline {
         Pipeline {
             point_data: PointData::new(),
             data_columns: Vec::<_>::new(),
             aux_columns: Vec::<_>::new(),
+            derived_columns: Vec::new(),
             //            highlightable_values: Vec::<_>::new(),
             parameters: mesh::Parameters::new(),
             mesh: mesh::Mesh::new(),
             unit_weights: Vec::<_>::new(),
-            highlights: BitVector::new(0),
+            row_filter: CombinedHighlightFilter::new(),
+            shown_rows: BitVector::new(0),
+            highlight_groups: HighlightGroup::default_groups(),
+            highlights: Vec::new(),
+            row_filter_cache: FilterCache::new(),
+            highlight_cache: FilterCache::new(),
+            categories: Vec::new(),
+            category_codes: Vec::new(),
             xyi: Vec::<_>::new(),
             txtype: TransformationType::Linear,
             tytype: TransformationType::Linear,
@@ -191,23 +264,59 @@ impl Pipeline {
             aspect_ratio: 1.0,
             ox: 0.5,
             oy: 0.5,
-            texture: None,
             stage: Stage::Stage0NewData,
         }
     }
-    pub async fn load(&mut self, path: &str) -> Result<()> {
-        let csv_content = load_file(path).await?;
-        self.point_data = PointData::from_csv(&mut csv_content.as_slice())?;
+    pub fn load_csv<R: std::io::Read + std::io::Seek>(&mut self, reader: &mut R) -> Result<()> {
+        self.set_point_data(PointData::from_csv(reader)?);
+        Ok(())
+    }
+    pub fn load_csv_file(&mut self, path: &str) -> Result<()> {
+        let mut file = std::fs::File::open(path)?;
+        self.load_csv(&mut file)
+    }
+    /// Load the content of a CSV, Arrow IPC or Parquet file (see `PointData::from_file_content`),
+    /// used on WebAssembly; native builds use `load`.
+    pub fn load_file_content(
+        &mut self,
+        path: &str,
+        content: Vec<u8>,
+        options: &CsvOptions,
+        columns: Option<&[&str]>,
+    ) -> Result<()> {
+        self.set_point_data(PointData::from_file_content(path, content, options, columns)?);
+        Ok(())
+    }
+    /// Load a CSV, Arrow IPC or Parquet file (see `PointData::from_file`).
+    pub fn load(
+        &mut self,
+        path: &str,
+        options: &CsvOptions,
+        columns: Option<&[&str]>,
+    ) -> Result<()> {
+        self.set_point_data(PointData::from_file(path, options, columns)?);
+        Ok(())
+    }
+    /// Mark the point data as changed, see `PointData::generation`.
+    fn next_data_generation(&mut self) {
+        self.point_data.generation = DATA_GENERATION.fetch_add(1, Ordering::Relaxed) + 1;
+    }
+    pub fn set_point_data(&mut self, point_data: PointData) {
+        self.point_data = point_data;
+        self.next_data_generation();
         self.stage = Stage::Stage0NewData;
         self.data_columns.clear();
         self.aux_columns.clear();
+        self.derived_columns.clear();
         for column in self.point_data.headers.iter() {
             if self.point_data.data.contains_key(column) {
                 self.data_columns.push(column.to_owned());
             }
         }
         for column in self.point_data.headers.iter() {
-            if self.point_data.aux.contains_key(column) {
+            if self.point_data.aux.contains_key(column)
+                || self.point_data.categorical.contains_key(column)
+            {
                 self.aux_columns.push(column.to_owned());
             }
         }
@@ -224,7 +333,27 @@ impl Pipeline {
         for _i in 0..self.point_data.length {
             self.unit_weights.push(1.0);
         }
-
+        self.row_filter_cache.clear();
+        self.highlight_cache.clear();
+        self.update_shown_rows();
+        self.update_highlights();
+    }
+    /// Add a column computed from an expression of the other columns.
+    pub fn add_derived_column(&mut self, column: DerivedColumn) -> Result<()> {
+        column.add_to(&mut self.point_data)?;
+        self.next_data_generation();
+        let name = column.name.trim().to_owned();
+        if self.point_data.data.contains_key(&name) {
+            self.data_columns.push(name.clone());
+        }
+        self.aux_columns.push(name);
+        self.derived_columns.push(column);
+        // the filters may use the new column
+        self.row_filter_cache.clear();
+        self.highlight_cache.clear();
+        self.update_shown_rows();
+        self.update_highlights();
+        self.stage = Stage::Stage0NewData;
         Ok(())
     }
     pub fn filter_headers(&mut self, filter: &dyn Fn(&str) -> bool) {
@@ -260,6 +389,98 @@ impl Pipeline {
         self.parameters.ymax = y2;
     }
 
+    /// Value of the x column at a relative position on the plot (0 is the left edge, 1 the right edge).
+    pub fn x_value_at(&self, position: f64) -> Option<f64> {
+        let p = &self.parameters;
+        self.tx.inverse(p.xmin + position * (p.xmax - p.xmin))
+    }
+    /// Value of the y column at a relative position on the plot (0 is the top edge, 1 the bottom edge).
+    pub fn y_value_at(&self, position: f64) -> Option<f64> {
+        let p = &self.parameters;
+        self.ty.inverse(1.0 - (p.ymin + position * (p.ymax - p.ymin)))
+    }
+    /// Polygon filter of the x and y columns from a polygon drawn on the plot,
+    /// the vertices are relative positions on the plot (0 is the top left corner).
+    /// The axes are transformed independently, so the edges of a rectangle stay straight,
+    /// other edges only approximate the drawn shape on non-linear axes.
+    pub fn selection_filter(&self, positions: &[(f64, f64)]) -> Option<HighlightFilterVariants> {
+        if positions.len() < 3 || self.xcolumn().is_empty() || self.ycolumn().is_empty() {
+            return None;
+        }
+        let polygon = positions
+            .iter()
+            .map(|&(x, y)| Some((self.x_value_at(x)?, self.y_value_at(y)?)))
+            .collect::<Option<Vec<_>>>()?;
+        Some(HighlightFilterVariants::Polygon(
+            self.xcolumn().to_owned(),
+            self.ycolumn().to_owned(),
+            polygon,
+        ))
+    }
+    /// Ticks of the x axis, the positions are relative to the plot width.
+    pub fn x_ticks(&self, count: usize) -> Vec<Tick> {
+        let datetime = self.point_data.column_type(self.xcolumn()) == Some(ColumnType::DateTime);
+        let p = &self.parameters;
+        ticks(self.tx.as_ref(), self.txtype.scale(), datetime, p.xmin, p.xmax, count)
+    }
+    /// Ticks of the y axis, the positions are relative to the plot height (0 is the top edge).
+    pub fn y_ticks(&self, count: usize) -> Vec<Tick> {
+        let datetime = self.point_data.column_type(self.ycolumn()) == Some(ColumnType::DateTime);
+        let p = &self.parameters;
+        ticks(self.ty.as_ref(), self.tytype.scale(), datetime, 1.0 - p.ymin, 1.0 - p.ymax, count)
+    }
+    /// Legend of the colors of the density plot. If only the highlighted points are shown,
+    /// the bar has the color of the highlight group, or white for several groups or categories.
+    /// When coloring by values, the bar shows the values of the value column.
+    pub fn color_bar(&self, steps: usize, count: usize) -> ColorBar {
+        if self.value_mode() {
+            let colormap = &self.parameters.value_colormap;
+            let (minimum, maximum) = self.mesh.value_range;
+            let mut scale = Normalize::new();
+            scale.calibrate(&[minimum, maximum]);
+            let datetime = self.point_data.column_type(self.value_column())
+                == Some(ColumnType::DateTime);
+            return ColorBar {
+                colors: (0..steps)
+                    .map(|i| colormap.color(i as f64 / (steps.max(2) - 1) as f64))
+                    .collect(),
+                ticks: ticks(&scale, Scale::Linear, datetime, 0.0, 1.0, count),
+            };
+        }
+        let highlighted_only = self.highlight_type() == HighlightType::HighlighedOnly;
+        let highlight_color = match self.layer_colors().as_slice() {
+            [color] => *color,
+            _ => Rgb([255, 255, 255]),
+        };
+        let colors = (0..steps)
+            .map(|i| {
+                let level = i as f64 / (steps.max(2) - 1) as f64;
+                if highlighted_only {
+                    let color = highlight_color.0;
+                    [0, 1, 2].map(|channel| (level * color[channel] as f64) as u8)
+                } else {
+                    self.parameters.colormap.color(level)
+                }
+            })
+            .collect();
+        let density_scale = if highlighted_only {
+            &self.mesh.highlight_density_scale
+        } else {
+            &self.mesh.density_scale
+        };
+        ColorBar {
+            colors,
+            ticks: ticks(density_scale, Scale::Nonlinear, false, 0.0, 1.0, count),
+        }
+    }
+    /// Format a value of a column for an axis label; datetime values are shown as dates.
+    pub fn format_axis_value(&self, column: &str, value: f64) -> String {
+        match self.point_data.column_type(column) {
+            Some(ColumnType::DateTime) => format_timestamp(value.round()),
+            _ => format!("{:.3}", value),
+        }
+    }
+
     pub fn get_zoom(&self) -> f64 {
         self.zoom
     }
@@ -323,12 +544,209 @@ impl Pipeline {
         &self.parameters.highlight_column
     }
     */
-    pub fn set_highlights(&mut self, new_highlights: BitVector) {
-        if self.highlights != new_highlights {
-            self.highlights = new_highlights;
+    fn update_shown_rows(&mut self) {
+        self.shown_rows = if self.row_filter.is_active() {
+            let shown = self.row_filter_cache.combined(&self.row_filter, &self.point_data);
+            self.row_filter_cache.retain_used();
+            shown
+        } else {
+            let mut all = BitVector::new(self.point_data.len());
+            for i in 0..self.point_data.len() {
+                all.insert(i);
+            }
+            all
+        };
+    }
+    /// Set the filter of the shown rows; the rows not matching it are removed from the plot,
+    /// the calibration of the transformations and the statistics.
+    pub fn set_row_filter(&mut self, filter: CombinedHighlightFilter) {
+        if self.row_filter != filter {
+            self.row_filter = filter;
+            self.update_shown_rows();
             self.stage = Stage::Stage0NewData;
         }
     }
+    /// Number of rows matching the row filter.
+    pub fn shown_count(&self) -> usize {
+        self.shown_rows.len()
+    }
+    /// Values of the rows matching the row filter.
+    fn shown_values(&self, values: &[f64]) -> Vec<f64> {
+        self.shown_rows.iter().map(|i| values[i]).collect()
+    }
+    /// Evaluate the highlight groups, only the changed clauses are evaluated again.
+    fn update_highlights(&mut self) {
+        let cache = &mut self.highlight_cache;
+        let point_data = &self.point_data;
+        self.highlights = self
+            .highlight_groups
+            .iter()
+            .map(|group| cache.group(group, point_data))
+            .collect();
+        cache.retain_used();
+    }
+    /// Set the highlight groups, the highlights are evaluated only when the filters change.
+    /// Returns true if the highlighted points or the group names changed,
+    /// i.e. the statistics have to be updated.
+    pub fn set_highlight_groups(&mut self, groups: Vec<HighlightGroup>) -> bool {
+        let names_changed = self.highlight_groups.len() != groups.len()
+            || self
+                .highlight_groups
+                .iter()
+                .zip(groups.iter())
+                .any(|(old, new)| old.name != new.name);
+        let filters_changed = self.highlight_groups.len() != groups.len()
+            || self
+                .highlight_groups
+                .iter()
+                .zip(groups.iter())
+                .any(|(old, new)| old.filter != new.filter);
+        let colors_changed = self
+            .highlight_groups
+            .iter()
+            .zip(groups.iter())
+            .any(|(old, new)| old.color != new.color);
+        self.highlight_groups = groups;
+        let mut highlights_changed = false;
+        if filters_changed {
+            let old_highlights = std::mem::take(&mut self.highlights);
+            self.update_highlights();
+            highlights_changed = old_highlights.len() != self.highlights.len()
+                || old_highlights
+                    .iter()
+                    .zip(self.highlights.iter())
+                    .any(|(old, new)| !old.iter().eq(new.iter()));
+        }
+        if highlights_changed {
+            self.stage = Stage::Stage0NewData;
+        } else if colors_changed {
+            self.stage = self.stage.down(Stage::Stage3ProcessedMesh);
+        }
+        highlights_changed || names_changed
+    }
+    /// Points highlighted by any of the groups.
+    pub fn highlighted(&self) -> BitVector {
+        let mut highlighted = BitVector::new(self.point_data.len());
+        for highlights in self.highlights.iter() {
+            highlighted.union_inplace(highlights);
+        }
+        highlighted
+    }
+    pub fn highlight_composition(&self) -> HighlightComposition {
+        self.parameters.highlight_composition
+    }
+    pub fn set_highlight_composition(&mut self, value: HighlightComposition) {
+        if self.parameters.highlight_composition != value {
+            self.parameters.highlight_composition = value;
+            self.stage = self.stage.down(Stage::Stage3ProcessedMesh);
+        }
+    }
+    pub fn color_column(&self) -> &str {
+        &self.parameters.color_column
+    }
+    /// Color the points by the categories of a column, empty column to switch the coloring off.
+    pub fn set_color_column(&mut self, column: String) {
+        if self.parameters.color_column != column {
+            self.parameters.color_column = column;
+            self.parameters.hidden_categories.clear();
+            self.stage = Stage::Stage0NewData;
+        }
+    }
+    /// The points are colored by categories
+    /// (the color column has at most `MAX_COLOR_CATEGORIES` values).
+    pub fn color_by_categories(&self) -> bool {
+        !self.categories.is_empty()
+    }
+    pub fn set_hidden_categories(&mut self, categories: Vec<String>) {
+        if self.parameters.hidden_categories != categories {
+            self.parameters.hidden_categories = categories;
+            self.stage = Stage::Stage0NewData;
+        }
+    }
+    /// Show or hide the points of a category.
+    pub fn set_category_visible(&mut self, name: &str, visible: bool) {
+        let hidden = &self.parameters.hidden_categories;
+        if hidden.iter().any(|x| x == name) == visible {
+            let mut hidden = hidden.clone();
+            hidden.retain(|x| x != name);
+            if !visible {
+                hidden.push(name.to_owned());
+            }
+            self.set_hidden_categories(hidden);
+        }
+    }
+    fn update_categories(&mut self) {
+        self.categories.clear();
+        self.category_codes.clear();
+        if self.parameters.color_column.is_empty() {
+            return;
+        }
+        if let Some((names, codes)) = self
+            .point_data
+            .category_codes(&self.parameters.color_column, MAX_COLOR_CATEGORIES)
+        {
+            let mut counts = vec![0; names.len()];
+            for &code in codes.iter().flatten() {
+                counts[code] += 1;
+            }
+            let hidden = &self.parameters.hidden_categories;
+            self.categories = names
+                .into_iter()
+                .zip(counts)
+                .enumerate()
+                .map(|(i, (name, count))| Category {
+                    visible: !hidden.contains(&name),
+                    name,
+                    color: Rgb::palette(i),
+                    count,
+                })
+                .collect();
+            self.category_codes = codes;
+        }
+    }
+    /// Colors of the highlight layers of the mesh: of the categories or of the highlight groups.
+    pub fn layer_colors(&self) -> Vec<Rgb> {
+        if self.color_by_categories() {
+            self.categories.iter().map(|x| x.color).collect()
+        } else {
+            self.highlight_groups.iter().map(|x| x.color).collect()
+        }
+    }
+
+    pub fn value_column(&self) -> &str {
+        &self.parameters.value_column
+    }
+    /// Show a statistic of a numeric column in each cell instead of the density,
+    /// empty column to show the density.
+    pub fn set_value_column(&mut self, column: String) {
+        if self.parameters.value_column != column {
+            self.parameters.value_column = column;
+            self.stage = Stage::Stage0NewData;
+        }
+    }
+    /// The cells show the values of a numeric value column.
+    pub fn value_mode(&self) -> bool {
+        self.point_data.data.contains_key(self.value_column())
+    }
+    pub fn value_statistic(&self) -> ValueStatistic {
+        self.parameters.value_statistic
+    }
+    pub fn set_value_statistic(&mut self, value: ValueStatistic) {
+        if self.parameters.value_statistic != value {
+            self.parameters.value_statistic = value;
+            self.stage = self.stage.down(Stage::Stage2Mesh);
+        }
+    }
+    pub fn value_colormap(&self) -> &Colormap {
+        &self.parameters.value_colormap
+    }
+    pub fn set_value_colormap(&mut self, colormap: Colormap) {
+        if self.parameters.value_colormap != colormap {
+            self.parameters.value_colormap = colormap;
+            self.stage = self.stage.down(Stage::Stage3ProcessedMesh);
+        }
+    }
+
     pub fn highlight_type(&self) -> HighlightType {
         self.parameters.highlight_type
     }
@@ -398,6 +816,15 @@ impl Pipeline {
         }
         self.parameters.point_sigma = value;
     }
+    pub fn blur_mesh(&self) -> bool {
+        self.parameters.blur_mesh
+    }
+    pub fn set_blur_mesh(&mut self, flag: bool) {
+        if self.parameters.blur_mesh != flag && self.gaussian_points() {
+            self.stage = self.stage.down(Stage::Stage1XYI);
+        }
+        self.parameters.blur_mesh = flag;
+    }
     pub fn density_multiplier(&self) -> f64 {
         self.parameters.density_multiplier
     }
@@ -417,6 +844,32 @@ impl Pipeline {
         }
     }
 
+    pub fn colormap(&self) -> &Colormap {
+        &self.parameters.colormap
+    }
+    pub fn set_colormap(&mut self, colormap: Colormap) {
+        if self.parameters.colormap != colormap {
+            self.parameters.colormap = colormap;
+            self.stage = self.stage.down(Stage::Stage3ProcessedMesh);
+        }
+    }
+    /// Color of the first highlight group.
+    pub fn highlight_color(&self) -> Rgb {
+        self.highlight_groups
+            .first()
+            .map(|group| group.color)
+            .unwrap_or_else(|| HighlightGroup::new(0).color)
+    }
+    /// Set the color of the first highlight group.
+    pub fn set_highlight_color(&mut self, color: Rgb) {
+        let mut groups = self.highlight_groups.clone();
+        if groups.is_empty() {
+            groups.push(HighlightGroup::new(0));
+        }
+        groups[0].color = color;
+        self.set_highlight_groups(groups);
+    }
+
     pub fn weights(&self) -> &Vec<f64> {
         if self.weight_column() == "" {
             &self.unit_weights
@@ -477,65 +930,101 @@ impl Pipeline {
             }
         }
         */
-        if self.highlights.len() != self.point_data.length {
-            self.highlights = BitVector::new(self.point_data.len()) | self.highlights.clone();
+        // mesh layer of each row, None for the hidden categories
+        self.update_categories();
+        let mut layers = vec![Some(0); self.point_data.len()];
+        if self.color_by_categories() {
+            for (layer, code) in layers.iter_mut().zip(self.category_codes.iter()) {
+                if let Some(code) = *code {
+                    *layer = Some(code + 1).filter(|_| self.categories[code].visible);
+                }
+            }
+        } else {
+            for (group, highlights) in self.highlights.iter().enumerate().rev() {
+                for i in highlights.iter() {
+                    layers[i] = Some(group + 1);
+                }
+            }
+        }
+        for (i, layer) in layers.iter_mut().enumerate() {
+            if !self.shown_rows.contains(i) {
+                *layer = None;
+            }
+        }
+        if self.value_mode() {
+            // points without a value are not shown
+            for (layer, value) in layers.iter_mut().zip(self.point_data.data[self.value_column()].iter()) {
+                if value.is_nan() {
+                    *layer = None;
+                }
+            }
         }
 
-        if self.point_data.data.contains_key(self.xcolumn())
+        if !self.point_data.contains_column(self.xcolumn())
+            || !self.point_data.contains_column(self.ycolumn())
+        {
+            // e.g. a CSV file read with a wrong delimiter - nothing to plot
+        } else if self.point_data.data.contains_key(self.xcolumn())
             && self.point_data.data.contains_key(self.ycolumn())
         {
             self.tx = self.txtype.to_transform();
             self.ty = self.tytype.to_transform();
             let xdata = &self.point_data.data[self.xcolumn()];
             let ydata = &self.point_data.data[self.ycolumn()];
-            self.tx.calibrate(xdata);
-            self.ty.calibrate(ydata);
+            if self.row_filter.is_active() {
+                let (xshown, yshown) = (self.shown_values(xdata), self.shown_values(ydata));
+                self.tx.calibrate(&xshown);
+                self.ty.calibrate(&yshown);
+            } else {
+                self.tx.calibrate(xdata);
+                self.ty.calibrate(ydata);
+            }
             for (i, (&x, &y)) in xdata.iter().zip(ydata.iter()).enumerate() {
+                if x.is_nan() || y.is_nan() {
+                    // missing value
+                    continue;
+                }
                 if let (Some(xx), Some(yy)) = (self.tx.transform(x), self.ty.transform(y)) {
                     let w: f64 = self.weights()[i];
-                    self.xyi
-                        .push((xx, 1.0 - yy, w, i + 1, self.highlights.contains(i)));
+                    if let Some(layer) = layers[i].filter(|_| !w.is_nan()) {
+                        self.xyi.push((xx, 1.0 - yy, w, i + 1, layer));
+                    }
                 }
             }
         } else {
             let xdata = if self.point_data.data.contains_key(self.xcolumn()) {
                 self.point_data.data[self.xcolumn()]
                     .iter()
-                    .map(|x| Some(*x))
+                    .map(|x| Some(*x).filter(|x| !x.is_nan()))
                     .collect::<Vec<_>>()
             } else {
-                assert!(self.point_data.aux.contains_key(self.xcolumn()));
-                self.point_data.aux[self.xcolumn()]
-                    .iter()
-                    .map(|x| x.parse::<f64>().ok())
-                    .collect::<Vec<_>>()
+                self.point_data.text_as_numbers(self.xcolumn()).unwrap()
             };
             let ydata = if self.point_data.data.contains_key(self.ycolumn()) {
                 self.point_data.data[self.ycolumn()]
                     .iter()
-                    .map(|x| Some(*x))
+                    .map(|x| Some(*x).filter(|x| !x.is_nan()))
                     .collect::<Vec<_>>()
             } else {
-                assert!(self.point_data.aux.contains_key(self.ycolumn()));
-                self.point_data.aux[self.ycolumn()]
-                    .iter()
-                    .map(|x| x.parse::<f64>().ok())
-                    .collect::<Vec<_>>()
+                self.point_data.text_as_numbers(self.ycolumn()).unwrap()
             };
 
+            let shown_rows = &self.shown_rows;
             self.tx.calibrate(
                 xdata
                     .iter()
-                    .flatten()
-                    .map(|x| *x)
+                    .enumerate()
+                    .filter(|(i, _)| shown_rows.contains(*i))
+                    .filter_map(|(_, x)| *x)
                     .collect::<Vec<_>>()
                     .as_slice(),
             );
             self.ty.calibrate(
                 ydata
                     .iter()
-                    .flatten()
-                    .map(|x| *x)
+                    .enumerate()
+                    .filter(|(i, _)| shown_rows.contains(*i))
+                    .filter_map(|(_, y)| *y)
                     .collect::<Vec<_>>()
                     .as_slice(),
             );
@@ -543,8 +1032,9 @@ impl Pipeline {
                 if let (&Some(x), &Some(y)) = pair {
                     if let (Some(xx), Some(yy)) = (self.tx.transform(x), self.ty.transform(y)) {
                         let w: f64 = self.weights()[i];
-                        self.xyi
-                            .push((xx, 1.0 - yy, w, i + 1, self.highlights.contains(i)));
+                        if let Some(layer) = layers[i].filter(|_| !w.is_nan()) {
+                            self.xyi.push((xx, 1.0 - yy, w, i + 1, layer));
+                        }
                     }
                 }
             }
@@ -592,17 +1082,19 @@ impl Pipeline {
         for row in self.statistics_table(ALL, None).transpose() {
             data.push(row);
         }
-        if self.highlights.len() > 0 {
-            for row in self
-                .statistics_table(HIGHLIGHTED, Some(&self.highlights))
-                .transpose()
-            {
-                data.push(row);
-            }
-            let mut nonhighlighted = BitVector::ones(self.point_data.len());
-            for i in self.highlights.iter() {
-                nonhighlighted.remove(i);
+        let highlighted = self.highlighted();
+        if highlighted.iter().next().is_some() {
+            for (group, highlights) in self.highlight_groups.iter().zip(self.highlights.iter()) {
+                if highlights.iter().next().is_some() {
+                    for row in self
+                        .statistics_table(&group.name, Some(highlights))
+                        .transpose()
+                    {
+                        data.push(row);
+                    }
+                }
             }
+            let nonhighlighted = self.shown_rows.difference(&highlighted);
             for row in self
                 .statistics_table(NON_HIGHLIGHTED, Some(&nonhighlighted))
                 .transpose()
@@ -619,6 +1111,16 @@ impl Pipeline {
         group_name: &str,
         selection: Option<&BitVector>,
     ) -> Vec<Vec<String>> {
+        // the rows removed by the row filter are not counted
+        let shown;
+        let selection = match selection {
+            Some(bv) if self.row_filter.is_active() => {
+                shown = bv.intersection(&self.shown_rows);
+                Some(&shown)
+            }
+            None if self.row_filter.is_active() => Some(&self.shown_rows),
+            selection => selection,
+        };
         let mut table = Vec::new();
         let weights = self.weights();
         let measure_names = NumericStatistics::new().all_measure_names();
@@ -643,17 +1145,59 @@ impl Pipeline {
         table
     }
 
+    /// Points of the value mode: weights in layer 0 and weight × value in the value layer.
+    fn value_points(&self) -> Vec<(f64, f64, f64, usize, usize)> {
+        let values = &self.point_data.data[self.value_column()];
+        let mut points = Vec::with_capacity(2 * self.xyi.len());
+        for &(x, y, w, index, _) in self.xyi.iter() {
+            points.push((x, y, w, index, 0));
+            points.push((x, y, w * values[index - 1], index, mesh::VALUE_LAYER));
+        }
+        points
+    }
+
     pub fn add_points(&mut self) {
-        if self.parameters.gaussian_points {
+        let value_points = if self.value_mode() {
+            Some(self.value_points())
+        } else {
+            None
+        };
+        let points = value_points.as_deref().unwrap_or(&self.xyi);
+        if self.parameters.gaussian_points && self.parameters.blur_mesh {
+            self.mesh
+                .add_points_blurred(points, self.parameters.point_sigma);
+        } else if self.parameters.gaussian_points {
             self.mesh
-                .add_points_gaussian(&self.xyi, self.parameters.point_sigma);
+                .add_points_gaussian(points, self.parameters.point_sigma);
         } else {
-            self.mesh.add_points(&self.xyi, false);
+            self.mesh.add_points(points, false);
         }
         self.stage = Stage::Stage2Mesh;
     }
 
+    /// Statistic of the value column in each cell.
+    fn process_value_mesh(&mut self) {
+        match self.value_statistic() {
+            ValueStatistic::Mean => self.mesh.to_processed_value_mesh_mean(),
+            statistic => {
+                let values = &self.point_data.data[self.value_column()];
+                let points = self
+                    .xyi
+                    .iter()
+                    .map(|&(x, y, w, index, _)| (x, y, w, values[index - 1]))
+                    .collect::<Vec<_>>();
+                self.mesh.to_processed_value_mesh_binned(&points, statistic);
+            }
+        }
+        self.mesh.update_value_range();
+    }
+
     pub fn to_processed_mesh(&mut self) {
+        if self.value_mode() {
+            self.process_value_mesh();
+            self.stage = Stage::Stage3ProcessedMesh;
+            return;
+        }
         match self.highlight_type() {
             HighlightType::Highlight => {
                 self.mesh.to_processed_mesh();
@@ -695,34 +1239,31 @@ impl Pipeline {
     }
 
     pub fn to_rgba8(&mut self) {
+        let colormap = &self.parameters.colormap;
+        let colors = self.layer_colors();
+        let composition = self.parameters.highlight_composition;
+        if self.value_mode() {
+            self.mesh.to_rgba8_values(&self.parameters.value_colormap);
+            return;
+        }
         match self.highlight_type() {
             HighlightType::Highlight => {
-                self.mesh.to_rgba8_blue_cyan();
-                self.mesh.add_rgba8_red_highlight();
+                self.mesh.to_rgba8_colormap(colormap);
+                self.mesh.add_rgba8_highlights(&colors, composition);
             }
             HighlightType::NoHighlight => {
-                self.mesh.to_rgba8_blue_cyan();
+                self.mesh.to_rgba8_colormap(colormap);
             }
             HighlightType::HighlighedOnly => {
                 self.mesh.clean_rgba();
-                self.mesh.add_rgba8_red_highlight();
+                self.mesh.add_rgba8_highlights(&colors, composition);
             }
             HighlightType::NonHighlightedOnly => {
-                self.mesh.to_rgba8_blue_cyan();
+                self.mesh.to_rgba8_colormap(colormap);
             }
         }
     }
 
-    pub fn to_texture(&mut self) {
-        let texture = Texture2D::from_rgba8(
-            self.mesh.width.try_into().unwrap(),
-            self.mesh.height.try_into().unwrap(),
-            &self.mesh.rgba8,
-        );
-        self.texture = Some(texture);
-        self.stage = Stage::Stage4Texture;
-    }
-
     pub fn pipeline_step(&mut self) -> bool {
         //        println!("Pipeline step {:?}", self.stage);
         match self.stage {
@@ -732,6 +1273,7 @@ impl Pipeline {
             }
             Stage::Stage1XYI => {
                 self.update_view_box();
+                self.mesh.set_highlight_layers(self.layer_colors().len());
                 self.parameters.adapt_mesh(&mut self.mesh);
                 self.add_points();
                 false
@@ -742,14 +1284,330 @@ impl Pipeline {
             }
             Stage::Stage3ProcessedMesh => {
                 self.to_rgba8();
-                self.to_texture();
+                self.stage = Stage::Stage4Image;
                 false
             }
-            Stage::Stage4Texture => true,
+            Stage::Stage4Image => true,
         }
     }
 
-    pub fn run(&mut self) {
+    /// Run all the stages up to the RGBA image in `mesh.rgba8`.
+    /// Returns true if the image has changed.
+    pub fn run(&mut self) -> bool {
+        let changed = self.stage != Stage::Stage4Image;
         while !self.pipeline_step() {}
+        changed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_run() {
+        let mut pipeline = Pipeline::new();
+        pipeline.set_point_data(test_point_data_circle(1000).unwrap());
+        pipeline.parameters.mesh_width = 40;
+        pipeline.parameters.mesh_height = 30;
+        assert!(pipeline.run());
+        assert!(!pipeline.run());
+        assert_eq!(pipeline.mesh.rgba8.len(), 4 * 40 * 30);
+        assert!(pipeline.mesh.rgba8.chunks(4).any(|pixel| pixel[2] > 0));
+        assert_eq!(pipeline.xyi.len(), 1000);
+
+        let mut png = Vec::new();
+        pipeline.mesh.write_png(&mut png).unwrap();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    }
+
+    #[test]
+    fn test_load() {
+        let path = std::env::temp_dir().join("pointcloud-viewer-test-load.csv");
+        let path = path.to_str().unwrap();
+        std::fs::write(path, "x,y,code\n1,2,01\n3,4,A\n").unwrap();
+        let mut pipeline = Pipeline::new();
+        pipeline
+            .load(path, &CsvOptions::new(), Some(&["x", "code"]))
+            .unwrap();
+        assert_eq!(pipeline.point_data.all_headers, vec!["x", "code"]);
+        assert_eq!(pipeline.point_data.get("code", 0), "01");
+        std::fs::remove_file(path).unwrap();
+        assert!(pipeline.load(path, &CsvOptions::new(), None).is_err());
+    }
+
+    #[test]
+    fn test_data_generation() {
+        let mut pipeline = Pipeline::new();
+        pipeline.set_point_data(test_point_data().unwrap());
+        let generation = pipeline.point_data.generation;
+        assert!(generation > 0);
+        pipeline
+            .add_derived_column(DerivedColumn::new("z", "x + y"))
+            .unwrap();
+        assert!(pipeline.point_data.generation > generation);
+        let generation = pipeline.point_data.generation;
+        let mut other = Pipeline::new();
+        other.set_point_data(test_point_data().unwrap());
+        assert_ne!(other.point_data.generation, generation);
+    }
+
+    #[test]
+    fn test_time_axis() {
+        let mut pipeline = Pipeline::new();
+        pipeline
+            .load_csv(&mut std::io::Cursor::new(
+                "time,y\n2023-05-01,1\n2023-05-02,2\n2023-05-03,3\n",
+            ))
+            .unwrap();
+        pipeline.set_xcolumn("time".to_owned());
+        pipeline.set_ycolumn("y".to_owned());
+        pipeline.set_txtype(TransformationType::Linear);
+        pipeline.set_tytype(TransformationType::Linear);
+        pipeline.run();
+        assert_eq!(pipeline.xyi.len(), 3);
+        let start = pipeline.x_value_at(0.0).unwrap();
+        let end = pipeline.x_value_at(1.0).unwrap();
+        assert!(start <= 1682899200.0 && end >= 1683072000.0);
+        assert!(pipeline.y_value_at(0.0).unwrap() > pipeline.y_value_at(1.0).unwrap());
+        assert_eq!(
+            pipeline.format_axis_value("time", 1683072000.0),
+            "2023-05-03"
+        );
+        assert_eq!(pipeline.format_axis_value("y", 2.5), "2.500");
+    }
+
+    #[test]
+    fn test_ticks() {
+        let mut pipeline = Pipeline::new();
+        pipeline.set_point_data(test_point_data_circle(1000).unwrap());
+        pipeline.set_xcolumn("x".to_owned());
+        pipeline.set_ycolumn("y".to_owned());
+        pipeline.set_tytype(TransformationType::Quantile);
+        pipeline.run();
+        let labels = pipeline
+            .x_ticks(4)
+            .iter()
+            .map(|tick| tick.label.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(labels, vec!["-1.0", "-0.5", "0.0", "0.5", "1.0"]);
+        let y_ticks = pipeline.y_ticks(4);
+        assert!(y_ticks.len() >= 3);
+        // the top of the plot shows the highest values
+        assert!(y_ticks.windows(2).all(|x| x[0].value > x[1].value));
+
+        let color_bar = pipeline.color_bar(16, 4);
+        assert_eq!(color_bar.colors.len(), 16);
+        assert_eq!(color_bar.colors[15], [0, 255, 255]);
+        assert_eq!(color_bar.ticks[0].label, "0");
+        assert!(color_bar.ticks.windows(2).all(|x| x[0].value < x[1].value));
+    }
+
+    #[test]
+    fn test_selection_filter() {
+        use crate::highlight::CombinedHighlightFilter;
+
+        let mut pipeline = Pipeline::new();
+        pipeline
+            .load_csv(&mut std::io::Cursor::new("x,y\n0,0\n1,1\n2,4\n3,9\n4,16\n"))
+            .unwrap();
+        pipeline.set_txtype(TransformationType::Linear);
+        pipeline.set_tytype(TransformationType::Logarithmic);
+        pipeline.run();
+        assert!(pipeline.selection_filter(&[(0.0, 0.0), (1.0, 1.0)]).is_none());
+        // upper right part of the plot, reaching over the edges
+        let filter = pipeline
+            .selection_filter(&[(0.4, -0.1), (1.1, -0.1), (1.1, 0.6), (0.4, 0.6)])
+            .unwrap();
+        if let HighlightFilterVariants::Polygon(xcolumn, ycolumn, polygon) = &filter {
+            assert_eq!((xcolumn.as_str(), ycolumn.as_str()), ("x", "y"));
+            assert_eq!(polygon[0].0, pipeline.x_value_at(0.4).unwrap());
+            assert_eq!(polygon[2].1, pipeline.y_value_at(0.6).unwrap());
+        } else {
+            panic!("Polygon expected, got {:?}", filter);
+        }
+        pipeline.set_highlight_groups(vec![HighlightGroup {
+            filter: CombinedHighlightFilter {
+                filters: vec![filter],
+                ..CombinedHighlightFilter::new()
+            },
+            ..HighlightGroup::new(0)
+        }]);
+        assert_eq!(pipeline.highlighted().iter().collect::<Vec<_>>(), vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn test_row_filter() {
+        use crate::highlight::CombinedHighlightFilter;
+
+        let mut pipeline = Pipeline::new();
+        pipeline
+            .load_csv(&mut std::io::Cursor::new("x,y\n0,0\n1,1\n2,4\n3,9\n100,10000\n"))
+            .unwrap();
+        pipeline.set_txtype(TransformationType::Linear);
+        pipeline.set_tytype(TransformationType::Linear);
+        pipeline.run();
+        assert_eq!(pipeline.shown_count(), 5);
+        assert_eq!(pipeline.x_value_at(1.0).unwrap(), 100.0);
+
+        // the outlier is removed from the plot, the axis range and the statistics
+        pipeline.set_row_filter(CombinedHighlightFilter::from_query("x < 50").unwrap());
+        assert_eq!(pipeline.stage, Stage::Stage0NewData);
+        pipeline.run();
+        assert_eq!(pipeline.shown_count(), 4);
+        assert_eq!(pipeline.xyi.len(), 4);
+        assert_eq!(pipeline.x_value_at(1.0).unwrap(), 3.0);
+        let table = pipeline.statistics_table(ALL, None);
+        assert_eq!(table[2][0], "4");
+        assert_eq!(table[2][4], "1.500");
+        pipeline.set_highlight_groups(vec![HighlightGroup {
+            filter: CombinedHighlightFilter::from_query("x > 1").unwrap(),
+            ..HighlightGroup::new(0)
+        }]);
+        let statistics = pipeline.statistics(-1.0, -1.0);
+        let count = |name: &str| {
+            statistics
+                .iter()
+                .find(|row| row[0] == name && row[1] == "Count")
+                .map(|row| row[2].clone())
+        };
+        assert_eq!(count("Group 1").as_deref(), Some("2"));
+        assert_eq!(count(NON_HIGHLIGHTED).as_deref(), Some("2"));
+
+        // a filter without enabled filters shows all rows
+        pipeline.set_row_filter(CombinedHighlightFilter::new());
+        assert_eq!(pipeline.shown_count(), 5);
+    }
+
+    #[test]
+    fn test_highlight_groups() {
+        let mut pipeline = Pipeline::new();
+        pipeline.set_point_data(test_point_data_circle(100).unwrap());
+        pipeline.parameters.mesh_width = 40;
+        pipeline.parameters.mesh_height = 40;
+        let mut groups = vec![HighlightGroup::new(0), HighlightGroup::new(1)];
+        groups[0]
+            .filter
+            .filters
+            .push(HighlightFilterVariants::LessThan("a".to_owned(), 3.0));
+        groups[1]
+            .filter
+            .filters
+            .push(HighlightFilterVariants::LessThan("a".to_owned(), 4.0));
+        pipeline.set_highlight_groups(groups.clone());
+        pipeline.run();
+        // overlapping points are drawn in the first group
+        let count = |layer| pipeline.xyi.iter().filter(|p| p.4 == layer).count();
+        assert_eq!((count(0), count(1), count(2)), (36, 48, 16));
+        assert_eq!(pipeline.mesh.highlight_layers(), 2);
+        assert_eq!(pipeline.highlighted().iter().count(), 64);
+        let statistics = pipeline.statistics(-1.0, -1.0);
+        assert!(statistics.iter().any(|row| row[0] == "Group 2"));
+        assert!(statistics.iter().any(|row| row[0] == NON_HIGHLIGHTED));
+
+        // a clause not changing the highlighted points keeps the mesh and the statistics
+        let mut same = groups.clone();
+        same[0]
+            .filter
+            .filters
+            .push(HighlightFilterVariants::LessThan("a".to_owned(), 10.0));
+        assert!(!pipeline.set_highlight_groups(same.clone()));
+        assert_eq!(pipeline.stage, Stage::Stage4Image);
+        same[0].name = "Small".to_owned();
+        assert!(pipeline.set_highlight_groups(same));
+
+        // changing only the color does not rebuild the mesh
+        groups[1].color = Rgb([0, 0, 255]);
+        assert!(pipeline.set_highlight_groups(groups));
+        assert_eq!(pipeline.stage, Stage::Stage3ProcessedMesh);
+        pipeline.set_highlight_composition(HighlightComposition::Dominance);
+        pipeline.set_highlight_type(HighlightType::HighlighedOnly);
+        pipeline.run();
+        let pixels = pipeline.mesh.rgba8.chunks(4);
+        // no pixel mixes the colors of the two groups
+        assert!(pixels.clone().any(|pixel| pixel[0] > 0));
+        assert!(pixels.clone().any(|pixel| pixel[2] > 0));
+        assert!(pixels.clone().all(|pixel| pixel[0] == 0 || pixel[2] == 0));
+    }
+
+    #[test]
+    fn test_color_by_categories() {
+        let mut pipeline = Pipeline::new();
+        pipeline
+            .load_csv(&mut std::io::Cursor::new("x,y,c\n1,2,a\n2,3,b\n3,1,a\n4,4,\n"))
+            .unwrap();
+        pipeline.set_color_column("c".to_owned());
+        pipeline.run();
+        let names = pipeline
+            .categories
+            .iter()
+            .map(|x| (x.name.as_str(), x.count))
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec![("a", 2), ("b", 1)]);
+        assert_eq!(pipeline.mesh.highlight_layers(), 2);
+        let layers = pipeline.xyi.iter().map(|p| p.4).collect::<Vec<_>>();
+        assert_eq!(layers, vec![1, 2, 1, 0]);
+
+        pipeline.set_category_visible("a", false);
+        pipeline.run();
+        assert_eq!(pipeline.xyi.len(), 2);
+        assert!(!pipeline.categories[0].visible);
+
+        // too many categories
+        pipeline.set_color_column("x".to_owned());
+        pipeline.run();
+        assert!(pipeline.color_by_categories());
+        let csv = (0..30).fold("x,y\n".to_owned(), |csv, i| format!("{}{},{}\n", csv, i, i));
+        pipeline.load_csv(&mut std::io::Cursor::new(csv)).unwrap();
+        pipeline.run();
+        assert!(!pipeline.color_by_categories());
+        assert_eq!(pipeline.mesh.highlight_layers(), 1);
+    }
+
+    #[test]
+    fn test_value_mode() {
+        let mut pipeline = Pipeline::new();
+        pipeline
+            .load_csv(&mut std::io::Cursor::new(
+                "x,y,v\n0,2,1\n0,2,3\n0,2,8\n1,1,-2\n1,1,\n2,0,0\n",
+            ))
+            .unwrap();
+        pipeline.parameters.mesh_width = 10;
+        pipeline.parameters.mesh_height = 10;
+        pipeline.set_value_column("v".to_owned());
+        pipeline.run();
+        assert_eq!(pipeline.xyi.len(), 5);
+        // value of the cell of a point
+        let cell = |pipeline: &Pipeline, point: usize| {
+            let (x, y, _, _, _) = pipeline.xyi[point];
+            pipeline.mesh.processed_value_mesh[pipeline.mesh.cell(x, y).unwrap()]
+        };
+        assert_eq!(cell(&pipeline, 0), 4.0);
+        assert_eq!(cell(&pipeline, 3), -2.0);
+        assert_eq!(pipeline.mesh.value_range, (-4.0, 4.0));
+        pipeline.set_value_statistic(ValueStatistic::Median);
+        pipeline.run();
+        assert_eq!(cell(&pipeline, 0), 3.0);
+        pipeline.set_value_statistic(ValueStatistic::Max);
+        pipeline.run();
+        assert_eq!(cell(&pipeline, 0), 8.0);
+        assert_eq!(pipeline.mesh.processed_value_mesh.iter().filter(|x| !x.is_nan()).count(), 2);
+        let color_bar = pipeline.color_bar(8, 4);
+        assert_eq!(color_bar.colors[0], Colormap::coolwarm().color(0.0));
+        assert!(color_bar.ticks.iter().any(|tick| tick.label == "0"));
+    }
+
+    #[test]
+    fn test_missing_values() {
+        let mut pipeline = Pipeline::new();
+        pipeline
+            .load_csv(&mut std::io::Cursor::new("x,y\n1,2\nNA,3\n4,\n5,6\n"))
+            .unwrap();
+        pipeline.run();
+        assert_eq!(pipeline.xyi.len(), 2);
+        let statistics = pipeline.statistics_table(ALL, None);
+        assert_eq!(statistics[1][1], "Missing");
+        assert_eq!(statistics[2][1], "1");
+        assert_eq!(statistics[3][0], "3");
     }
 }
@@ -1,10 +1,144 @@
 #![allow(dead_code)]
 
-use anyhow::Result;
-use csv;
+use crate::csv_loader::{CsvLoader, CsvOptions};
+use crate::datetime::{format_timestamp, parse_timestamp};
+use anyhow::*;
 use std::collections::HashMap;
 use std::collections::BTreeSet;
 use std::f64::consts::PI;
+use std::path::Path;
+
+/// Supported input file formats.
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub enum FileFormat {
+    Csv,
+    ArrowIpc,
+    Parquet,
+}
+
+impl FileFormat {
+    /// Guess the format from the file extension, CSV is the default.
+    pub fn from_path(path: &str) -> FileFormat {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|x| x.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        match extension.as_str() {
+            "arrow" | "feather" | "ipc" => FileFormat::ArrowIpc,
+            "parquet" | "pq" => FileFormat::Parquet,
+            _ => FileFormat::Csv,
+        }
+    }
+}
+
+/// Text columns with more distinct values are not dictionary-encoded.
+pub const MAX_CATEGORIES: usize = 10000;
+
+/// Type of a column.
+/// Integer, float, boolean and datetime columns are stored in `PointData::data`
+/// (booleans as 0/1, datetimes as seconds since the Unix epoch, missing values as NaN),
+/// categorical columns in `PointData::categorical` and other text columns in `PointData::aux`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Copy)]
+pub enum ColumnType {
+    Integer,
+    Float,
+    Boolean,
+    DateTime,
+    Categorical,
+    Text,
+}
+
+impl ColumnType {
+    pub fn text(&self) -> &str {
+        match self {
+            ColumnType::Integer => "Integer",
+            ColumnType::Float => "Float",
+            ColumnType::Boolean => "Boolean",
+            ColumnType::DateTime => "DateTime",
+            ColumnType::Categorical => "Categorical",
+            ColumnType::Text => "Text",
+        }
+    }
+    /// Column stored in `PointData::data`.
+    pub fn is_numeric(&self) -> bool {
+        !matches!(self, ColumnType::Categorical | ColumnType::Text)
+    }
+}
+
+pub fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "true" | "True" | "TRUE" => Some(true),
+        "false" | "False" | "FALSE" => Some(false),
+        _ => None,
+    }
+}
+
+/// Dictionary-encoded text column: every row holds a code (index) of a category.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Categorical {
+    pub codes: Vec<u32>,
+    pub categories: Vec<String>,
+    index: HashMap<String, u32>,
+}
+
+impl Categorical {
+    pub fn new() -> Categorical {
+        Categorical {
+            codes: Vec::new(),
+            categories: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+    pub fn from_values<'a>(values: impl IntoIterator<Item = &'a str>) -> Categorical {
+        let mut categorical = Categorical::new();
+        for value in values {
+            categorical.push(value);
+        }
+        categorical
+    }
+    pub fn len(&self) -> usize {
+        self.codes.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.codes.is_empty()
+    }
+    /// Code of a category, the category is created if it does not exist.
+    pub fn category_code(&mut self, value: &str) -> u32 {
+        if let Some(&code) = self.index.get(value) {
+            code
+        } else {
+            let code = self.categories.len() as u32;
+            self.categories.push(value.to_owned());
+            self.index.insert(value.to_owned(), code);
+            code
+        }
+    }
+    pub fn push(&mut self, value: &str) {
+        let code = self.category_code(value);
+        self.codes.push(code);
+    }
+    pub fn set(&mut self, index: usize, value: &str) {
+        let code = self.category_code(value);
+        self.codes[index] = code;
+    }
+    pub fn resize(&mut self, n: usize) {
+        let code = self.category_code("");
+        self.codes.resize(n, code);
+    }
+    pub fn get(&self, index: usize) -> &str {
+        &self.categories[self.codes[index] as usize]
+    }
+    /// Code of an existing category.
+    pub fn code(&self, value: &str) -> Option<u32> {
+        self.index.get(value).copied()
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &str> + '_ {
+        self.codes
+            .iter()
+            .map(move |&code| self.categories[code as usize].as_str())
+    }
+}
 
 pub struct PointData {
     pub length: usize,
@@ -12,6 +146,18 @@ pub struct PointData {
     pub all_headers: Vec<String>,
     pub data: HashMap<String, Vec<f64>>,
     pub aux: HashMap<String, Vec<String>>,
+    pub categorical: HashMap<String, Categorical>,
+    /// Types of the numeric columns in `data`, float is assumed if missing.
+    pub column_types: HashMap<String, ColumnType>,
+    /// Changed by the pipeline when the data are replaced or a column is added,
+    /// caches of the column values are keyed by it.
+    pub generation: usize,
+}
+
+impl Default for PointData {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl PointData {
@@ -22,11 +168,99 @@ impl PointData {
             all_headers: Vec::new(),
             data: HashMap::new(),
             aux: HashMap::new(),
+            categorical: HashMap::new(),
+            column_types: HashMap::new(),
+            generation: 0,
         }
     }
     pub fn len(&self) -> usize {
         self.length
     }
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+    pub fn contains_column(&self, column: &str) -> bool {
+        self.data.contains_key(column)
+            || self.categorical.contains_key(column)
+            || self.aux.contains_key(column)
+    }
+
+    pub fn column_type(&self, column: &str) -> Option<ColumnType> {
+        if self.data.contains_key(column) {
+            Some(
+                self.column_types
+                    .get(column)
+                    .copied()
+                    .unwrap_or(ColumnType::Float),
+            )
+        } else if self.categorical.contains_key(column) {
+            Some(ColumnType::Categorical)
+        } else if self.aux.contains_key(column) {
+            Some(ColumnType::Text)
+        } else {
+            None
+        }
+    }
+
+    /// Format a value of a numeric column according to the column type.
+    pub fn format_value(&self, column: &str, value: f64) -> String {
+        if value.is_nan() {
+            return String::new();
+        }
+        match self.column_type(column) {
+            Some(ColumnType::Boolean) => format!("{}", value != 0.0),
+            Some(ColumnType::DateTime) => format_timestamp(value),
+            _ => format!("{}", value),
+        }
+    }
+
+    /// Parse a value of a numeric column (inverse of `format_value`).
+    pub fn parse_value(&self, column: &str, value: &str) -> Option<f64> {
+        match self.column_type(column) {
+            Some(ColumnType::Boolean) => parse_bool(value).map(|x| if x { 1.0 } else { 0.0 }),
+            Some(ColumnType::DateTime) => parse_timestamp(value).or_else(|| value.parse().ok()),
+            _ => value.parse::<f64>().ok(),
+        }
+    }
+
+    /// Value of a text (categorical or aux) column.
+    pub fn text(&self, column: &str, index: usize) -> Option<&str> {
+        if let Some(categorical) = self.categorical.get(column) {
+            Some(categorical.get(index))
+        } else {
+            self.aux.get(column).map(|v| v[index].as_str())
+        }
+    }
+
+    /// Values of a text (categorical or aux) column parsed as numbers.
+    pub fn text_as_numbers(&self, column: &str) -> Option<Vec<Option<f64>>> {
+        if let Some(categorical) = self.categorical.get(column) {
+            let parsed = categorical
+                .categories
+                .iter()
+                .map(|x| x.parse::<f64>().ok())
+                .collect::<Vec<_>>();
+            Some(
+                categorical
+                    .codes
+                    .iter()
+                    .map(|&code| parsed[code as usize])
+                    .collect(),
+            )
+        } else {
+            self.aux
+                .get(column)
+                .map(|v| v.iter().map(|x| x.parse::<f64>().ok()).collect())
+        }
+    }
+
+    /// Number of missing values (stored as NaN) in a numeric column.
+    pub fn missing_count(&self, column: &str) -> usize {
+        self.data
+            .get(column)
+            .map(|v| v.iter().filter(|x| x.is_nan()).count())
+            .unwrap_or(0)
+    }
     pub fn reset_headers(&mut self) {
         self.headers.clear();
         for column in self.all_headers.iter() {
@@ -42,29 +276,95 @@ impl PointData {
         }
     }
 
-    pub fn unique_values(&self, column:&str)->Vec<String> {
-        let mut set = BTreeSet::new();
-        if self.aux.contains_key(column){
-            let data = &self.aux[column];
-            for key in data.iter(){
-                set.insert(key.to_string());
-                if set.len()>=100{
-                    break;
-                }
+    /// All distinct values of a column (sorted, numbers in numeric order, without missing values).
+    pub fn unique_values(&self, column: &str) -> Vec<String> {
+        if let Some(data) = self.data.get(column) {
+            let mut values = data
+                .iter()
+                .filter(|x| !x.is_nan())
+                .copied()
+                .collect::<Vec<_>>();
+            values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            values.dedup();
+            values
+                .into_iter()
+                .map(|x| self.format_value(column, x))
+                .collect()
+        } else if let Some(categorical) = self.categorical.get(column) {
+            let mut set = BTreeSet::new();
+            for key in categorical.categories.iter() {
+                set.insert(key.as_str());
             }
+            set.iter().map(|x| x.to_string()).collect()
+        } else if let Some(data) = self.aux.get(column) {
+            let mut set = BTreeSet::new();
+            for key in data.iter() {
+                set.insert(key.as_str());
+            }
+            set.iter().map(|x| x.to_string()).collect()
+        } else {
+            Vec::new()
         }
-        if self.data.contains_key(column){
-            let data = &self.data[column];
-            for key in data.iter(){
-                set.insert(format!("{}",key));
-                if set.len()>=100{
-                    break;
-                }
+    }
+
+    /// Categories of a column (sorted as `unique_values`) and the index of the category of every row,
+    /// None for missing or empty values. None if the column has more than `max_categories` values.
+    pub fn category_codes(
+        &self,
+        column: &str,
+        max_categories: usize,
+    ) -> Option<(Vec<String>, Vec<Option<usize>>)> {
+        if let Some(data) = self.data.get(column) {
+            let mut values = data
+                .iter()
+                .filter(|x| !x.is_nan())
+                .copied()
+                .collect::<Vec<_>>();
+            values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            values.dedup();
+            if values.len() > max_categories {
+                return None;
             }
+            let codes = data
+                .iter()
+                .map(|x| {
+                    if x.is_nan() {
+                        None
+                    } else {
+                        values
+                            .binary_search_by(|value| value.partial_cmp(x).unwrap())
+                            .ok()
+                    }
+                })
+                .collect();
+            let names = values
+                .iter()
+                .map(|x| self.format_value(column, *x))
+                .collect();
+            Some((names, codes))
+        } else if self.contains_column(column) {
+            let mut names = self.unique_values(column);
+            names.retain(|x| !x.is_empty());
+            if names.len() > max_categories {
+                return None;
+            }
+            let index = names
+                .iter()
+                .enumerate()
+                .map(|(i, name)| (name.as_str(), i))
+                .collect::<HashMap<_, _>>();
+            let codes = (0..self.length)
+                .map(|i| {
+                    self.text(column, i)
+                        .and_then(|value| index.get(value).copied())
+                })
+                .collect();
+            Some((names, codes))
+        } else {
+            None
         }
-        set.iter().map(|x| x.to_owned()).collect()
     }
-    
+
     pub fn with_data_column(&mut self, column: &str) -> &mut Self {
         self.headers.push(column.to_owned());
         self.all_headers.push(column.to_owned());
@@ -85,6 +385,9 @@ impl PointData {
         for (_, value) in self.aux.iter_mut() {
             value.resize(n, "".to_string());
         }
+        for (_, value) in self.categorical.iter_mut() {
+            value.resize(n);
+        }
         self
     }
     pub fn set_data(&mut self, column: &str, index: usize, value: f64) -> &mut Self {
@@ -111,6 +414,8 @@ impl PointData {
         }
         if let Some(v) = self.aux.get_mut(column) {
             v[index] = value;
+        } else if let Some(v) = self.categorical.get_mut(column) {
+            v.set(index, &value);
         } else {
             let mut v: Vec<String> = Vec::with_capacity(self.length);
             v.resize(self.length, "".to_string());
@@ -127,9 +432,9 @@ impl PointData {
         if index >= self.length {
             "".to_string()
         } else if self.data.contains_key(column) {
-            format!("{}", self.data[column][index])
-        } else if self.aux.contains_key(column) {
-            self.aux[column][index].to_owned()
+            self.format_value(column, self.data[column][index])
+        } else if let Some(text) = self.text(column, index) {
+            text.to_owned()
         } else {
             "".to_string()
         }
@@ -139,10 +444,10 @@ impl PointData {
         if index < self.length {
             for column in self.headers.iter() {
                 if let Some(column_data) = self.data.get(column) {
-                    v.push(format!("{}", column_data[index]));
+                    v.push(self.format_value(column, column_data[index]));
                 } else {
-                    if let Some(aux_data) = self.aux.get(column) {
-                        v.push(format!("\"{}\"", aux_data[index]));
+                    if let Some(text) = self.text(column, index) {
+                        v.push(format!("\"{}\"", text));
                     }
                 }
             }
@@ -161,33 +466,100 @@ impl PointData {
         )
     }
 
-    pub fn from_csv<R: std::io::Read>(reader: &mut R) -> Result<Self> {
-        let mut csv_reader = csv::ReaderBuilder::new().from_reader(reader);
+    pub fn from_csv<R: std::io::Read + std::io::Seek>(reader: &mut R) -> Result<Self> {
+        Self::from_csv_with_options(reader, &CsvOptions::new())
+    }
 
-        let mut point_data = Self::new();
-        let mut records = Vec::new();
-        for record in csv_reader.records() {
-            records.push(record?);
+    pub fn from_csv_with_options<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        options: &CsvOptions,
+    ) -> Result<Self> {
+        let mut loader = CsvLoader::with_options(reader, options)?;
+        loader.read_all()?;
+        Ok(loader.finish())
+    }
+
+    /// Read a file without loading it into memory first; the format is determined
+    /// by the file extension. The `options` are used for CSV files.
+    /// If `columns` are specified, only these columns are kept
+    /// (Arrow IPC and Parquet files do not read the other columns at all).
+    pub fn from_file(path: &str, options: &CsvOptions, columns: Option<&[&str]>) -> Result<Self> {
+        let file = std::fs::File::open(path)?;
+        match FileFormat::from_path(path) {
+            FileFormat::Csv => {
+                let mut point_data =
+                    Self::from_csv_with_options(&mut std::io::BufReader::new(file), options)?;
+                if let Some(columns) = columns {
+                    point_data.select_columns(columns)?;
+                }
+                Ok(point_data)
+            }
+            #[cfg(feature = "arrow")]
+            FileFormat::ArrowIpc => Self::from_arrow_ipc(std::io::BufReader::new(file), columns),
+            #[cfg(feature = "parquet")]
+            FileFormat::Parquet => Self::from_parquet(file, columns),
+            #[allow(unreachable_patterns)]
+            format => Err(Self::unsupported_format(format)),
         }
+    }
 
-        for (i, column) in csv_reader.headers()?.iter().enumerate() {
-            let data = records
-                .iter()
-                .flat_map(|r| r[i].parse::<f64>())
-                .collect::<Vec<f64>>();
-            point_data.headers.push(column.to_owned());
-            point_data.all_headers.push(column.to_owned());
-            if data.len() == records.len() {
-                point_data.data.insert(column.to_owned(), data);
+    /// Parse the content of a file; the format is determined by the file extension.
+    /// The `options` are used for CSV files.
+    /// If `columns` are specified, only these columns are kept.
+    /// Used on WebAssembly, where the files are loaded into memory,
+    /// native builds read the files with `from_file`.
+    pub fn from_file_content(
+        path: &str,
+        content: Vec<u8>,
+        options: &CsvOptions,
+        columns: Option<&[&str]>,
+    ) -> Result<Self> {
+        match FileFormat::from_path(path) {
+            FileFormat::Csv => {
+                let mut point_data =
+                    Self::from_csv_with_options(&mut std::io::Cursor::new(content), options)?;
+                if let Some(columns) = columns {
+                    point_data.select_columns(columns)?;
+                }
+                Ok(point_data)
+            }
+            #[cfg(feature = "arrow")]
+            FileFormat::ArrowIpc => Self::from_arrow_ipc(std::io::Cursor::new(content), columns),
+            #[cfg(feature = "parquet")]
+            FileFormat::Parquet => Self::from_parquet(bytes::Bytes::from(content), columns),
+            #[allow(unreachable_patterns)]
+            format => Err(Self::unsupported_format(format)),
+        }
+    }
+
+    fn unsupported_format(format: FileFormat) -> Error {
+        anyhow!(
+            "Support for {:?} files is not enabled, rebuild with the '{}' feature",
+            format,
+            if format == FileFormat::Parquet {
+                "parquet"
             } else {
-                point_data.aux.insert(
-                    column.to_owned(),
-                    records.iter().map(|r| r[i].to_owned()).collect::<Vec<_>>(),
-                );
+                "arrow"
+            }
+        )
+    }
+
+    /// Keep only the specified columns (in the specified order).
+    pub fn select_columns(&mut self, columns: &[&str]) -> Result<()> {
+        for &column in columns.iter() {
+            if !self.contains_column(column) {
+                bail!("Column '{}' not found", column);
             }
-            point_data.length = records.len();
         }
-        Ok(point_data)
+        self.data.retain(|column, _| columns.contains(&column.as_str()));
+        self.aux.retain(|column, _| columns.contains(&column.as_str()));
+        self.categorical
+            .retain(|column, _| columns.contains(&column.as_str()));
+        self.column_types
+            .retain(|column, _| columns.contains(&column.as_str()));
+        self.all_headers = columns.iter().map(|x| x.to_string()).collect();
+        self.reset_headers();
+        Ok(())
     }
 
     pub fn points<'a>(
@@ -245,6 +617,9 @@ pub fn test_point_data() -> Result<PointData> {
             .collect(),
         data: HashMap::new(),
         aux: HashMap::new(),
+        categorical: HashMap::new(),
+        column_types: HashMap::new(),
+        generation: 0,
     };
     point_data
         .data
@@ -283,3 +658,47 @@ pub fn test_point_data_circle(n: usize) -> Result<PointData> {
     }
     Ok(point_data)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_typed_columns() {
+        let mut point_data = PointData::new();
+        point_data
+            .with_data_column("n")
+            .with_data_column("flag")
+            .allocate(200);
+        point_data.column_types.insert("flag".into(), ColumnType::Boolean);
+        point_data.categorical.insert(
+            "c".into(),
+            Categorical::from_values((0..200).map(|i| if i % 2 == 0 { "even" } else { "odd" })),
+        );
+        for i in 0..200 {
+            point_data
+                .set_data("n", i, (199 - i) as f64)
+                .set_data("flag", i, (i % 2) as f64);
+        }
+        point_data.set_aux("c", 0, "zero".to_owned());
+
+        // exact, not truncated and in numeric order
+        let values = point_data.unique_values("n");
+        assert_eq!(values.len(), 200);
+        assert_eq!(values[..3], ["0", "1", "2"]);
+        assert_eq!(point_data.unique_values("flag"), vec!["false", "true"]);
+        assert_eq!(point_data.parse_value("flag", "true"), Some(1.0));
+        assert_eq!(point_data.unique_values("c"), vec!["even", "odd", "zero"]);
+        assert_eq!(point_data.get("c", 0), "zero");
+        assert_eq!(point_data.column_type("c"), Some(ColumnType::Categorical));
+        assert_eq!(point_data.text_as_numbers("c").unwrap()[1], None);
+
+        let (names, codes) = point_data.category_codes("c", 10).unwrap();
+        assert_eq!(names, vec!["even", "odd", "zero"]);
+        assert_eq!(codes[..3], [Some(2), Some(1), Some(0)]);
+        let (names, codes) = point_data.category_codes("flag", 10).unwrap();
+        assert_eq!(names, vec!["false", "true"]);
+        assert_eq!(codes[..2], [Some(0), Some(1)]);
+        assert!(point_data.category_codes("n", 10).is_none());
+    }
+}
@@ -0,0 +1,33 @@
+//! Headless rendering of the density plot into a PNG image.
+//! Unlike the viewer, it does not depend on macroquad or egui.
+use anyhow::{anyhow, Result};
+use pointcloud_viewer::cli::*;
+
+fn render(arguments: &mut Arguments) -> Result<()> {
+    let session = arguments.load_session()?;
+    let output = arguments
+        .output
+        .clone()
+        .ok_or_else(|| anyhow!("Missing the output file, use -o or --output"))?;
+    arguments.check_input()?;
+    arguments.render(session.as_ref(), &output)
+}
+
+fn main() {
+    let usage = USAGE.replacen("pointcloud-viewer", "pointcloud-render", 1);
+    let mut arguments = match Arguments::from_env() {
+        Ok(arguments) => arguments,
+        Err(error) => {
+            eprintln!("Error: {}\n\n{}", error, usage);
+            std::process::exit(2);
+        }
+    };
+    if arguments.help {
+        println!("{}", usage);
+        return;
+    }
+    if let Err(error) = render(&mut arguments) {
+        eprintln!("Error: {:#}", error);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,295 @@
+//! Viewer sessions: everything needed to reproduce a view - the input file and its CSV options,
+//! the derived columns, the plotted columns, transformations, zoom and offset, mesh parameters,
+//! the row filter, the highlight groups and the statistics settings.
+//! Sessions are saved as YAML, or as JSON when the file name ends with `.json`.
+
+use crate::csv_loader::CsvOptions;
+use crate::evaluate::DerivedColumn;
+use crate::highlight::{CombinedHighlightFilter, HighlightGroup};
+use crate::mesh::Parameters;
+use crate::pipeline::{Pipeline, TransformationType};
+use anyhow::*;
+
+/// Settings of the statistics window.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct StatisticsSettings {
+    /// The statistics window is open.
+    pub enabled: bool,
+    pub all: bool,
+    /// Show the columns of the highlight groups.
+    pub highlighted: bool,
+    pub non_highlighted: bool,
+    /// Names of the displayed measures.
+    pub measures: Vec<String>,
+}
+
+impl Default for StatisticsSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StatisticsSettings {
+    pub fn new() -> StatisticsSettings {
+        StatisticsSettings {
+            enabled: false,
+            all: true,
+            highlighted: true,
+            non_highlighted: true,
+            measures: vec!["Mean".to_owned()],
+        }
+    }
+}
+
+/// Serializable state of the viewer.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Session {
+    /// Input data file.
+    pub input: String,
+    pub csv_options: CsvOptions,
+    /// Columns to load, all if not specified.
+    pub columns: Option<Vec<String>>,
+    /// Columns computed from expressions after loading the data.
+    pub derived_columns: Vec<DerivedColumn>,
+    pub parameters: Parameters,
+    pub txtype: TransformationType,
+    pub tytype: TransformationType,
+    pub zoom: f64,
+    pub aspect_ratio: f64,
+    pub ox: f64,
+    pub oy: f64,
+    /// Filter of the shown rows.
+    pub row_filter: CombinedHighlightFilter,
+    pub highlight_groups: Vec<HighlightGroup>,
+    pub statistics: StatisticsSettings,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Session {
+    pub fn new() -> Session {
+        let pipeline = Pipeline::new();
+        Session {
+            input: String::new(),
+            csv_options: CsvOptions::new(),
+            columns: None,
+            derived_columns: Vec::new(),
+            parameters: pipeline.parameters.clone(),
+            txtype: pipeline.txtype,
+            tytype: pipeline.tytype,
+            zoom: pipeline.zoom,
+            aspect_ratio: pipeline.aspect_ratio,
+            ox: pipeline.ox,
+            oy: pipeline.oy,
+            row_filter: pipeline.row_filter.clone(),
+            highlight_groups: pipeline.highlight_groups.clone(),
+            statistics: StatisticsSettings::new(),
+        }
+    }
+
+    /// Session with the view, row filter and highlight groups of a pipeline;
+    /// the input and statistics settings are defaults.
+    pub fn from_pipeline(pipeline: &Pipeline) -> Session {
+        Session {
+            derived_columns: pipeline.derived_columns.clone(),
+            parameters: pipeline.parameters.clone(),
+            txtype: pipeline.txtype,
+            tytype: pipeline.tytype,
+            zoom: pipeline.zoom,
+            aspect_ratio: pipeline.aspect_ratio,
+            ox: pipeline.ox,
+            oy: pipeline.oy,
+            row_filter: pipeline.row_filter.clone(),
+            highlight_groups: pipeline.highlight_groups.clone(),
+            ..Session::new()
+        }
+    }
+
+    /// Columns to load as string slices.
+    pub fn columns(&self) -> Option<Vec<&str>> {
+        self.columns
+            .as_ref()
+            .map(|columns| columns.iter().map(|x| x.as_str()).collect())
+    }
+
+    /// Restore the derived columns, the view, the row filter and the highlight groups
+    /// in a pipeline with loaded data.
+    /// The mesh size follows the window and is not restored.
+    pub fn apply(&self, pipeline: &mut Pipeline) -> Result<()> {
+        for column in self.derived_columns.iter() {
+            // already added when the session is applied again to the same data
+            if !pipeline.derived_columns.contains(column) {
+                pipeline.add_derived_column(column.clone())?;
+            }
+        }
+        let parameters = &self.parameters;
+        for column in [
+            &parameters.xcolumn,
+            &parameters.ycolumn,
+            &parameters.weight_column,
+            &parameters.color_column,
+            &parameters.value_column,
+        ]
+        .iter()
+        {
+            if !column.is_empty() && !pipeline.point_data.all_headers.contains(column) {
+                bail!(
+                    "Column '{}' of the session not found; available columns: {}",
+                    column,
+                    pipeline.point_data.all_headers.join(", ")
+                );
+            }
+        }
+        pipeline.set_xcolumn(parameters.xcolumn.clone());
+        pipeline.set_ycolumn(parameters.ycolumn.clone());
+        pipeline.set_weight_column(parameters.weight_column.clone());
+        pipeline.set_color_column(parameters.color_column.clone());
+        pipeline.set_hidden_categories(parameters.hidden_categories.clone());
+        pipeline.set_value_column(parameters.value_column.clone());
+        pipeline.set_value_statistic(parameters.value_statistic);
+        pipeline.set_value_colormap(parameters.value_colormap.clone());
+        pipeline.set_txtype(self.txtype);
+        pipeline.set_tytype(self.tytype);
+        // after the columns, changing a column resets the zoom
+        pipeline.set_zoom(self.zoom);
+        pipeline.set_aspect_ratio(self.aspect_ratio);
+        pipeline.set_offset_x(self.ox);
+        pipeline.set_offset_y(self.oy);
+        pipeline.set_highlight_type(parameters.highlight_type);
+        pipeline.set_gaussian_points(parameters.gaussian_points);
+        pipeline.set_point_sigma(parameters.point_sigma);
+        pipeline.set_blur_mesh(parameters.blur_mesh);
+        pipeline.set_density_multiplier(parameters.density_multiplier);
+        pipeline.set_contrast(parameters.contrast);
+        pipeline.set_colormap(parameters.colormap.clone());
+        pipeline.set_highlight_composition(parameters.highlight_composition);
+        pipeline.set_row_filter(self.row_filter.clone());
+        pipeline.set_highlight_groups(self.highlight_groups.clone());
+        Ok(())
+    }
+
+    fn is_json(path: &str) -> bool {
+        path.to_lowercase().ends_with(".json")
+    }
+
+    pub fn to_yaml(&self) -> Result<String> {
+        serde_yaml::to_string(self).context("Failed to serialize the session")
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).context("Failed to serialize the session")
+    }
+
+    pub fn from_yaml(text: &str) -> Result<Session> {
+        serde_yaml::from_str(text).context("Invalid session")
+    }
+
+    pub fn from_json(text: &str) -> Result<Session> {
+        serde_json::from_str(text).context("Invalid session")
+    }
+
+    /// Save the session as JSON if the path ends with `.json`, as YAML otherwise.
+    pub fn save(&self, path: &str) -> Result<()> {
+        let text = if Self::is_json(path) {
+            self.to_json()?
+        } else {
+            self.to_yaml()?
+        };
+        std::fs::write(path, text).with_context(|| format!("Failed to write '{}'", path))
+    }
+
+    /// Load a session saved by `save`.
+    pub fn load(path: &str) -> Result<Session> {
+        let text =
+            std::fs::read_to_string(path).with_context(|| format!("Failed to read '{}'", path))?;
+        if Self::is_json(path) {
+            Self::from_json(&text)
+        } else {
+            Self::from_yaml(&text)
+        }
+        .with_context(|| format!("Failed to load the session '{}'", path))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::colormap::Rgb;
+    use crate::highlight::HighlightFilterVariants;
+    use crate::pointdata::test_point_data_circle;
+
+    #[test]
+    fn test_session() {
+        let mut pipeline = Pipeline::new();
+        pipeline.set_point_data(test_point_data_circle(100).unwrap());
+        pipeline.set_xcolumn("y".to_owned());
+        pipeline.set_txtype(TransformationType::Quantile);
+        pipeline.set_zoom(2.0);
+        pipeline.set_offset_x(0.25);
+        pipeline.set_contrast(3.0);
+        let mut session = Session::from_pipeline(&pipeline);
+        session.input = "circle.csv".to_owned();
+        session.highlight_groups[0]
+            .filter
+            .filters
+            .push(HighlightFilterVariants::LessThan("a".to_owned(), 3.0));
+        let mut group = HighlightGroup::new(1);
+        group.color = Rgb([0, 0, 255]);
+        group
+            .filter
+            .filters
+            .push(HighlightFilterVariants::GreaterThan("a".to_owned(), 5.5));
+        session.highlight_groups.push(group);
+        let mut group = HighlightGroup::new(2);
+        group.filter.filters.push(HighlightFilterVariants::Polygon(
+            "x".to_owned(),
+            "y".to_owned(),
+            vec![(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0)],
+        ));
+        session.highlight_groups.push(group);
+        session.statistics.measures.push("Count".to_owned());
+        session.derived_columns.push(DerivedColumn::new("r", "sqrt(x^2 + y^2)"));
+        session.parameters.weight_column = "r".to_owned();
+        session.row_filter = CombinedHighlightFilter::from_query("r > 0.5 and a < 3").unwrap();
+
+        let yaml = session.to_yaml().unwrap();
+        assert_eq!(Session::from_yaml(&yaml).unwrap(), session);
+        let json = session.to_json().unwrap();
+        assert_eq!(Session::from_json(&json).unwrap(), session);
+
+        let mut restored = Pipeline::new();
+        restored.set_point_data(test_point_data_circle(100).unwrap());
+        session.apply(&mut restored).unwrap();
+        assert_eq!(restored.xcolumn(), "y");
+        assert_eq!(restored.tx_type(), TransformationType::Quantile);
+        assert_eq!(restored.get_zoom(), 2.0);
+        assert_eq!(restored.offset_x(), 0.25);
+        assert_eq!(restored.contrast(), 3.0);
+        assert_eq!(restored.weight_column(), "r");
+        assert!((restored.point_data.data["r"][7] - 1.0).abs() < 1e-12);
+        assert_eq!(restored.shown_count(), 48);
+        // applying the session again keeps the derived column
+        session.apply(&mut restored).unwrap();
+        assert_eq!(restored.derived_columns.len(), 1);
+        assert_eq!(restored.highlights.len(), 3);
+        assert_eq!(restored.highlights[0].iter().count(), 48);
+        assert_eq!(restored.highlights[1].iter().count(), 12);
+        // the first quarter of the circle, including the points on the axes
+        assert_eq!(restored.highlights[2].iter().count(), 26);
+        assert_eq!(restored.highlight_groups[1].color, Rgb([0, 0, 255]));
+
+        session.parameters.ycolumn = "z".to_owned();
+        assert!(session.apply(&mut restored).is_err());
+
+        // missing fields take the default values
+        let session = Session::from_yaml("input: data.csv\nzoom: 3.0\n").unwrap();
+        assert_eq!(session.zoom, 3.0);
+        assert_eq!(session.statistics, StatisticsSettings::new());
+    }
+}
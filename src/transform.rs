@@ -38,10 +38,11 @@ impl NewTransform for Normalize{
 
 impl Transform for Normalize{
     fn calibrate(&mut self, values:&[f64]){
-        if values.len()>0{
-            self.minimum = values[0];
-            let mut maximum = values[0];
-            for &x in values{
+        let mut valid = values.iter().filter(|x| !x.is_nan());
+        if let Some(&first) = valid.next(){
+            self.minimum = first;
+            let mut maximum = first;
+            for &x in valid{
                 if x<self.minimum{
                     self.minimum =x;
                 }
@@ -192,6 +193,9 @@ impl Transform for Quantile{
         let mut index=0;
         self.values.clear();
         self.quantiles.clear();
+        if n==0{
+            return;
+        }
         while index<buffer.len()-1{
             self.values.push(buffer[index]);
             self.quantiles.push((index as f64)/((n-1) as f64));
@@ -201,6 +205,9 @@ impl Transform for Quantile{
         self.quantiles.push(1.0);
     }
     fn transform(&self, value:f64)->Option<f64>{
+        if self.values.is_empty() || value.is_nan(){
+            return None;
+        }
         Some(Quantile::general_transform(value, &self.values, &self.quantiles))
         /*
         if self.values.len()>1 && self.values.len() == self.quantiles.len(){
@@ -212,6 +219,9 @@ impl Transform for Quantile{
         */
     }
     fn inverse(&self, value:f64)->Option<f64>{
+        if self.values.is_empty(){
+            return None;
+        }
         Some(Quantile::general_transform(value, &self.quantiles, &self.values))
         /*
         if self.values.len()>1 && self.values.len() == self.quantiles.len(){
@@ -300,4 +310,18 @@ mod test{
         assert_eq!(transform.transform(10.0), Some(1.0));
         assert!((transform.inverse(transform.transform(3.0).unwrap()).unwrap()-3.0).abs()<1e-5);
     }
-}    
+
+    #[test]
+    fn test_missing_values(){
+        let data = [f64::NAN, 1.0, f64::NAN, 3.0];
+        let mut transform = Normalize::new();
+        transform.calibrate(&data);
+        assert_eq!(transform.transform(2.0), Some(0.5));
+        let mut transform = Quantile::new();
+        transform.calibrate(&data);
+        assert_eq!(transform.transform(3.0), Some(1.0));
+        assert_eq!(transform.transform(f64::NAN), None);
+        transform.calibrate(&[f64::NAN]);
+        assert_eq!(transform.transform(1.0), None);
+    }
+}